@@ -0,0 +1,302 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use crate::custom::{self, ArchiveHeader, CustomWriter};
+
+// Name of the table-of-contents entry inside an archive
+pub const TOC_FILE: &str = "toc.dat";
+
+// Files written next to the entries by --parallel-copy
+pub const RESTORE_SCRIPT: &str = "restore.sh";
+pub const SESSION_FILE: &str = "session.sql";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Plain,
+    Directory,
+    Tar,
+    Custom,
+}
+
+impl std::str::FromStr for ArchiveFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(ArchiveFormat::Plain),
+            "directory" => Ok(ArchiveFormat::Directory),
+            "tar" => Ok(ArchiveFormat::Tar),
+            "custom" => Ok(ArchiveFormat::Custom),
+            _ => Err(format!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+// One restorable piece of the dump, e.g. the schema or a single table's data
+pub struct TocEntry {
+    pub id: usize,
+    pub kind: String,
+    pub name: String,
+    pub file: String,
+}
+
+enum Sink {
+    Directory(PathBuf),
+    Tar(tar::Builder<Box<dyn Write>>),
+    Custom(Box<CustomWriter>),
+}
+
+// Writes dump sections as separate files, either into a directory or a
+// (optionally gzip'd) tar archive, and records them in a TOC. Entry files
+// are numbered so concatenating them in name order reproduces the plain dump.
+pub struct ArchiveWriter {
+    sink: Sink,
+    header: Vec<String>,
+    entries: Vec<TocEntry>,
+}
+
+impl ArchiveWriter {
+    pub fn create(path: &Path, format: ArchiveFormat, compress: bool, header: Vec<String>) -> Result<Self, Box<dyn Error>> {
+        let sink = match format {
+            ArchiveFormat::Directory => {
+                fs::create_dir_all(path)?;
+                Sink::Directory(path.to_path_buf())
+            }
+            ArchiveFormat::Tar => {
+                let file = File::create(path)?;
+                let writer: Box<dyn Write> = if compress {
+                    Box::new(GzEncoder::new(file, Compression::default()))
+                } else {
+                    Box::new(file)
+                };
+                Sink::Tar(tar::Builder::new(writer))
+            }
+            ArchiveFormat::Plain => {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Plain output is not an archive format",
+                )));
+            }
+            ArchiveFormat::Custom => {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Custom archives are created with ArchiveWriter::custom",
+                )));
+            }
+        };
+
+        Ok(ArchiveWriter { sink, header, entries: Vec::new() })
+    }
+
+    // A pg_dump custom-format archive, for pg_restore. Its header replaces
+    // the comment lines of the other formats' TOC.
+    pub fn custom(path: &Path, compress: bool, header: ArchiveHeader) -> Result<Self, Box<dyn Error>> {
+        let sink = Sink::Custom(Box::new(CustomWriter::create(path, compress, header)?));
+        Ok(ArchiveWriter { sink, header: Vec::new(), entries: Vec::new() })
+    }
+
+    // Adds a section as the next entry. `file_name` replaces the default
+    // `<kind>-<name>.sql`; the number prefix is kept either way.
+    pub fn add_entry(&mut self, kind: &str, name: &str, file_name: Option<&str>, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        // pg_restore numbers its own entries, down to single statements
+        if let Sink::Custom(writer) = &mut self.sink {
+            return writer.add_section(kind, name, &String::from_utf8_lossy(data));
+        }
+
+        let id = self.entries.len() + 1;
+        let kind_name = kind.to_lowercase();
+        let file = match file_name {
+            Some(file_name) => format!("{:04}-{}", id, sanitize_file_name(file_name)),
+            None if name == kind_name => format!("{:04}-{}.sql", id, kind_name),
+            None => format!("{:04}-{}-{}.sql", id, kind_name, sanitize_file_name(name)),
+        };
+        self.write_file(&file, data, 0o644)?;
+        self.entries.push(TocEntry {
+            id,
+            kind: kind.to_string(),
+            name: name.to_string(),
+            file,
+        });
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[TocEntry] {
+        &self.entries
+    }
+
+    // Adds a file that is not a TOC entry, such as the restore script
+    pub fn add_file(&mut self, file: &str, data: &[u8], executable: bool) -> Result<(), Box<dyn Error>> {
+        self.write_file(file, data, if executable { 0o755 } else { 0o644 })
+    }
+
+    pub fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        if let Sink::Custom(writer) = self.sink {
+            return writer.finish();
+        }
+
+        let mut toc = String::new();
+        for line in &self.header {
+            toc.push_str(&format!("# {}\n", line));
+        }
+        for entry in &self.entries {
+            toc.push_str(&format!("{}\t{}\t{}\t{}\n", entry.id, entry.kind, entry.name, entry.file));
+        }
+        self.write_file(TOC_FILE, toc.as_bytes(), 0o644)?;
+
+        if let Sink::Tar(builder) = self.sink {
+            let mut writer = builder.into_inner()?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_file(&mut self, file: &str, data: &[u8], mode: u32) -> Result<(), Box<dyn Error>> {
+        match &mut self.sink {
+            Sink::Directory(dir) => {
+                fs::write(dir.join(file), data)?;
+                #[cfg(unix)]
+                if mode & 0o111 != 0 {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(dir.join(file), fs::Permissions::from_mode(mode))?;
+                }
+            }
+            Sink::Tar(builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(mode);
+                header.set_mtime(SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0));
+                header.set_cksum();
+                builder.append_data(&mut header, file, data)?;
+            }
+            Sink::Custom(_) => {
+                return Err(format!("Custom archives cannot hold {}", file).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+// A POSIX shell script that restores the entries with psql, passing its own
+// arguments on as connection options. Data entries run a level at a time
+// through `xargs -P`, which exits non-zero if any load failed.
+pub fn restore_script(dbname: &str, entries: &[TocEntry], levels: &[Vec<String>], jobs: usize, session_file: Option<&str>, stop_on_error: bool) -> String {
+    let stop = if stop_on_error { " -v ON_ERROR_STOP=1" } else { "" };
+    let mut script = String::new();
+    script.push_str("#!/bin/sh\n");
+    script.push_str(&format!("# Restores the dump of {} with psql, loading up to {} tables at a time.\n", dbname, jobs));
+    script.push_str("# Tables within a level don't reference each other; levels load in order.\n");
+    script.push_str("# Pass psql connection options, e.g.: ./restore.sh -h localhost -U postgres -d mydb\n");
+    script.push_str("set -e\n");
+    script.push_str("cd \"$(dirname \"$0\")\"\n\n");
+
+    let is_data = |entry: &&TocEntry| entry.kind == "DATA";
+    let first_data = entries.iter().position(|entry| entry.kind == "DATA").unwrap_or(entries.len());
+    let serial = |entry: &TocEntry| match entry.kind.as_str() {
+        // Roles often exist on the target already, so their errors never stop the restore
+        "ROLES" => format!("psql -X -q \"$@\" -f {}\n", entry.file),
+        // Each psql run is its own session, so the meta-command has no effect here
+        "ERROR-STOP" => String::new(),
+        _ => format!("psql -X -q{} \"$@\" -f {}\n", stop, entry.file),
+    };
+
+    for entry in &entries[..first_data] {
+        script.push_str(&serial(entry));
+    }
+
+    let session = session_file.map(|file| format!(" -f {}", file)).unwrap_or_default();
+    for (i, level) in levels.iter().enumerate() {
+        let files: Vec<&str> = level.iter()
+            .filter_map(|table| entries.iter().filter(is_data).find(|entry| &entry.name == table))
+            .map(|entry| entry.file.as_str())
+            .collect();
+        if files.is_empty() {
+            continue;
+        }
+        script.push_str(&format!("\n# Level {}\n", i + 1));
+        script.push_str(&format!(
+            "printf '%s\\n' {} | xargs -P {} -I {{}} psql -X -q{} \"$@\"{} -f {{}}\n",
+            files.join(" "), jobs, stop, session
+        ));
+    }
+    script.push('\n');
+
+    for entry in entries[first_data..].iter().filter(|entry| entry.kind != "DATA") {
+        script.push_str(&serial(entry));
+    }
+    script
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' { c } else { '_' })
+        .collect()
+}
+
+// Reads the TOC of a directory, tar (gzip'd or not) or custom archive without extracting it
+pub fn read_toc(path: &Path) -> Result<(Vec<String>, Vec<TocEntry>), Box<dyn Error>> {
+    if custom::is_custom_archive(path) {
+        return custom::read_toc(path);
+    }
+
+    let contents = if path.is_dir() {
+        fs::read_to_string(path.join(TOC_FILE))?
+    } else {
+        let mut file = File::open(path)?;
+        let mut magic = [0u8; 2];
+        let is_gzip = file.read_exact(&mut magic).is_ok() && magic == [0x1f, 0x8b];
+        let file = File::open(path)?;
+        let reader: Box<dyn Read> = if is_gzip {
+            Box::new(GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+
+        let mut archive = tar::Archive::new(reader);
+        let mut toc = None;
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.path()?.as_os_str() == TOC_FILE {
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents)?;
+                toc = Some(contents);
+                break;
+            }
+        }
+
+        toc.ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} has no {} entry", path.display(), TOC_FILE),
+        ))?
+    };
+
+    let mut header = Vec::new();
+    let mut entries = Vec::new();
+
+    for line in BufReader::new(contents.as_bytes()).lines() {
+        let line = line?;
+        if let Some(comment) = line.strip_prefix("# ") {
+            header.push(comment.to_string());
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 4 {
+            continue;
+        }
+        entries.push(TocEntry {
+            id: fields[0].parse()?,
+            kind: fields[1].to_string(),
+            name: fields[2].to_string(),
+            file: fields[3].to_string(),
+        });
+    }
+
+    Ok((header, entries))
+}
@@ -0,0 +1,43 @@
+use std::error::Error;
+use std::fs;
+
+// A long option name and the values to pass for it
+pub type ConfigOption = (String, Vec<String>);
+
+// Reads a TOML config file into (option, values) pairs keyed by the long
+// option name. Underscores are accepted in place of hyphens; `true` turns a
+// flag on, `false` leaves it off, and arrays supply repeatable options.
+pub fn load(path: &str) -> Result<Vec<ConfigOption>, Box<dyn Error>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read config file {}: {}", path, e))?;
+    let table: toml::Table = toml::from_str(&text)
+        .map_err(|e| format!("Could not parse config file {}: {}", path, e))?;
+
+    let mut options = Vec::new();
+    for (key, value) in table {
+        let name = key.replace('_', "-");
+        if name == "config" {
+            return Err(format!("Config file {} cannot include another config file", path).into());
+        }
+        let values = match value {
+            toml::Value::Boolean(false) => continue,
+            toml::Value::Boolean(true) => Vec::new(),
+            toml::Value::Array(items) => items
+                .into_iter()
+                .map(|item| scalar(&name, item))
+                .collect::<Result<_, _>>()?,
+            other => vec![scalar(&name, other)?],
+        };
+        options.push((name, values));
+    }
+    Ok(options)
+}
+
+fn scalar(name: &str, value: toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(n) => Ok(n.to_string()),
+        _ => Err(format!("Unsupported value for {} in config file", name)),
+    }
+}
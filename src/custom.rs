@@ -0,0 +1,553 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+
+use crate::archive::TocEntry;
+use crate::verify::{is_copy_from_stdin, split_statements};
+
+// pg_dump's custom archive format, version 1.14 as written by PostgreSQL 12 to 15
+const MAGIC: &[u8] = b"PGDMP";
+const VERSION: [u8; 3] = [1, 14, 0];
+const INT_SIZE: u8 = 4;
+const OFFSET_SIZE: u8 = 8;
+const FORMAT_CUSTOM: u8 = 1;
+const BLOCK_DATA: u8 = 1;
+const OFFSET_SET: u8 = 2;
+const OFFSET_NO_DATA: u8 = 3;
+
+// Table data is written in chunks of at most this many bytes
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// The restore phase of an entry, as pg_restore --section names them
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RestoreSection {
+    PreData = 2,
+    Data = 3,
+    PostData = 4,
+}
+
+// What the archive header records about the dump
+pub struct ArchiveHeader {
+    pub dbname: String,
+    pub server_version: String,
+    // UTC time as YYYYMMDDTHHMMSSZ
+    pub created: String,
+}
+
+struct Entry {
+    id: i32,
+    desc: String,
+    tag: String,
+    section: RestoreSection,
+    defn: String,
+    drop: String,
+    copy: String,
+    namespace: Option<String>,
+    dependencies: Vec<i32>,
+    // Position of the entry's data block in the spool file
+    data: Option<u64>,
+}
+
+// Writes a custom-format archive that pg_restore can read. The TOC comes
+// before the data in the file but is only complete once every section has
+// been added, so data blocks are spooled to a file next to the archive and
+// copied in behind the TOC by finish().
+pub struct CustomWriter {
+    path: PathBuf,
+    spool_path: PathBuf,
+    spool: BufWriter<File>,
+    spool_len: u64,
+    compress: bool,
+    header: ArchiveHeader,
+    entries: Vec<Entry>,
+    // The latest entry without data, and the data entries added since: data
+    // entries only wait for the former, so pg_restore -j can load tables in
+    // parallel, while the next entry waits for all of them
+    last_step: Option<i32>,
+    data_since: Vec<i32>,
+    // DROP DATABASE precedes CREATE DATABASE and becomes its drop statement
+    database_drop: Option<String>,
+}
+
+impl CustomWriter {
+    pub fn create(path: &Path, compress: bool, header: ArchiveHeader) -> Result<Self, Box<dyn Error>> {
+        let mut spool_name = path.file_name().unwrap_or_default().to_os_string();
+        spool_name.push(".data");
+        let spool_path = path.with_file_name(spool_name);
+        let spool = BufWriter::new(File::create(&spool_path)?);
+
+        let mut writer = CustomWriter {
+            path: path.to_path_buf(),
+            spool_path,
+            spool,
+            spool_len: 0,
+            compress,
+            header,
+            entries: Vec::new(),
+            last_step: None,
+            data_since: Vec::new(),
+            database_drop: None,
+        };
+
+        // pg_restore applies these to every connection it opens
+        let pre_data = RestoreSection::PreData;
+        writer.push("ENCODING", "ENCODING", pre_data, "SET client_encoding = 'UTF8';\n", None);
+        writer.push("STDSTRINGS", "STDSTRINGS", pre_data, "SET standard_conforming_strings = 'on';\n", None);
+        writer.push("SEARCHPATH", "SEARCHPATH", pre_data, "SELECT pg_catalog.set_config('search_path', 'public, pg_catalog', false);\n", None);
+        Ok(writer)
+    }
+
+    // Adds a dump section. Table data becomes a single TABLE DATA entry;
+    // any other section is split into an entry per statement.
+    pub fn add_section(&mut self, kind: &str, name: &str, sql: &str) -> Result<(), Box<dyn Error>> {
+        if kind == "DATA" {
+            return self.add_table_data(name, sql);
+        }
+
+        let section = match kind {
+            "ROLES" | "DATABASE" | "SCHEMA" | "COMMENTS" => RestoreSection::PreData,
+            "LOAD-SETUP" | "TRUNCATE" | "LOAD-FINISH" => RestoreSection::Data,
+            _ => RestoreSection::PostData,
+        };
+
+        let dbname = self.header.dbname.clone();
+        for statement in split_statements(sql) {
+            let words: Vec<String> = statement.split_whitespace().take(6).map(str::to_uppercase).collect();
+            let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+            if kind == "DATABASE" {
+                match words.as_slice() {
+                    ["DROP", "DATABASE", ..] => self.database_drop = Some(statement),
+                    ["CREATE", "DATABASE", ..] => {
+                        let id = self.push("DATABASE", &dbname, section, &statement, None);
+                        if let Some(drop) = self.database_drop.take() {
+                            self.entries[id as usize - 1].drop = format!("{}\n", drop);
+                        }
+                    }
+                    _ => {
+                        self.push("DATABASE PROPERTIES", &dbname, section, &statement, None);
+                    }
+                }
+                continue;
+            }
+
+            let (desc, tag, namespace) = describe(&words, &statement, name);
+            self.push(&desc, &tag, section, &statement, namespace);
+        }
+        Ok(())
+    }
+
+    // A table's rows, loaded with COPY when the section has a COPY block and
+    // by running its INSERT statements otherwise
+    fn add_table_data(&mut self, table_name: &str, sql: &str) -> Result<(), Box<dyn Error>> {
+        let mut copy = String::new();
+        let mut data = String::new();
+        let mut lines = sql.split('\n');
+        while let Some(line) = lines.next() {
+            if is_copy_from_stdin(line) {
+                copy = format!("{}\n", line);
+                for row in lines.by_ref() {
+                    if row == "\\." {
+                        break;
+                    }
+                    data.push_str(row);
+                    data.push('\n');
+                }
+                // pg_dump ends the rows with the terminator in this archive version
+                data.push_str("\\.\n\n");
+                break;
+            }
+        }
+        if copy.is_empty() {
+            for statement in split_statements(sql) {
+                data.push_str(&statement);
+                data.push('\n');
+            }
+        }
+
+        let position = self.spool_len;
+        let id = self.entries.len() as i32 + 1;
+        self.write_data_block(id, data.as_bytes())?;
+
+        // The table's own entry comes first, which is how pg_restore pairs
+        // the data with the table it fills
+        let mut dependencies: Vec<i32> = self.entries.iter()
+            .filter(|entry| entry.desc == "TABLE" && entry.tag == table_name)
+            .map(|entry| entry.id)
+            .collect();
+        dependencies.extend(self.last_step.filter(|step| !dependencies.contains(step)));
+
+        self.entries.push(Entry {
+            id,
+            desc: "TABLE DATA".to_string(),
+            tag: table_name.to_string(),
+            section: RestoreSection::Data,
+            defn: String::new(),
+            drop: String::new(),
+            copy,
+            namespace: Some("public".to_string()),
+            dependencies,
+            data: Some(position),
+        });
+        self.data_since.push(id);
+        Ok(())
+    }
+
+    fn push(&mut self, desc: &str, tag: &str, section: RestoreSection, defn: &str, namespace: Option<String>) -> i32 {
+        let id = self.entries.len() as i32 + 1;
+        let mut dependencies: Vec<i32> = self.last_step.into_iter().collect();
+        dependencies.append(&mut self.data_since);
+
+        let defn = if defn.ends_with('\n') { defn.to_string() } else { format!("{}\n", defn) };
+        self.entries.push(Entry {
+            id,
+            desc: desc.to_string(),
+            tag: tag.to_string(),
+            section,
+            defn,
+            drop: String::new(),
+            copy: String::new(),
+            namespace,
+            dependencies,
+            data: None,
+        });
+        self.last_step = Some(id);
+        id
+    }
+
+    fn write_data_block(&mut self, id: i32, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut block = vec![BLOCK_DATA];
+        write_int(&mut block, id);
+
+        let payload = if self.compress {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?
+        } else {
+            data.to_vec()
+        };
+        for chunk in payload.chunks(CHUNK_SIZE) {
+            write_int(&mut block, chunk.len() as i32);
+            block.extend_from_slice(chunk);
+        }
+        write_int(&mut block, 0);
+
+        self.spool.write_all(&block)?;
+        self.spool_len += block.len() as u64;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        self.spool.flush()?;
+        drop(self.spool);
+
+        let mut head = Vec::new();
+        head.extend_from_slice(MAGIC);
+        head.extend_from_slice(&VERSION);
+        head.extend_from_slice(&[INT_SIZE, OFFSET_SIZE, FORMAT_CUSTOM]);
+        // -1 is zlib's default level; 0 means the data blocks are stored as is
+        write_int(&mut head, if self.compress { -1 } else { 0 });
+        for field in created_fields(&self.header.created) {
+            write_int(&mut head, field);
+        }
+        write_str(&mut head, Some(&self.header.dbname));
+        write_str(&mut head, Some(&self.header.server_version));
+        write_str(&mut head, Some(concat!("database-dump ", env!("CARGO_PKG_VERSION"))));
+
+        // Offsets take a fixed number of bytes, so the TOC's length doesn't
+        // depend on them and a first pass with placeholders measures it
+        let toc_len = toc_bytes(&self.entries, 0).len() as u64;
+        let data_start = head.len() as u64 + toc_len;
+
+        let mut file = BufWriter::new(File::create(&self.path)?);
+        file.write_all(&head)?;
+        file.write_all(&toc_bytes(&self.entries, data_start))?;
+        io::copy(&mut BufReader::new(File::open(&self.spool_path)?), &mut file)?;
+        file.flush()?;
+        fs::remove_file(&self.spool_path)?;
+        Ok(())
+    }
+}
+
+// The desc, tag and schema of a statement's entry. Tables, sequences and
+// indexes are tagged with their name so pg_restore -t and -I can pick them.
+fn describe(words: &[&str], statement: &str, section_name: &str) -> (String, String, Option<String>) {
+    let object_name = |position: usize| -> String {
+        let name = statement.split_whitespace().nth(position).unwrap_or_default();
+        name.split('(').next().unwrap_or_default().to_string()
+    };
+    match words {
+        ["GRANT", ..] | ["REVOKE", ..] => ("ACL".to_string(), section_name.to_string(), None),
+        ["COMMENT", "ON", ..] => ("COMMENT".to_string(), section_name.to_string(), None),
+        ["SECURITY", "LABEL", ..] => ("SECURITY LABEL".to_string(), section_name.to_string(), None),
+        ["CREATE", "TABLE", "IF", "NOT", "EXISTS", ..] => ("TABLE".to_string(), object_name(5), Some("public".to_string())),
+        ["CREATE", "TABLE", ..] => ("TABLE".to_string(), object_name(2), Some("public".to_string())),
+        ["CREATE", "UNLOGGED", "TABLE", ..] => ("TABLE".to_string(), object_name(3), Some("public".to_string())),
+        ["CREATE", "SEQUENCE", ..] => ("SEQUENCE".to_string(), object_name(2), Some("public".to_string())),
+        ["CREATE", "INDEX", ..] => ("INDEX".to_string(), object_name(2), Some("public".to_string())),
+        ["CREATE", "UNIQUE", "INDEX", ..] => ("INDEX".to_string(), object_name(3), Some("public".to_string())),
+        // Anything else is listed by its command, e.g. ALTER TABLE or SET
+        ["CREATE" | "ALTER" | "DROP", "OR", "REPLACE", object, ..] => (format!("{} {}", words[0], object), section_name.to_string(), None),
+        ["CREATE" | "ALTER" | "DROP", object, ..] => (format!("{} {}", words[0], object), section_name.to_string(), None),
+        [command, ..] => (command.to_string(), section_name.to_string(), None),
+        [] => (String::new(), section_name.to_string(), None),
+    }
+}
+
+fn toc_bytes(entries: &[Entry], data_start: u64) -> Vec<u8> {
+    let mut toc = Vec::new();
+    write_int(&mut toc, entries.len() as i32);
+    for entry in entries {
+        write_int(&mut toc, entry.id);
+        write_int(&mut toc, entry.data.is_some() as i32);
+        // Catalog table OID and object OID, which only pg_dump knows
+        write_str(&mut toc, Some("0"));
+        write_str(&mut toc, Some("0"));
+        write_str(&mut toc, Some(&entry.tag));
+        write_str(&mut toc, Some(&entry.desc));
+        write_int(&mut toc, entry.section as i32);
+        write_str(&mut toc, Some(&entry.defn));
+        write_str(&mut toc, Some(&entry.drop));
+        write_str(&mut toc, Some(&entry.copy));
+        write_str(&mut toc, entry.namespace.as_deref());
+        // Tablespace and table access method: the server defaults
+        write_str(&mut toc, None);
+        write_str(&mut toc, None);
+        // Owner, and the long-gone WITH OIDS flag
+        write_str(&mut toc, Some(""));
+        write_str(&mut toc, Some("false"));
+        for dependency in &entry.dependencies {
+            write_str(&mut toc, Some(&dependency.to_string()));
+        }
+        write_str(&mut toc, None);
+        match entry.data {
+            Some(position) => {
+                toc.push(OFFSET_SET);
+                toc.extend_from_slice(&(data_start + position).to_le_bytes());
+            }
+            None => {
+                toc.push(OFFSET_NO_DATA);
+                toc.extend_from_slice(&0u64.to_le_bytes());
+            }
+        }
+    }
+    toc
+}
+
+// Seconds, minutes, hours, day, month from 0, years since 1900 and the DST
+// flag, as in a C struct tm
+fn created_fields(created: &str) -> [i32; 7] {
+    let field = |range: std::ops::Range<usize>| created.get(range).and_then(|s| s.parse().ok()).unwrap_or(0);
+    [field(13..15), field(11..13), field(9..11), field(6..8), field(4..6) - 1, field(0..4) - 1900, 0]
+}
+
+// Integers are a sign byte followed by the magnitude in little-endian order
+fn write_int(out: &mut Vec<u8>, value: i32) {
+    out.push((value < 0) as u8);
+    out.extend_from_slice(&value.unsigned_abs().to_le_bytes());
+}
+
+// Strings are their length followed by the bytes; -1 stands for NULL
+fn write_str(out: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            write_int(out, value.len() as i32);
+            out.extend_from_slice(value.as_bytes());
+        }
+        None => write_int(out, -1),
+    }
+}
+
+pub fn is_custom_archive(path: &Path) -> bool {
+    let mut magic = [0u8; 5];
+    File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && magic == MAGIC
+}
+
+// Reads the header and TOC of a custom-format archive written by this tool
+// or by pg_dump 12 to 15
+pub fn read_toc(path: &Path) -> Result<(Vec<String>, Vec<TocEntry>), Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut head = [0u8; 11];
+    reader.read_exact(&mut head)?;
+    if head[5..8] != VERSION || head[8] != INT_SIZE || head[9] != OFFSET_SIZE || head[10] != FORMAT_CUSTOM {
+        return Err(format!(
+            "{} is a version {}.{} archive, which only pg_restore -l can list",
+            path.display(), head[5], head[6]
+        ).into());
+    }
+
+    let compression = read_int(&mut reader)?;
+    let created: Vec<i32> = (0..7).map(|_| read_int(&mut reader)).collect::<Result<_, _>>()?;
+    let dbname = read_str(&mut reader)?.unwrap_or_default();
+    let server_version = read_str(&mut reader)?.unwrap_or_default();
+    let dumped_by = read_str(&mut reader)?.unwrap_or_default();
+
+    let header = vec![
+        format!("Database Dump for: {}", dbname),
+        format!(
+            "Created: {:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            created[5] + 1900, created[4] + 1, created[3], created[2], created[1], created[0]
+        ),
+        format!("Server version: {}", server_version),
+        format!("Dumped by: {}", dumped_by),
+        format!("Compression: {}", if compression == 0 { "none" } else { "zlib" }),
+    ];
+
+    let count = read_int(&mut reader)?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let id = read_int(&mut reader)?;
+        read_int(&mut reader)?;
+        read_str(&mut reader)?;
+        read_str(&mut reader)?;
+        let tag = read_str(&mut reader)?.unwrap_or_default();
+        let desc = read_str(&mut reader)?.unwrap_or_default();
+        read_int(&mut reader)?;
+        // Definition, drop and copy statements, schema, tablespace, access
+        // method, owner and WITH OIDS
+        for _ in 0..8 {
+            read_str(&mut reader)?;
+        }
+        while read_str(&mut reader)?.is_some() {}
+        let mut offset = [0u8; 1 + OFFSET_SIZE as usize];
+        reader.read_exact(&mut offset)?;
+
+        entries.push(TocEntry {
+            id: id as usize,
+            kind: desc,
+            name: tag,
+            file: String::new(),
+        });
+    }
+    Ok((header, entries))
+}
+
+fn read_int(reader: &mut impl Read) -> io::Result<i32> {
+    let mut bytes = [0u8; 1 + INT_SIZE as usize];
+    reader.read_exact(&mut bytes)?;
+    // The magnitude of i32::MIN only fits once negated
+    let magnitude = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as i32;
+    Ok(if bytes[0] != 0 { magnitude.wrapping_neg() } else { magnitude })
+}
+
+fn read_str(reader: &mut impl Read) -> io::Result<Option<String>> {
+    let len = read_int(reader)?;
+    if len < 0 {
+        return Ok(None);
+    }
+    let mut bytes = vec![0u8; len as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+
+    #[test]
+    fn ints_are_a_sign_byte_and_the_magnitude() {
+        let mut out = Vec::new();
+        write_int(&mut out, 258);
+        write_int(&mut out, -1);
+        assert_eq!(out, [0, 2, 1, 0, 0, 1, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn ints_round_trip() {
+        let values = [0, 1, 255, 65536, -1, -70000, i32::MAX, i32::MIN];
+        let mut out = Vec::new();
+        for value in values {
+            write_int(&mut out, value);
+        }
+        let mut reader = out.as_slice();
+        for value in values {
+            assert_eq!(read_int(&mut reader).unwrap(), value);
+        }
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn strings_round_trip() {
+        let mut out = Vec::new();
+        write_str(&mut out, Some("café"));
+        write_str(&mut out, Some(""));
+        write_str(&mut out, None);
+        let mut reader = out.as_slice();
+        assert_eq!(read_str(&mut reader).unwrap().as_deref(), Some("café"));
+        assert_eq!(read_str(&mut reader).unwrap().as_deref(), Some(""));
+        assert_eq!(read_str(&mut reader).unwrap(), None);
+    }
+
+    // Writes a small archive and reads back its header, its TOC and the rows
+    // in the data block of the table
+    fn round_trip(compress: bool) -> (Vec<String>, Vec<(usize, String, String)>, String) {
+        let dir = std::env::temp_dir().join(format!("database-dump-custom-{}-{}", std::process::id(), compress));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dump.custom");
+        let mut writer = CustomWriter::create(&path, compress, ArchiveHeader {
+            dbname: "shop".to_string(),
+            server_version: "15.18".to_string(),
+            created: "20261016T123456Z".to_string(),
+        }).unwrap();
+        writer.add_section("SCHEMA", "schema", "CREATE TABLE orders (id integer);\nCREATE INDEX orders_id ON orders (id);\n").unwrap();
+        writer.add_section("DATA", "orders", "COPY orders (id) FROM stdin;\n1\n2\n\\.\n").unwrap();
+
+        // Block type, entry id, one chunk's length and bytes, and a closing 0
+        writer.spool.flush().unwrap();
+        let block = fs::read(&writer.spool_path).unwrap();
+        assert_eq!(block[..6], [BLOCK_DATA, 0, 6, 0, 0, 0]);
+        assert_eq!(block[block.len() - 5..], [0, 0, 0, 0, 0]);
+        let chunk = &block[11..block.len() - 5];
+        assert_eq!(read_int(&mut &block[6..11]).unwrap() as usize, chunk.len());
+        let mut rows = String::new();
+        if compress {
+            ZlibDecoder::new(chunk).read_to_string(&mut rows).unwrap();
+        } else {
+            rows = String::from_utf8(chunk.to_vec()).unwrap();
+        }
+
+        writer.finish().unwrap();
+        assert!(!dir.join("dump.custom.data").exists());
+        assert!(is_custom_archive(&path));
+        let (header, entries) = read_toc(&path).unwrap();
+        let archive = fs::read(&path).unwrap();
+        assert!(archive.ends_with(&block));
+        fs::remove_dir_all(&dir).unwrap();
+        (header, entries.into_iter().map(|entry| (entry.id, entry.kind, entry.name)).collect(), rows)
+    }
+
+    fn expected_entries() -> Vec<(usize, String, String)> {
+        [(1, "ENCODING", "ENCODING"), (2, "STDSTRINGS", "STDSTRINGS"), (3, "SEARCHPATH", "SEARCHPATH"),
+         (4, "TABLE", "orders"), (5, "INDEX", "orders_id"), (6, "TABLE DATA", "orders")]
+            .into_iter()
+            .map(|(id, kind, name)| (id, kind.to_string(), name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn archives_round_trip() {
+        let (header, entries, rows) = round_trip(false);
+        assert_eq!(header, [
+            "Database Dump for: shop",
+            "Created: 2026-10-16 12:34:56",
+            "Server version: 15.18",
+            &format!("Dumped by: database-dump {}", env!("CARGO_PKG_VERSION")),
+            "Compression: none",
+        ]);
+        assert_eq!(entries, expected_entries());
+        assert_eq!(rows, "1\n2\n\\.\n\n");
+    }
+
+    #[test]
+    fn compressed_archives_round_trip() {
+        let (header, entries, rows) = round_trip(true);
+        assert_eq!(header[4], "Compression: zlib");
+        assert_eq!(entries, expected_entries());
+        assert_eq!(rows, "1\n2\n\\.\n\n");
+    }
+}
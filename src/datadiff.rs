@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use tokio_postgres::Client;
+
+use crate::{DumpTarget, StringQuoting, list_foreign_key_edges, list_tables, load_levels, quote_string};
+
+// The columns --data-diff compares and the primary key that pairs up rows.
+// Generated columns follow from the others and are left out.
+struct TableShape {
+    columns: Vec<String>,
+    key: Vec<String>,
+    // Set when a column is GENERATED ALWAYS AS IDENTITY, which INSERT only
+    // accepts values for with OVERRIDING SYSTEM VALUE
+    identity_always: bool,
+}
+
+async fn table_shape(client: &Client, table_name: &str) -> Result<TableShape, Box<dyn Error>> {
+    let columns = client.query(
+        "SELECT a.attname::text, a.attidentity::text
+         FROM pg_catalog.pg_attribute a
+         JOIN pg_catalog.pg_class c ON c.oid = a.attrelid
+         WHERE c.relname = $1
+         AND c.relnamespace = 'public'::regnamespace
+         AND a.attnum > 0
+         AND NOT a.attisdropped
+         AND a.attgenerated = ''
+         ORDER BY a.attnum",
+        &[&table_name],
+    ).await?;
+    let key = client.query(
+        "SELECT a.attname::text
+         FROM pg_catalog.pg_index i
+         JOIN pg_catalog.pg_class c ON c.oid = i.indrelid
+         JOIN pg_catalog.pg_attribute a ON a.attrelid = c.oid AND a.attnum = ANY(i.indkey)
+         WHERE i.indisprimary
+         AND c.relname = $1
+         AND c.relnamespace = 'public'::regnamespace
+         ORDER BY pg_catalog.array_position(i.indkey::int2[], a.attnum)",
+        &[&table_name],
+    ).await?;
+
+    Ok(TableShape {
+        identity_always: columns.iter().any(|row| row.get::<_, &str>(1) == "a"),
+        columns: columns.iter().map(|row| row.get(0)).collect(),
+        key: key.iter().map(|row| row.get(0)).collect(),
+    })
+}
+
+type Values = Vec<Option<String>>;
+
+// Every row of the table as text, which is how the two sides are compared
+async fn fetch_rows(client: &Client, table_name: &str, columns: &[String]) -> Result<Vec<Values>, Box<dyn Error>> {
+    let select: Vec<String> = columns.iter().map(|column| format!("{}::text", column)).collect();
+    let rows = client.query(&format!("SELECT {} FROM public.{}", select.join(", "), table_name), &[]).await?;
+    Ok(rows.iter()
+        .map(|row| (0..columns.len()).map(|i| row.get(i)).collect())
+        .collect())
+}
+
+// The statements for one table, kept apart so all deletes can run after the
+// inserts and updates, in the opposite table order
+#[derive(Default)]
+struct TableChanges {
+    deletes: Vec<String>,
+    upserts: Vec<String>,
+}
+
+// Pairs the rows of a table by primary key and builds the statements that
+// turn the target's rows into the source's. `shape` holds the columns both
+// sides have, and the target's identity kind.
+fn diff_rows(table: &str, shape: &TableShape, source_rows: Vec<Values>, target_rows: Vec<Values>, quoting: StringQuoting) -> TableChanges {
+    let columns = &shape.columns;
+    let literal = |value: &Option<String>| match value {
+        Some(value) => quote_string(value, quoting),
+        None => "NULL".to_string(),
+    };
+    let key_positions: Vec<usize> = shape.key.iter()
+        .filter_map(|key| columns.iter().position(|column| column == key))
+        .collect();
+    let key_of = |values: &Values| -> Values { key_positions.iter().map(|&i| values[i].clone()).collect() };
+    let key_condition = |values: &Values| -> String {
+        key_positions.iter()
+            .map(|&i| format!("{} = {}", columns[i], literal(&values[i])))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    };
+
+    let mut target_rows: HashMap<Values, Values> = target_rows.into_iter()
+        .map(|values| (key_of(&values), values))
+        .collect();
+
+    let mut table_changes = TableChanges::default();
+    let overriding = if shape.identity_always { " OVERRIDING SYSTEM VALUE" } else { "" };
+    for values in source_rows {
+        match target_rows.remove(&key_of(&values)) {
+            None => table_changes.upserts.push(format!(
+                "INSERT INTO {} ({}){} VALUES ({});",
+                table, columns.join(", "), overriding, values.iter().map(literal).collect::<Vec<_>>().join(", ")
+            )),
+            Some(current) if current != values => {
+                let assignments: Vec<String> = (0..columns.len())
+                    .filter(|&i| current[i] != values[i])
+                    .map(|i| format!("{} = {}", columns[i], literal(&values[i])))
+                    .collect();
+                table_changes.upserts.push(format!(
+                    "UPDATE {} SET {} WHERE {};",
+                    table, assignments.join(", "), key_condition(&values)
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+
+    // Sorted so the same data always gives the same migration
+    let mut removed: Vec<Values> = target_rows.into_values().collect();
+    removed.sort();
+    for values in removed {
+        table_changes.deletes.push(format!("DELETE FROM {} WHERE {};", table, key_condition(&values)));
+    }
+    table_changes
+}
+
+// Writes the INSERT, UPDATE and DELETE statements that bring the rows of the
+// tables in `target` in line with `source`, pairing rows by primary key.
+// Inserts and updates come first, parents before children, so rows that move
+// to another parent have done so before deletes run, children first. Returns
+// the number of statements and of tables compared.
+pub async fn write_data_migration(source: &Client, target: &Client, only_tables: &[String], quoting: StringQuoting, out: &mut DumpTarget<'_>) -> Result<(usize, usize), Box<dyn Error>> {
+    let target_tables = list_tables(target).await?;
+    let mut tables: Vec<String> = list_tables(source).await?.into_iter()
+        .filter(|table| only_tables.is_empty() || only_tables.contains(table))
+        .collect();
+    for table in tables.iter().filter(|table| !target_tables.contains(table)) {
+        out.write_line(&format!("-- Table {} is skipped: it only exists in the source", table))?;
+    }
+    tables.retain(|table| target_tables.contains(table));
+
+    let edges = list_foreign_key_edges(source).await?;
+    let order = load_levels(&tables, &edges).concat();
+
+    let mut changes: Vec<(String, TableChanges)> = Vec::new();
+    for table in &order {
+        let source_shape = table_shape(source, table).await?;
+        let target_shape = table_shape(target, table).await?;
+        if source_shape.key.is_empty() || source_shape.key != target_shape.key {
+            eprintln!("Warning: table {} has no primary key, or a different one in each database; its data is not compared", table);
+            out.write_line(&format!("-- Table {} is skipped: it needs the same primary key on both sides", table))?;
+            continue;
+        }
+
+        // Columns on one side only are left as they are
+        let shape = TableShape {
+            columns: source_shape.columns.into_iter()
+                .filter(|column| target_shape.columns.contains(column))
+                .collect(),
+            key: source_shape.key,
+            identity_always: target_shape.identity_always,
+        };
+        let source_rows = fetch_rows(source, table, &shape.columns).await?;
+        let target_rows = fetch_rows(target, table, &shape.columns).await?;
+        let table_changes = diff_rows(table, &shape, source_rows, target_rows, quoting);
+        changes.push((table.clone(), table_changes));
+    }
+
+    let total = changes.iter().map(|(_, table)| table.deletes.len() + table.upserts.len()).sum();
+    out.write_line("BEGIN;")?;
+    out.write_line("")?;
+    for (table, table_changes) in changes.iter().filter(|(_, table)| !table.upserts.is_empty()) {
+        out.write_line(&format!("-- Rows new or changed in the source: {}", table))?;
+        for statement in &table_changes.upserts {
+            out.write_line(statement)?;
+        }
+        out.write_line("")?;
+    }
+    for (table, table_changes) in changes.iter().rev().filter(|(_, table)| !table.deletes.is_empty()) {
+        out.write_line(&format!("-- Rows only in the target: {}", table))?;
+        for statement in &table_changes.deletes {
+            out.write_line(statement)?;
+        }
+        out.write_line("")?;
+    }
+    out.write_line("COMMIT;")?;
+    Ok((total, changes.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(columns: &[&str], key: &[&str]) -> TableShape {
+        TableShape {
+            columns: columns.iter().map(|column| column.to_string()).collect(),
+            key: key.iter().map(|column| column.to_string()).collect(),
+            identity_always: false,
+        }
+    }
+
+    fn row(values: &[Option<&str>]) -> Values {
+        values.iter().map(|value| value.map(str::to_string)).collect()
+    }
+
+    #[test]
+    fn new_rows_are_inserted() {
+        let changes = diff_rows("items", &shape(&["id", "name"], &["id"]), vec![row(&[Some("1"), Some("pen")]), row(&[Some("2"), None])], vec![row(&[Some("1"), Some("pen")])], StringQuoting::Standard);
+        assert_eq!(changes.upserts, ["INSERT INTO items (id, name) VALUES ('2', NULL);"]);
+        assert!(changes.deletes.is_empty());
+
+        let mut always = shape(&["id", "name"], &["id"]);
+        always.identity_always = true;
+        let changes = diff_rows("items", &always, vec![row(&[Some("3"), Some("it's")])], vec![], StringQuoting::Standard);
+        assert_eq!(changes.upserts, ["INSERT INTO items (id, name) OVERRIDING SYSTEM VALUE VALUES ('3', 'it''s');"]);
+    }
+
+    #[test]
+    fn changed_rows_update_only_the_changed_columns() {
+        let changes = diff_rows(
+            "items",
+            &shape(&["id", "name", "note", "price"], &["id"]),
+            vec![row(&[Some("1"), Some("pen"), None, Some("2.50")]), row(&[Some("2"), Some("ink"), Some("blue"), None])],
+            vec![row(&[Some("2"), Some("ink"), None, Some("1.00")]), row(&[Some("1"), Some("pen"), Some("old"), Some("2.50")])],
+            StringQuoting::Standard,
+        );
+        assert_eq!(changes.upserts, [
+            "UPDATE items SET note = NULL WHERE id = '1';",
+            "UPDATE items SET note = 'blue', price = NULL WHERE id = '2';",
+        ]);
+        assert!(changes.deletes.is_empty());
+    }
+
+    #[test]
+    fn rows_only_in_the_target_are_deleted_in_a_stable_order() {
+        let changes = diff_rows(
+            "items",
+            &shape(&["id", "name"], &["id"]),
+            vec![row(&[Some("2"), Some("ink")])],
+            vec![row(&[Some("3"), None]), row(&[Some("2"), Some("ink")]), row(&[Some("1"), Some("pen")])],
+            StringQuoting::Standard,
+        );
+        assert!(changes.upserts.is_empty());
+        assert_eq!(changes.deletes, ["DELETE FROM items WHERE id = '1';", "DELETE FROM items WHERE id = '3';"]);
+    }
+
+    #[test]
+    fn composite_keys_pair_rows_on_every_key_column() {
+        // The key is (shelf, slot), listed in key order rather than column order
+        let shape = shape(&["slot", "item", "shelf"], &["shelf", "slot"]);
+        let changes = diff_rows(
+            "stock",
+            &shape,
+            vec![row(&[Some("1"), Some("pen"), Some("A")]), row(&[Some("1"), None, Some("B")]), row(&[Some("2"), Some("ink"), Some("A")])],
+            vec![row(&[Some("1"), Some("pen"), Some("A")]), row(&[Some("1"), Some("cap"), Some("B")]), row(&[Some("2"), Some("ink"), Some("B")])],
+            StringQuoting::Standard,
+        );
+        assert_eq!(changes.upserts, [
+            "UPDATE stock SET item = NULL WHERE shelf = 'B' AND slot = '1';",
+            "INSERT INTO stock (slot, item, shelf) VALUES ('2', 'ink', 'A');",
+        ]);
+        assert_eq!(changes.deletes, ["DELETE FROM stock WHERE shelf = 'B' AND slot = '2';"]);
+    }
+}
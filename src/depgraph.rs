@@ -0,0 +1,133 @@
+use std::error::Error;
+use std::fs;
+
+use crate::{cyclic_edges, json_string, load_levels};
+
+// How --emit-depgraph writes the graph
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    Json,
+    Dot,
+}
+
+impl std::str::FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(GraphFormat::Json),
+            "dot" => Ok(GraphFormat::Dot),
+            _ => Err(format!("Unknown dependency graph format: {}", s)),
+        }
+    }
+}
+
+// A table of the graph, with the load level its rows are written at. Tables
+// the dumped ones reference but that aren't in the dump have no level.
+struct Node<'a> {
+    name: &'a str,
+    level: Option<usize>,
+}
+
+// Writes the tables of the dump and the foreign keys between them, as the
+// dump orders them: edges go from the referencing table to the referenced
+// one, and those on a cycle, whose constraints are added after the data,
+// are marked as such
+pub fn write(path: &str, format: GraphFormat, tables: &[String], edges: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+    // Cycles and levels are worked out on all foreign keys, as for the dump
+    let cyclic = cyclic_edges(edges);
+    let levels = load_levels(tables, edges);
+
+    let mut edges: Vec<(String, String)> = edges.iter()
+        .filter(|(child, _)| tables.contains(child))
+        .cloned()
+        .collect();
+    edges.sort();
+    let mut nodes: Vec<Node> = tables.iter()
+        .map(|table| Node {
+            name: table,
+            level: levels.iter().position(|level| level.contains(table)),
+        })
+        .collect();
+    for (_, parent) in &edges {
+        if !nodes.iter().any(|node| node.name == parent) {
+            nodes.push(Node { name: parent, level: None });
+        }
+    }
+
+    let text = match format {
+        GraphFormat::Json => {
+            let nodes: Vec<String> = nodes.iter()
+                .map(|node| format!(
+                    "    {{\"name\": {}, \"kind\": \"table\", \"dumped\": {}, \"level\": {}}}",
+                    json_string(node.name),
+                    node.level.is_some(),
+                    node.level.map_or("null".to_string(), |level| level.to_string()),
+                ))
+                .collect();
+            let edges: Vec<String> = edges.iter()
+                .map(|edge| format!(
+                    "    {{\"from\": {}, \"to\": {}, \"kind\": \"foreign key\", \"cyclic\": {}}}",
+                    json_string(&edge.0),
+                    json_string(&edge.1),
+                    cyclic.contains(edge),
+                ))
+                .collect();
+            format!("{{\n  \"nodes\": [\n{}\n  ],\n  \"edges\": [\n{}\n  ]\n}}\n", nodes.join(",\n"), edges.join(",\n"))
+        }
+        GraphFormat::Dot => {
+            let mut lines = vec!["digraph dependencies {".to_string(), "  rankdir = RL;".to_string()];
+            for node in &nodes {
+                match node.level {
+                    Some(level) => lines.push(format!("  {} [label = {}];", dot_id(node.name), dot_label(node.name, &format!("level {}", level)))),
+                    None => lines.push(format!("  {} [style = dashed, label = {}];", dot_id(node.name), dot_label(node.name, "not dumped"))),
+                }
+            }
+            for edge in &edges {
+                if cyclic.contains(edge) {
+                    lines.push(format!("  {} -> {} [color = red, style = dashed, label = \"cycle\"];", dot_id(&edge.0), dot_id(&edge.1)));
+                } else {
+                    lines.push(format!("  {} -> {};", dot_id(&edge.0), dot_id(&edge.1)));
+                }
+            }
+            lines.push("}".to_string());
+            lines.join("\n") + "\n"
+        }
+    };
+
+    fs::write(path, text).map_err(|e| format!("Could not write dependency graph {}: {}", path, e).into())
+}
+
+// A quoted DOT identifier
+fn dot_id(name: &str) -> String {
+    format!("\"{}\"", dot_escape(name))
+}
+
+// A quoted DOT label of two lines, the second under the first
+fn dot_label(first: &str, second: &str) -> String {
+    format!("\"{}\\n{}\"", dot_escape(first), dot_escape(second))
+}
+
+// Backslashes go first, so those escaping quotes aren't doubled
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot_ids_escape_backslashes_and_quotes() {
+        assert_eq!(dot_id("orders"), "\"orders\"");
+        assert_eq!(dot_id("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(dot_id("a\\b"), "\"a\\\\b\"");
+        assert_eq!(dot_id("ends\\"), "\"ends\\\\\"");
+    }
+
+    #[test]
+    fn dot_labels_keep_their_line_break() {
+        assert_eq!(dot_label("orders", "level 1"), "\"orders\\nlevel 1\"");
+        assert_eq!(dot_label("a\\nb", "not dumped"), "\"a\\\\nb\\nnot dumped\"");
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use tokio_postgres::Client;
+
+use crate::{ColumnInfo, ConstraintInfo, DumpTarget, IndexInfo, fetch_columns, fetch_constraints, fetch_indexes, list_tables};
+
+// Structure of one table as seen by the schema diff
+pub struct TableSnapshot {
+    columns: Vec<ColumnInfo>,
+    indexes: Vec<IndexInfo>,
+    constraints: Vec<ConstraintInfo>,
+}
+
+pub type SchemaSnapshot = BTreeMap<String, TableSnapshot>;
+
+// Differences --diff-ignore leaves out of the migration. They only apply to
+// tables on both sides; a new table is still created complete.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffIgnore {
+    Defaults,
+    NotNull,
+    Indexes,
+    CheckConstraints,
+    ForeignKeys,
+}
+
+impl std::str::FromStr for DiffIgnore {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "defaults" => Ok(DiffIgnore::Defaults),
+            "not-null" => Ok(DiffIgnore::NotNull),
+            "indexes" => Ok(DiffIgnore::Indexes),
+            "check-constraints" => Ok(DiffIgnore::CheckConstraints),
+            "foreign-keys" => Ok(DiffIgnore::ForeignKeys),
+            _ => Err(format!("Unknown diff category: {}", s)),
+        }
+    }
+}
+
+// Whether a constraint of this kind falls under an ignored category
+fn ignores_constraint(ignore: &[DiffIgnore], kind: &str) -> bool {
+    match kind {
+        "c" => ignore.contains(&DiffIgnore::CheckConstraints),
+        "f" => ignore.contains(&DiffIgnore::ForeignKeys),
+        _ => false,
+    }
+}
+
+// Collects tables, columns, indexes and constraints using the same catalog
+// queries as the dump itself
+pub async fn snapshot(client: &Client) -> Result<SchemaSnapshot, Box<dyn Error>> {
+    let mut tables = BTreeMap::new();
+
+    for table_name in list_tables(client).await? {
+        let table = TableSnapshot {
+            columns: fetch_columns(client, &table_name).await?,
+            indexes: fetch_indexes(client, &table_name).await?,
+            constraints: fetch_constraints(client, &table_name, &["p", "u", "x", "c", "f"]).await?,
+        };
+        tables.insert(table_name, table);
+    }
+
+    Ok(tables)
+}
+
+// Constraints are added in this order so foreign keys find their referenced keys
+fn constraint_rank(kind: &str) -> u8 {
+    match kind {
+        "p" => 0,
+        "u" => 1,
+        "x" => 2,
+        "c" => 3,
+        _ => 4,
+    }
+}
+
+fn find<'a, T>(items: &'a [T], name: &str, name_of: impl Fn(&T) -> &str) -> Option<&'a T> {
+    items.iter().find(|item| name_of(item) == name)
+}
+
+// Writes the statements that migrate `target` so it matches `source`: drops
+// first (constraints, indexes, tables), then new tables and column changes,
+// then constraints and indexes. Differences in the `ignore` categories are
+// skipped on tables that exist on both sides.
+pub fn write_migration(source: &SchemaSnapshot, target: &SchemaSnapshot, ignore: &[DiffIgnore], out: &mut DumpTarget<'_>) -> Result<usize, Box<dyn Error>> {
+    let mut statements: Vec<String> = Vec::new();
+
+    // Constraints that disappeared or changed on tables that remain, foreign keys first
+    let mut dropped_constraints: Vec<(&str, &ConstraintInfo)> = Vec::new();
+    for (table_name, old) in target {
+        let Some(new) = source.get(table_name) else { continue };
+        for constraint in &old.constraints {
+            if ignores_constraint(ignore, &constraint.kind) {
+                continue;
+            }
+            let unchanged = find(&new.constraints, &constraint.name, |c| &c.name)
+                .is_some_and(|c| c.definition == constraint.definition);
+            if !unchanged {
+                dropped_constraints.push((table_name, constraint));
+            }
+        }
+    }
+    dropped_constraints.sort_by_key(|(_, c)| std::cmp::Reverse(constraint_rank(&c.kind)));
+    for (table_name, constraint) in dropped_constraints {
+        statements.push(format!("ALTER TABLE {} DROP CONSTRAINT {};", table_name, constraint.name));
+    }
+
+    // Indexes that disappeared or changed
+    for (table_name, old) in target {
+        let Some(new) = source.get(table_name) else { continue };
+        if ignore.contains(&DiffIgnore::Indexes) {
+            continue;
+        }
+        for index in &old.indexes {
+            let unchanged = find(&new.indexes, &index.name, |i| &i.name)
+                .is_some_and(|i| i.definition == index.definition);
+            if !unchanged {
+                statements.push(format!("DROP INDEX {};", index.name));
+            }
+        }
+    }
+
+    // Tables that no longer exist
+    for table_name in target.keys() {
+        if !source.contains_key(table_name) {
+            statements.push(format!("DROP TABLE {};", table_name));
+        }
+    }
+
+    // New tables; their keys and indexes are added with everything else below
+    for (table_name, new) in source {
+        if target.contains_key(table_name) {
+            continue;
+        }
+        let columns: Vec<String> = new.columns.iter().map(|c| c.definition(0)).collect();
+        statements.push(format!("CREATE TABLE {} ({});", table_name, columns.join(", ")));
+    }
+
+    // Column changes on tables present in both
+    for (table_name, new) in source {
+        let Some(old) = target.get(table_name) else { continue };
+
+        for column in &old.columns {
+            if find(&new.columns, &column.name, |c| &c.name).is_none() {
+                statements.push(format!("ALTER TABLE {} DROP COLUMN {};", table_name, column.name));
+            }
+        }
+
+        for column in &new.columns {
+            let Some(existing) = find(&old.columns, &column.name, |c| &c.name) else {
+                statements.push(format!("ALTER TABLE {} ADD COLUMN {};", table_name, column.definition(0)));
+                continue;
+            };
+
+            // An old default may not cast to a new type, so it goes first
+            let default_changed = existing.default != column.default && !ignore.contains(&DiffIgnore::Defaults);
+            if default_changed && existing.default.is_some() {
+                statements.push(format!("ALTER TABLE {} ALTER COLUMN {} DROP DEFAULT;", table_name, column.name));
+            }
+
+            if existing.data_type != column.data_type {
+                statements.push(format!(
+                    "ALTER TABLE {} ALTER COLUMN {} TYPE {} USING {}::{};",
+                    table_name, column.name, column.data_type, column.name, column.data_type
+                ));
+            }
+
+            if default_changed && let Some(default) = &column.default {
+                statements.push(format!("ALTER TABLE {} ALTER COLUMN {} SET DEFAULT {};", table_name, column.name, default));
+            }
+
+            if existing.not_null != column.not_null && !ignore.contains(&DiffIgnore::NotNull) {
+                let action = if column.not_null { "SET" } else { "DROP" };
+                statements.push(format!("ALTER TABLE {} ALTER COLUMN {} {} NOT NULL;", table_name, column.name, action));
+            }
+        }
+    }
+
+    // Constraints that are new or changed, keys before the foreign keys that need them
+    let mut added_constraints: Vec<(&str, &ConstraintInfo)> = Vec::new();
+    for (table_name, new) in source {
+        let old = target.get(table_name);
+        for constraint in &new.constraints {
+            if old.is_some() && ignores_constraint(ignore, &constraint.kind) {
+                continue;
+            }
+            let unchanged = old
+                .and_then(|old| find(&old.constraints, &constraint.name, |c| &c.name))
+                .is_some_and(|c| c.definition == constraint.definition);
+            if !unchanged {
+                added_constraints.push((table_name, constraint));
+            }
+        }
+    }
+    added_constraints.sort_by_key(|(_, c)| constraint_rank(&c.kind));
+    for (table_name, constraint) in added_constraints {
+        statements.push(format!("ALTER TABLE {} ADD CONSTRAINT {} {};", table_name, constraint.name, constraint.definition));
+    }
+
+    // Indexes that are new or changed
+    for (table_name, new) in source {
+        let old = target.get(table_name);
+        if old.is_some() && ignore.contains(&DiffIgnore::Indexes) {
+            continue;
+        }
+        for index in &new.indexes {
+            let unchanged = old
+                .and_then(|old| find(&old.indexes, &index.name, |i| &i.name))
+                .is_some_and(|i| i.definition == index.definition);
+            if !unchanged {
+                statements.push(format!("{};", index.definition));
+            }
+        }
+    }
+
+    if statements.is_empty() {
+        out.write_line("-- No schema differences found")?;
+    }
+    for statement in &statements {
+        out.write_line(statement)?;
+    }
+
+    Ok(statements.len())
+}
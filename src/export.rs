@@ -0,0 +1,277 @@
+use std::error::Error;
+
+use tokio_postgres::Client;
+
+use crate::{ColumnFilter, ColumnOrder, CursorRows, DumpTarget, Opt, RowFilter};
+
+// The catalog reads and row writing shared by the mysql and sqlite dialects.
+// Everything here is in PostgreSQL's terms; each module maps it to its engine.
+
+// A column, with a domain read as its base type
+pub struct SourceColumn {
+    pub name: String,
+    // As format_type writes it, such as character varying(20) or integer[]
+    pub pg_type: String,
+    pub not_null: bool,
+    pub default: Option<String>,
+    // Identity columns and serials, whose values come from a sequence
+    pub sequence: bool,
+    // GENERATED ALWAYS AS (default) STORED
+    pub generated: bool,
+    pub enum_labels: Vec<String>,
+}
+
+pub async fn fetch_columns(client: &Client, table_name: &str, column_filter: &ColumnFilter, opt: &Opt) -> Result<Vec<SourceColumn>, Box<dyn Error>> {
+    // Domains keep their NOT NULL
+    let rows = client.query(
+        "SELECT a.attname::text,
+                pg_catalog.format_type(
+                    CASE WHEN t.typtype = 'd' THEN t.typbasetype ELSE a.atttypid END,
+                    CASE WHEN t.typtype = 'd' THEN t.typtypmod ELSE a.atttypmod END
+                ),
+                a.attnotnull OR (t.typtype = 'd' AND t.typnotnull),
+                pg_catalog.pg_get_expr(d.adbin, d.adrelid),
+                a.attidentity::text,
+                a.attgenerated::text,
+                ARRAY(SELECT e.enumlabel::text FROM pg_catalog.pg_enum e
+                      WHERE e.enumtypid = a.atttypid ORDER BY e.enumsortorder)
+         FROM pg_catalog.pg_attribute a
+         JOIN pg_catalog.pg_class c ON c.oid = a.attrelid
+         JOIN pg_catalog.pg_type t ON t.oid = a.atttypid
+         LEFT JOIN pg_catalog.pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+         WHERE c.relname = $1
+         AND c.relnamespace = 'public'::regnamespace
+         AND a.attnum > 0
+         AND NOT a.attisdropped
+         ORDER BY a.attnum",
+        &[&table_name],
+    ).await?;
+
+    let mut columns: Vec<SourceColumn> = rows.iter()
+        .filter(|row| column_filter.keeps(table_name, row.get(0)))
+        .map(|row| {
+            let default: Option<String> = row.get(3);
+            SourceColumn {
+                name: row.get(0),
+                pg_type: row.get(1),
+                not_null: row.get(2),
+                sequence: !row.get::<_, &str>(4).is_empty()
+                    || default.as_deref().is_some_and(|expr| expr.starts_with("nextval(")),
+                generated: row.get::<_, &str>(5) == "s",
+                default,
+                enum_labels: row.get(6),
+            }
+        })
+        .collect();
+    if opt.sort_columns == ColumnOrder::Alphabetical {
+        columns.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    Ok(columns)
+}
+
+pub struct SourceConstraint {
+    pub name: String,
+    // p, u, f, c or x, as in pg_constraint
+    pub kind: String,
+    pub columns: Vec<String>,
+    pub references: Option<String>,
+    pub referenced_columns: Vec<String>,
+    // a (no action), r, c, n or d, as in pg_constraint
+    pub on_update: String,
+    pub on_delete: String,
+    pub definition: String,
+}
+
+// The constraints of a table, primary key first, leaving out those in `left_out`
+pub async fn fetch_constraints(client: &Client, table_name: &str, left_out: &[(String, String)]) -> Result<Vec<SourceConstraint>, Box<dyn Error>> {
+    let rows = client.query(
+        "SELECT con.conname::text,
+                con.contype::text,
+                ARRAY(SELECT a.attname::text FROM unnest(con.conkey) WITH ORDINALITY k(attnum, n)
+                      JOIN pg_catalog.pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum
+                      ORDER BY k.n),
+                r.relname::text,
+                ARRAY(SELECT a.attname::text FROM unnest(con.confkey) WITH ORDINALITY k(attnum, n)
+                      JOIN pg_catalog.pg_attribute a ON a.attrelid = con.confrelid AND a.attnum = k.attnum
+                      ORDER BY k.n),
+                con.confupdtype::text,
+                con.confdeltype::text,
+                pg_catalog.pg_get_constraintdef(con.oid)
+         FROM pg_catalog.pg_constraint con
+         JOIN pg_catalog.pg_class c ON c.oid = con.conrelid
+         LEFT JOIN pg_catalog.pg_class r ON r.oid = con.confrelid
+         WHERE c.relname = $1
+         AND c.relnamespace = 'public'::regnamespace
+         ORDER BY CASE con.contype WHEN 'p' THEN 0 WHEN 'u' THEN 1 ELSE 2 END, con.conname",
+        &[&table_name],
+    ).await?;
+
+    Ok(rows.iter()
+        .map(|row| SourceConstraint {
+            name: row.get(0),
+            kind: row.get(1),
+            columns: row.get(2),
+            references: row.get(3),
+            referenced_columns: row.get(4),
+            on_update: row.get(5),
+            on_delete: row.get(6),
+            definition: row.get(7),
+        })
+        .filter(|constraint| !left_out.contains(&(table_name.to_string(), constraint.name.clone())))
+        .collect())
+}
+
+pub struct SourceIndex {
+    pub name: String,
+    pub unique: bool,
+    // A B-tree index on plain columns without a predicate, which every
+    // engine has; others are only described by `definition`
+    pub simple: bool,
+    pub columns: Vec<String>,
+    pub definition: String,
+}
+
+// The indexes of a table that don't back a constraint, leaving out those in
+// `left_out` and those --filter excludes
+pub async fn fetch_indexes(client: &Client, table_name: &str, left_out: &[(String, String)], opt: &Opt) -> Result<Vec<SourceIndex>, Box<dyn Error>> {
+    let rows = client.query(
+        "SELECT ic.relname::text,
+                i.indisunique,
+                am.amname = 'btree' AND i.indpred IS NULL AND NOT 0 = ANY (i.indkey::int2[]),
+                ARRAY(SELECT a.attname::text FROM unnest((i.indkey::int2[])[0:i.indnkeyatts - 1]) WITH ORDINALITY k(attnum, n)
+                      JOIN pg_catalog.pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = k.attnum
+                      ORDER BY k.n),
+                pg_catalog.pg_get_indexdef(i.indexrelid)
+         FROM pg_catalog.pg_index i
+         JOIN pg_catalog.pg_class ic ON ic.oid = i.indexrelid
+         JOIN pg_catalog.pg_class c ON c.oid = i.indrelid
+         JOIN pg_catalog.pg_am am ON am.oid = ic.relam
+         WHERE c.relname = $1
+         AND c.relnamespace = 'public'::regnamespace
+         AND NOT EXISTS (
+             SELECT 1 FROM pg_catalog.pg_constraint con
+             WHERE con.conindid = i.indexrelid AND con.conrelid = i.indrelid
+         )
+         ORDER BY ic.relname",
+        &[&table_name],
+    ).await?;
+
+    Ok(rows.iter()
+        .map(|row| SourceIndex {
+            name: row.get(0),
+            unique: row.get(1),
+            simple: row.get(2),
+            columns: row.get(3),
+            definition: row.get(4),
+        })
+        .filter(|index| !left_out.contains(&(table_name.to_string(), index.name.clone())) && opt.object_filter.keeps_index(&index.name))
+        .collect())
+}
+
+// Lists what a table lost on the way to the other engine, after its CREATE TABLE
+pub fn write_not_converted(target: &mut DumpTarget<'_>, items: &[String]) -> Result<(), Box<dyn Error>> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    target.write_line("-- Not converted:")?;
+    for item in items {
+        target.write_line(&format!("--   {}", item))?;
+    }
+    Ok(())
+}
+
+// How a column's values are read for another engine
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueKind {
+    Number,
+    Bool,
+    Money,
+    Bytes,
+    Bits,
+    Array,
+    // Read as the UTC time, since the other engines keep no zone
+    Timestamptz,
+    Text,
+}
+
+impl ValueKind {
+    // The expression that reads the column as text: booleans as 1 or 0, bytea
+    // as hex digits and arrays as JSON
+    pub fn select(self, column: &str) -> String {
+        match self {
+            ValueKind::Bool => format!("{}::int::text", column),
+            ValueKind::Money => format!("{}::numeric::text", column),
+            ValueKind::Bytes => format!("pg_catalog.encode({}, 'hex')", column),
+            ValueKind::Array => format!("pg_catalog.array_to_json({})::text", column),
+            ValueKind::Timestamptz => format!("({} AT TIME ZONE 'UTC')::text", column),
+            _ => format!("{}::text", column),
+        }
+    }
+}
+
+// The value of a standard '...' string literal, with what follows it
+pub fn string_literal(expr: &str) -> Option<(String, &str)> {
+    let mut chars = expr.strip_prefix('\'')?.char_indices().peekable();
+    let mut value = String::new();
+    while let Some((i, c)) = chars.next() {
+        if c == '\'' {
+            if chars.peek().map(|&(_, next)| next) == Some('\'') {
+                chars.next();
+                value.push('\'');
+                continue;
+            }
+            return Some((value, &expr[i + 2..]));
+        }
+        value.push(c);
+    }
+    None
+}
+
+// Writes the rows of a table, reading `select_list` and turning the values of
+// each row into its statement with `statement`. Rows are written as they
+// arrive; --sample-rows, --where-all and --commit-every apply as they do for
+// PostgreSQL output.
+pub async fn write_rows(
+    client: &Client,
+    target: &mut DumpTarget<'_>,
+    table_name: &str,
+    select_list: &[String],
+    statement: impl Fn(&[Option<&str>]) -> String,
+    row_filter: &RowFilter,
+    opt: &Opt,
+) -> Result<(), Box<dyn Error>> {
+    let mut query = format!("SELECT {} FROM {}", select_list.join(", "), table_name);
+    if let Some(expr) = &row_filter.predicate {
+        query.push_str(&format!(" WHERE ({})", expr));
+    }
+    if let Some(limit) = row_filter.rows {
+        if row_filter.random {
+            query.push_str(" ORDER BY random()");
+        }
+        query.push_str(&format!(" LIMIT {}", limit));
+    }
+
+    // Read through a cursor, so a large table isn't held in memory
+    let mut rows = CursorRows::open(client, &query, opt).await?;
+    // Statements written since the last BEGIN of --commit-every
+    let mut batch = 0;
+    while let Some(row) = rows.next().await? {
+        let values: Vec<Option<&str>> = (0..select_list.len()).map(|i| row.get(i)).collect();
+        if opt.commit_every.is_some() && batch == 0 {
+            target.write_line("BEGIN;")?;
+        }
+        target.write_line(&statement(&values))?;
+        target.stats.rows += 1;
+        if let Some(every) = opt.commit_every {
+            batch += 1;
+            if batch == every {
+                target.write_line("COMMIT;")?;
+                batch = 0;
+            }
+        }
+    }
+    if batch > 0 {
+        target.write_line("COMMIT;")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,381 @@
+use std::error::Error;
+use std::fs;
+
+use tokio_postgres::Client;
+
+use crate::glob_matches;
+
+// The objects a --filter rule can pick. Schema rules are checked when the file
+// is read, since the public schema is the only one dumped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Object {
+    Table,
+    TableAndChildren,
+    TableData,
+    TableDataAndChildren,
+    ForeignData,
+    Index,
+    Trigger,
+}
+
+impl Object {
+    fn is_table(self) -> bool {
+        matches!(self, Object::Table | Object::TableAndChildren)
+    }
+
+    fn is_table_data(self) -> bool {
+        matches!(self, Object::TableData | Object::TableDataAndChildren)
+    }
+
+    fn with_children(self) -> bool {
+        matches!(self, Object::TableAndChildren | Object::TableDataAndChildren)
+    }
+}
+
+// A name pattern, where * and ? are wildcards, with the schema it is qualified by
+#[derive(Debug)]
+struct Pattern {
+    schema: Option<String>,
+    name: String,
+    // As written in the file, for messages
+    text: String,
+}
+
+impl Pattern {
+    fn matches(&self, name: &str) -> bool {
+        self.schema.as_deref().is_none_or(|schema| glob_matches(schema, "public"))
+            && glob_matches(&self.name, name)
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    include: bool,
+    object: Object,
+    pattern: Pattern,
+    line: usize,
+}
+
+// The rules of a --filter file, in pg_dump's format: one
+// `include|exclude <object> <pattern>` per line, with # starting a comment
+#[derive(Debug, Default)]
+pub struct ObjectFilter {
+    rules: Vec<Rule>,
+}
+
+impl ObjectFilter {
+    pub fn load(path: &str) -> Result<ObjectFilter, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read filter file {}: {}", path, e))?;
+        Self::parse(&text, path)
+    }
+
+    // The rules in `text`, read from `path`
+    fn parse(text: &str, path: &str) -> Result<ObjectFilter, Box<dyn Error>> {
+        let mut rules = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let at = |message: &str| format!("{} line {}: {}", path, i + 1, message);
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, rest) = next_word(line);
+            let include = match keyword {
+                "include" => true,
+                "exclude" => false,
+                _ => return Err(at("expected include or exclude").into()),
+            };
+            let (object, rest) = next_word(rest);
+            let object = match object {
+                "table" => Object::Table,
+                "table_and_children" => Object::TableAndChildren,
+                "table_data" => Object::TableData,
+                "table_data_and_children" => Object::TableDataAndChildren,
+                "foreign_data" => Object::ForeignData,
+                "index" => Object::Index,
+                "trigger" => Object::Trigger,
+                "schema" => {
+                    let pattern = parse_pattern(rest).map_err(|e| at(&e))?;
+                    if include && !glob_matches(&pattern.name, "public") {
+                        return Err(at(&format!("schema {} is not the public schema, the only one dumped", pattern.text)).into());
+                    }
+                    if !include && glob_matches(&pattern.name, "public") {
+                        return Err(at("excluding the public schema leaves nothing to dump").into());
+                    }
+                    continue;
+                }
+                "" => return Err(at("expected an object type after include or exclude").into()),
+                other => return Err(at(&format!("{} rules are not supported; the dump only holds the public schema's tables and what belongs to them", other)).into()),
+            };
+            // As with pg_dump, foreign data is only dumped on request
+            if object == Object::ForeignData && !include {
+                return Err(at("foreign data is only dumped for servers that include foreign_data names, so it can't be excluded").into());
+            }
+            let pattern = parse_pattern(rest).map_err(|e| at(&e))?;
+            rules.push(Rule { include, object, pattern, line: i + 1 });
+        }
+        Ok(ObjectFilter { rules })
+    }
+
+    // Whether rules of `object` let `name` through: some include rule, if
+    // there are any, names it and no exclude rule does
+    fn keeps(&self, object: impl Fn(Object) -> bool, name: &str) -> bool {
+        let mut includes = self.rules.iter().filter(|rule| rule.include && object(rule.object)).peekable();
+        let included = includes.peek().is_none() || includes.any(|rule| rule.pattern.matches(name));
+        included && !self.rules.iter().any(|rule| !rule.include && object(rule.object) && rule.pattern.matches(name))
+    }
+
+    // Whether tables are picked by include rules, like --table picks them
+    pub fn includes_tables(&self) -> bool {
+        self.rules.iter().any(|rule| rule.include && rule.object.is_table())
+    }
+
+    // The tables of `table_names` the include (or exclude) table rules name,
+    // with the inheritance children of those named by table_and_children. An
+    // include rule that names no table is an error, as it is for --table.
+    pub fn tables(&self, include: bool, table_names: &[String], inheritance: &[(String, String)]) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut tables = Vec::new();
+        for rule in self.rules.iter().filter(|rule| rule.include == include && rule.object.is_table()) {
+            let matched = matching_tables(rule, table_names, inheritance);
+            if include && matched.is_empty() {
+                return Err(format!("No table in the public schema matches the --filter pattern {} (line {})", rule.pattern.text, rule.line).into());
+            }
+            tables.extend(matched);
+        }
+        tables.sort();
+        tables.dedup();
+        Ok(tables)
+    }
+
+    // The tables of `table_names` whose rows the table_data rules leave out
+    pub fn tables_without_data(&self, table_names: &[String], inheritance: &[(String, String)]) -> Vec<String> {
+        let data_rules = |include: bool| -> Vec<String> {
+            self.rules.iter()
+                .filter(|rule| rule.include == include && rule.object.is_table_data())
+                .flat_map(|rule| matching_tables(rule, table_names, inheritance))
+                .collect()
+        };
+        let any_includes = self.rules.iter().any(|rule| rule.include && rule.object.is_table_data());
+        let (included, excluded) = (data_rules(true), data_rules(false));
+        table_names.iter()
+            .filter(|table| (any_includes && !included.contains(table)) || excluded.contains(table))
+            .cloned()
+            .collect()
+    }
+
+    // Whether no exclude table rule names this foreign table. Include rules
+    // pick regular tables only, as --table does.
+    pub fn keeps_foreign_table(&self, name: &str) -> bool {
+        !self.rules.iter().any(|rule| !rule.include && rule.object.is_table() && rule.pattern.matches(name))
+    }
+
+    // Whether the rows of foreign tables on this server are dumped
+    pub fn includes_foreign_data(&self, server: &str) -> bool {
+        self.rules.iter().any(|rule| rule.object == Object::ForeignData && rule.pattern.matches(server))
+    }
+
+    pub fn keeps_index(&self, name: &str) -> bool {
+        self.keeps(|object| object == Object::Index, name)
+    }
+
+    pub fn keeps_trigger(&self, name: &str) -> bool {
+        self.keeps(|object| object == Object::Trigger, name)
+    }
+}
+
+// The first word of `text` and what follows it
+fn next_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    text.split_once(char::is_whitespace).unwrap_or((text, ""))
+}
+
+// The tables a rule names, with their descendants for the *_and_children kinds
+fn matching_tables(rule: &Rule, table_names: &[String], inheritance: &[(String, String)]) -> Vec<String> {
+    let mut matched: Vec<String> = table_names.iter()
+        .filter(|table| rule.pattern.matches(table))
+        .cloned()
+        .collect();
+    if rule.object.with_children() {
+        let mut i = 0;
+        while i < matched.len() {
+            let table = matched[i].clone();
+            for (child, _) in inheritance.iter().filter(|(_, parent)| *parent == table) {
+                if table_names.contains(child) && !matched.contains(child) {
+                    matched.push(child.clone());
+                }
+            }
+            i += 1;
+        }
+    }
+    matched
+}
+
+// Reads a pattern as pg_dump does: unquoted letters are folded to lower case,
+// "..." keeps its case and a doubled "" inside it is a quote, and an unquoted
+// dot separates the schema from the name
+fn parse_pattern(text: &str) -> Result<Pattern, String> {
+    let text = text.trim();
+    let mut parts = vec![String::new()];
+    let mut quoted = false;
+    let mut end = text.len();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek().map(|&(_, next)| next) == Some('"') => {
+                chars.next();
+                parts.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            c if quoted => parts.last_mut().unwrap().push(c),
+            '.' => parts.push(String::new()),
+            '#' => {
+                end = i;
+                break;
+            }
+            c if c.is_whitespace() => {
+                let rest = text[i..].trim_start();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err(format!("unexpected text after pattern {}", &text[..i]));
+                }
+                end = i;
+                break;
+            }
+            c => parts.last_mut().unwrap().extend(c.to_lowercase()),
+        }
+    }
+    if quoted {
+        return Err(format!("unterminated quoted name in {}", text));
+    }
+    let text = text[..end].to_string();
+    match parts.as_slice() {
+        [name] if !name.is_empty() => Ok(Pattern { schema: None, name: name.clone(), text }),
+        [schema, name] if !schema.is_empty() && !name.is_empty() => Ok(Pattern { schema: Some(schema.clone()), name: name.clone(), text }),
+        [_] => Err("expected a pattern".to_string()),
+        _ => Err(format!("expected a pattern or schema.pattern, got {}", text)),
+    }
+}
+
+// Inheritance between tables of the public schema, as (child, parent) pairs
+pub async fn list_inheritance(client: &Client) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let rows = client.query(
+        "SELECT c.relname::text, p.relname::text
+         FROM pg_catalog.pg_inherits i
+         JOIN pg_catalog.pg_class c ON c.oid = i.inhrelid
+         JOIN pg_catalog.pg_class p ON p.oid = i.inhparent
+         WHERE c.relnamespace = 'public'::regnamespace
+         AND p.relnamespace = 'public'::regnamespace",
+        &[],
+    ).await?;
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(text: &str) -> ObjectFilter {
+        ObjectFilter::parse(text, "rules").unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        ObjectFilter::parse(text, "rules").unwrap_err().to_string()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn patterns_fold_unquoted_letters() {
+        let pattern = parse_pattern("Orders").unwrap();
+        assert_eq!((pattern.schema, pattern.name.as_str(), pattern.text.as_str()), (None, "orders", "Orders"));
+        assert_eq!(parse_pattern("\"Orders\"").unwrap().name, "Orders");
+        assert_eq!(parse_pattern("\"Big\"Orders").unwrap().name, "Bigorders");
+        assert_eq!(parse_pattern("\"say \"\"hi\"\"\"").unwrap().name, "say \"hi\"");
+    }
+
+    #[test]
+    fn patterns_split_the_schema_at_an_unquoted_dot() {
+        let pattern = parse_pattern("Public.orders").unwrap();
+        assert_eq!((pattern.schema.as_deref(), pattern.name.as_str()), (Some("public"), "orders"));
+        let pattern = parse_pattern("\"v1.orders\"").unwrap();
+        assert_eq!((pattern.schema, pattern.name.as_str()), (None, "v1.orders"));
+        assert_eq!(parse_pattern("a.b.c").unwrap_err(), "expected a pattern or schema.pattern, got a.b.c");
+        assert_eq!(parse_pattern(".orders").unwrap_err(), "expected a pattern or schema.pattern, got .orders");
+    }
+
+    #[test]
+    fn patterns_end_at_a_comment() {
+        let pattern = parse_pattern("orders  # the big one").unwrap();
+        assert_eq!((pattern.name.as_str(), pattern.text.as_str()), ("orders", "orders"));
+        assert_eq!(parse_pattern("orders#x").unwrap().name, "orders");
+        assert_eq!(parse_pattern("\"a#b\"").unwrap().name, "a#b");
+        assert_eq!(parse_pattern("orders items").unwrap_err(), "unexpected text after pattern orders");
+        assert_eq!(parse_pattern("\"orders").unwrap_err(), "unterminated quoted name in \"orders");
+        assert_eq!(parse_pattern("  ").unwrap_err(), "expected a pattern");
+    }
+
+    #[test]
+    fn patterns_glob() {
+        let pattern = parse_pattern("order*").unwrap();
+        assert!(pattern.matches("orders") && pattern.matches("order") && pattern.matches("order_items"));
+        assert!(!pattern.matches("reorders"));
+        let pattern = parse_pattern("?rders").unwrap();
+        assert!(pattern.matches("orders") && !pattern.matches("rders"));
+        assert!(parse_pattern("pub*.orders").unwrap().matches("orders"));
+        assert!(!parse_pattern("sales.orders").unwrap().matches("orders"));
+    }
+
+    #[test]
+    fn blank_and_comment_lines_are_skipped() {
+        let rules = filter("# indexes\n\n   # kept\ninclude index orders_* # all of them\n\texclude index orders_tmp\n");
+        assert_eq!(rules.rules.len(), 2);
+        assert_eq!(rules.rules[1].line, 5);
+    }
+
+    #[test]
+    fn excludes_win_over_includes() {
+        let rules = filter("include index orders_*\nexclude index orders_tmp\n");
+        assert!(rules.keeps_index("orders_id"));
+        assert!(!rules.keeps_index("orders_tmp"));
+        assert!(!rules.keeps_index("items_id"));
+        // Without include rules everything not excluded is kept
+        let rules = filter("exclude trigger audit_*\n");
+        assert!(rules.keeps_trigger("touch") && !rules.keeps_trigger("audit_orders"));
+        // Index rules don't speak for triggers
+        assert!(rules.keeps_index("audit_orders"));
+    }
+
+    #[test]
+    fn children_come_with_table_and_children() {
+        let tables = names(&["events", "events_2025", "events_2026", "events_2026_q1", "other"]);
+        let inheritance = [("events_2025", "events"), ("events_2026", "events"), ("events_2026_q1", "events_2026")]
+            .map(|(child, parent)| (child.to_string(), parent.to_string()));
+        let rules = filter("include table_and_children events\n");
+        assert_eq!(rules.tables(true, &tables, &inheritance).unwrap(), names(&["events", "events_2025", "events_2026", "events_2026_q1"]));
+        let rules = filter("include table events\n");
+        assert_eq!(rules.tables(true, &tables, &inheritance).unwrap(), names(&["events"]));
+        let rules = filter("exclude table_data_and_children events_2026\n");
+        assert_eq!(rules.tables_without_data(&tables, &inheritance), names(&["events_2026", "events_2026_q1"]));
+        let rules = filter("include table event\n");
+        assert_eq!(
+            rules.tables(true, &tables, &inheritance).unwrap_err().to_string(),
+            "No table in the public schema matches the --filter pattern event (line 1)"
+        );
+    }
+
+    #[test]
+    fn malformed_lines_are_errors() {
+        assert_eq!(error("include table orders\nkeep table items\n"), "rules line 2: expected include or exclude");
+        assert_eq!(error("include\n"), "rules line 1: expected an object type after include or exclude");
+        assert_eq!(error("include table\n"), "rules line 1: expected a pattern");
+        assert_eq!(
+            error("exclude function f\n"),
+            "rules line 1: function rules are not supported; the dump only holds the public schema's tables and what belongs to them"
+        );
+        assert_eq!(error("include schema sales\n"), "rules line 1: schema sales is not the public schema, the only one dumped");
+        assert_eq!(error("exclude schema pub*\n"), "rules line 1: excluding the public schema leaves nothing to dump");
+        assert!(error("exclude foreign_data srv\n").starts_with("rules line 1: foreign data is only dumped"));
+    }
+}
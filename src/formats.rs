@@ -0,0 +1,118 @@
+use std::error::Error;
+use std::io::Write;
+
+use tokio_postgres::types::Type;
+
+use crate::{Dialect, has_native_format, mysql, sqlite, typed_literal};
+
+// Column types as format_type writes them, with their type for the
+// PostgreSQL serializer; extension types have no fixed oid and get 0
+fn reference_types() -> Vec<(&'static str, u32)> {
+    vec![
+        ("smallint", Type::INT2.oid()),
+        ("integer", Type::INT4.oid()),
+        ("bigint", Type::INT8.oid()),
+        ("real", Type::FLOAT4.oid()),
+        ("double precision", Type::FLOAT8.oid()),
+        ("numeric", Type::NUMERIC.oid()),
+        ("numeric(10,2)", Type::NUMERIC.oid()),
+        ("money", Type::MONEY.oid()),
+        ("boolean", Type::BOOL.oid()),
+        ("text", Type::TEXT.oid()),
+        ("character varying(255)", Type::VARCHAR.oid()),
+        ("character(1)", Type::BPCHAR.oid()),
+        ("name", Type::NAME.oid()),
+        ("bytea", Type::BYTEA.oid()),
+        ("date", Type::DATE.oid()),
+        ("time without time zone", Type::TIME.oid()),
+        ("time with time zone", Type::TIMETZ.oid()),
+        ("timestamp without time zone", Type::TIMESTAMP.oid()),
+        ("timestamp with time zone", Type::TIMESTAMPTZ.oid()),
+        ("interval", Type::INTERVAL.oid()),
+        ("uuid", Type::UUID.oid()),
+        ("json", Type::JSON.oid()),
+        ("jsonb", Type::JSONB.oid()),
+        ("xml", Type::XML.oid()),
+        ("inet", Type::INET.oid()),
+        ("cidr", Type::CIDR.oid()),
+        ("macaddr", Type::MACADDR.oid()),
+        ("bit(8)", Type::BIT.oid()),
+        ("bit varying(8)", Type::VARBIT.oid()),
+        ("point", Type::POINT.oid()),
+        ("tsvector", Type::TS_VECTOR.oid()),
+        ("integer[]", Type::INT4_ARRAY.oid()),
+        ("text[]", Type::TEXT_ARRAY.oid()),
+        ("citext", 0),
+        ("hstore", 0),
+        ("geometry", 0),
+    ]
+}
+
+// How the PostgreSQL INSERT output writes values of a type
+fn insert_form(type_oid: u32) -> &'static str {
+    if has_native_format(type_oid) {
+        "native"
+    } else if typed_literal(type_oid, "'v'".to_string(), Dialect::Postgres) != "'v'" {
+        "text + cast"
+    } else {
+        "text"
+    }
+}
+
+// --help-formats: how each output writes the common column types, worked out
+// by the same functions that write the dump
+pub fn print_reference() -> Result<(), Box<dyn Error>> {
+    let mut rows = vec![["type".to_string(), "insert".to_string(), "copy".to_string(), "mysql".to_string(), "sqlite".to_string()]];
+    let mut notes: Vec<String> = Vec::new();
+    let mut note = |dialect: &str, items: Vec<String>| -> String {
+        let marks: Vec<String> = items.into_iter()
+            .map(|item| {
+                notes.push(format!("{}: {}", dialect, item));
+                format!("[{}]", notes.len())
+            })
+            .collect();
+        marks.concat()
+    };
+    // Enums have labels instead of a fixed type
+    let enum_labels = vec!["a".to_string(), "b".to_string()];
+    let mut types: Vec<(&str, u32, &[String])> = reference_types().into_iter()
+        .map(|(pg_type, type_oid)| (pg_type, type_oid, &[][..]))
+        .collect();
+    types.push(("enum", 0, &enum_labels));
+    for (pg_type, type_oid, labels) in types {
+        let (mysql_type, mysql_notes) = mysql::describe_type(pg_type, labels);
+        let (sqlite_type, sqlite_notes) = sqlite::describe_type(pg_type, labels);
+        let mysql_marks = note("mysql", mysql_notes);
+        let sqlite_marks = note("sqlite", sqlite_notes);
+        rows.push([
+            pg_type.to_string(),
+            insert_form(type_oid).to_string(),
+            "text".to_string(),
+            format!("{}{}", mysql_type, mysql_marks),
+            format!("{}{}", sqlite_type, sqlite_marks),
+        ]);
+    }
+
+    let widths: Vec<usize> = (0..5).map(|i| rows.iter().map(|row| row[i].chars().count()).max().unwrap_or(0)).collect();
+    let mut out = std::io::stdout().lock();
+    writeln!(out, "How each output writes column types. Every type round-trips through PostgreSQL output:")?;
+    writeln!(out, "  native       read in the driver's binary form; numbers and booleans are written bare")?;
+    writeln!(out, "  text         read as text and written quoted, for the type's input to read back")?;
+    writeln!(out, "  text + cast  the same, with a cast that keeps the value independent of the session's settings")?;
+    writeln!(out, "The insert column is --data-format insert (--dialect generic writes CAST(... AS ...) for ::),")?;
+    writeln!(out, "copy is --data-format copy. The mysql and sqlite columns are the type a column gets with")?;
+    writeln!(out, "--dialect mysql and sqlite; a number marks a loss, listed below. Types not listed are")?;
+    writeln!(out, "written as text in PostgreSQL output and stored as text, with a note, by MySQL and SQLite.")?;
+    writeln!(out)?;
+    for row in &rows {
+        let cells: Vec<String> = row.iter().zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        writeln!(out, "{}", cells.join("  ").trim_end())?;
+    }
+    writeln!(out)?;
+    for (i, note) in notes.iter().enumerate() {
+        writeln!(out, "[{}] {}", i + 1, note)?;
+    }
+    Ok(())
+}
@@ -1,70 +1,680 @@
-use std::process;
+mod archive;
+mod config;
+mod custom;
+mod datadiff;
+mod depgraph;
+mod diff;
+mod export;
+mod filter;
+mod formats;
+mod mysql;
+mod pgpass;
+mod service;
+mod sqlite;
+mod verify;
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::process::{self, Child, ChildStdin, Command, Stdio};
 use std::error::Error;
 use std::fs::{self, File};
-use std::io::Write;
-use std::path::Path;
-use std::time::Duration;
+use std::io::{BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
-use tokio_postgres::{Client, NoTls};
+use tokio::task::JoinHandle;
+use tokio_postgres::config::{SslMode, TargetSessionAttrs};
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::Type;
+use tokio_postgres::{Client, Config, NoTls, Row};
 use structopt::StructOpt;
+use archive::{ArchiveFormat, ArchiveWriter};
+use custom::ArchiveHeader;
+use depgraph::GraphFormat;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "pg-dump", about = "A utility to dump PostgreSQL database tables, users, and roles")]
 struct Opt {
-    #[structopt(short, long, help = "Database host")]
-    host: String,
+    #[structopt(long, help = "Read options from a TOML file; options given on the command line or through the environment take precedence")]
+    config: Option<String>,
+    
+    #[structopt(long, env = "PGSERVICE", help = "Connection service to take defaults from in the service file (PGSERVICEFILE, default: ~/.pg_service.conf)")]
+    service: Option<String>,
+    
+    #[structopt(short, long, required_unless_one = &["list", "help-formats", "config", "service"], help = "Database host (comma-separated list to try several hosts in order)")]
+    host: Option<String>,
     
     #[structopt(short = "P", long, help = "Database port", default_value = "5432")]
     port: u16,
     
-    #[structopt(short, long, help = "Database name")]
-    dbname: String,
+    #[structopt(short, long, required_unless_one = &["list", "help-formats", "config", "service"], help = "Database name")]
+    dbname: Option<String>,
+    
+    #[structopt(short, long, required_unless_one = &["list", "help-formats", "config", "service"], help = "Database user")]
+    user: Option<String>,
     
-    #[structopt(short, long, help = "Database user")]
-    user: String,
+    #[structopt(short = "p", long, env = "PGPASSWORD", hide_env_values = true, help = "Database password (default: looked up in the password file)")]
+    password: Option<String>,
     
-    #[structopt(short = "p", long, help = "Database password")]
-    password: String,
+    #[structopt(long, env = "PGPASSFILE", help = "Password file to search when no password is given (default: ~/.pgpass)")]
+    passfile: Option<String>,
     
-    #[structopt(short, long, help = "Output file (default: <dbname>-dump.sql in dump-output directory)")]
+    #[structopt(short, long, help = "Output file, or - for standard output (default: <dbname>-dump.sql in dump-output directory)")]
     output: Option<String>,
+    
+    #[structopt(long, conflicts_with = "output", help = "Output path with {dbname}, {schema} and {timestamp} placeholders; with --format directory, {table} in the file name names each table's data file")]
+    output_template: Option<String>,
+    
+    #[structopt(long, number_of_values = 1, help = "Also write the plain dump to this file, or - for standard output (repeatable)")]
+    tee: Vec<String>,
+    
+    #[structopt(long, help = "Pipe the plain dump through this shell command, e.g. 'pigz -p8' or 'zstd', and write what it prints to the output")]
+    compress_program: Option<String>,
+    
+    #[structopt(long, help = "Connection timeout in seconds", default_value = "30")]
+    connect_timeout: u64,
+    
+    #[structopt(long, help = "Seconds of inactivity before TCP keepalive probes are sent (default: the driver's 2 hours)")]
+    keepalives_idle: Option<u64>,
+    
+    #[structopt(long, help = "Seconds between TCP keepalive probes")]
+    keepalives_interval: Option<u64>,
+    
+    #[structopt(long, help = "Seconds transmitted data may stay unacknowledged before the connection is closed (Linux only)")]
+    tcp_user_timeout: Option<u64>,
+    
+    #[structopt(long, help = "Wait exactly 2, 4, 8, ... seconds between connection attempts instead of a random part of that")]
+    no_jitter: bool,
+    
+    #[structopt(long, help = "Before dumping, run the catalog queries the dump relies on, with retries, and stop at once if one keeps failing")]
+    warmup: bool,
+    
+    #[structopt(long, help = "Include CREATE DATABASE and \\connect statements to recreate the database")]
+    create: bool,
+    
+    #[structopt(long, requires = "create", help = "Emit DROP DATABASE IF EXISTS before CREATE DATABASE (requires --create)")]
+    drop_database: bool,
+    
+    #[structopt(long, help = "Output layout: pretty or compact", default_value = "pretty", possible_values = &["pretty", "compact"])]
+    format_style: FormatStyle,
+    
+    #[structopt(long, help = "Required session type when several hosts are given: any, read-write, read-only, primary or standby", default_value = "any", possible_values = &["any", "read-write", "read-only", "primary", "standby"])]
+    target_session_attrs: SessionAttrs,
+    
+    #[structopt(short = "F", long, help = "Output format: plain SQL file, a directory or tar archive with one entry per section, or a pg_dump custom-format archive for pg_restore", default_value = "plain", possible_values = &["plain", "directory", "tar", "custom"])]
+    format: ArchiveFormat,
+    
+    #[structopt(short = "Z", long, help = "Gzip the tar archive, or zlib-compress the table data of a custom archive")]
+    compress: bool,
+    
+    #[structopt(long, help = "Print the table of contents of a directory, tar or custom archive and exit")]
+    list: Option<String>,
+    
+    #[structopt(long, help = "Print how each data format and dialect writes the common column types, and what MySQL and SQLite lose, and exit")]
+    help_formats: bool,
+    
+    #[structopt(long, help = "Print each table's row count and exit without dumping anything")]
+    counts_only: bool,
+    
+    #[structopt(long, requires = "counts-only", help = "Print the planner's row estimates (pg_class.reltuples) instead of exact counts")]
+    estimate: bool,
+    
+    #[structopt(long, help = "Output of --counts-only: csv or json", default_value = "csv", possible_values = &["csv", "json"])]
+    counts_format: CountsFormat,
+    
+    #[structopt(long, help = "Overwrite the output file if it already exists")]
+    force: bool,
+    
+    #[structopt(long, help = "Only dump up to N rows per table (referential integrity is not guaranteed)")]
+    sample_rows: Option<u64>,
+    
+    #[structopt(long, requires = "sample-rows", help = "Pick sampled rows at random instead of taking the first N")]
+    sample_random: bool,
+    
+    #[structopt(long, conflicts_with_all = &["create", "comments-only"], help = "Instead of dumping, write the statements that migrate the schema of this connection URL to match the source database")]
+    diff: Option<String>,
+    
+    #[structopt(long, requires = "diff", number_of_values = 1, possible_values = &["defaults", "not-null", "indexes", "check-constraints", "foreign-keys"], help = "Leave differences of this kind on existing tables out of the --diff migration (repeatable)")]
+    diff_ignore: Vec<diff::DiffIgnore>,
+    
+    #[structopt(long, conflicts_with_all = &["create", "comments-only", "diff"], help = "Instead of dumping, write the INSERT, UPDATE and DELETE statements that bring the data of this connection URL in line with the source, pairing rows by primary key")]
+    data_diff: Option<String>,
+    
+    #[structopt(long, conflicts_with = "create", help = "Only dump COMMENT ON statements for tables, columns, types, functions and roles")]
+    comments_only: bool,
+    
+    #[structopt(long, help = "Print a summary of elapsed time per phase, tables, rows and bytes written to stderr")]
+    timing: bool,
+    
+    #[structopt(long, help = "Abort the dump if it hasn't finished after this many seconds, marking a plain dump as aborted and exiting with status 3")]
+    deadline: Option<u64>,
+    
+    #[structopt(short = "v", long, help = "Report each table on stderr as its data is dumped, with the share of the total table size done and, on a terminal, the estimated time left")]
+    verbose: bool,
+    
+    #[structopt(long, help = "Run SET ROLE with this role after connecting, so the dump sees what the role sees (privileges, RLS)")]
+    set_role: Option<String>,
+    
+    #[structopt(long, help = "Read everything from this exported snapshot (from pg_export_snapshot() or a replication slot), in one repeatable read transaction")]
+    snapshot: Option<String>,
+    
+    #[structopt(long, conflicts_with_all = &["create", "copy-freeze", "disable-triggers"], help = "Also write a restore.sh into the archive that loads up to this many tables at a time with psql, in foreign key order")]
+    parallel_copy: Option<usize>,
+    
+    #[structopt(long, conflicts_with = "snapshot", help = "Read each table's data over a fresh connection; the first one still serves the rest of the dump (tables are no longer read from one consistent snapshot)")]
+    reconnect_per_table: bool,
+    
+    #[structopt(name = "table", short = "t", long, number_of_values = 1, help = "Only dump this table (repeat for several tables)")]
+    tables: Vec<String>,
+    
+    #[structopt(long, help = "Read tables to dump from a file, one per line (# starts a comment); adds to --table")]
+    tables_from_file: Option<String>,
+    
+    #[structopt(long, help = "Read include and exclude rules for tables, table data, indexes, triggers and foreign data from a file in pg_dump's --filter format")]
+    filter: Option<String>,
+    
+    // The rules of the --filter file
+    #[structopt(skip)]
+    object_filter: filter::ObjectFilter,
+    
+    #[structopt(long, conflicts_with_all = &["diff", "data-diff", "comments-only", "counts-only"], help = "Also write the tables of the dump and the foreign keys between them to this file, with each table's load level and the keys on a cycle marked")]
+    emit_depgraph: Option<String>,
+    
+    #[structopt(long, help = "Format of --emit-depgraph: json or dot (Graphviz)", default_value = "json", possible_values = &["json", "dot"])]
+    depgraph_format: GraphFormat,
+    
+    #[structopt(long, help = "End the dump with a table holding a row about it: when it was taken, from which host and database, by which version of this tool and how many tables it holds")]
+    record_dump_metadata: bool,
+    
+    #[structopt(long, help = "Name of the --record-dump-metadata table", default_value = "_dump_metadata")]
+    dump_metadata_table: String,
+    
+    #[structopt(name = "sequence", long, number_of_values = 1, help = "Only dump sequences matching this pattern, where * and ? are wildcards (repeat for several patterns)")]
+    sequences: Vec<String>,
+    
+    #[structopt(long, number_of_values = 1, help = "Don't dump sequences matching this pattern, where * and ? are wildcards (repeat for several patterns)")]
+    exclude_sequence: Vec<String>,
+    
+    #[structopt(long, number_of_values = 1, help = "Dump only the rows of tables matching this pattern, whose definitions the target already has; * and ? are wildcards (repeat for several patterns)")]
+    data_only_for: Vec<String>,
+    
+    #[structopt(long, number_of_values = 1, help = "Dump only the definitions of tables matching this pattern, without their rows; * and ? are wildcards (repeat for several patterns)")]
+    schema_only_for: Vec<String>,
+    
+    #[structopt(long, help = "Also dump the definitions of tables the selected tables reference through foreign keys, recursively")]
+    include_dependents: bool,
+    
+    #[structopt(long, requires = "include-dependents", help = "Also dump the rows of the tables pulled in by --include-dependents")]
+    include_dependent_data: bool,
+    
+    #[structopt(long, help = "Emit TRUNCATE ... CASCADE for every dumped table before loading any data, to reseed an existing database")]
+    truncate_before_load: bool,
+    
+    #[structopt(long, help = "Leave out the tables, sequences, types, collations, text search objects and functions that extensions created, and write CREATE EXTENSION IF NOT EXISTS for the extensions instead")]
+    exclude_extension_owned_objects: bool,
+    
+    #[structopt(long, requires = "exclude-extension-owned-objects", help = "Also dump the rows of extension configuration tables that the extension didn't add itself, as pg_dump does")]
+    extension_data: bool,
+    
+    #[structopt(long, number_of_values = 1, help = "Rename a role in the output, given as old=new (repeat for several roles)")]
+    map_role: Vec<RoleMapping>,
+    
+    #[structopt(long, help = "Don't write ALTER ... OWNER TO statements; objects are owned by the role that runs the reload")]
+    no_owner: bool,
+    
+    #[structopt(long, help = "Also dump SECURITY LABEL statements for the tables, columns, trigger functions and roles in the dump; the label providers must be loaded where the dump is restored")]
+    security_labels: bool,
+    
+    #[structopt(long, help = "How table rows are written: insert statements or copy blocks", default_value = "insert", possible_values = &["insert", "copy"])]
+    data_format: DataFormat,
+    
+    #[structopt(long, help = "Load all data in one transaction that truncates the tables first, using COPY ... WITH (FREEZE) (requires --data-format copy)")]
+    copy_freeze: bool,
+    
+    #[structopt(long, help = "NULL marker of COPY blocks instead of \\N (requires --data-format copy); an empty marker writes the rows as CSV, quoting empty strings")]
+    null_as: Option<String>,
+    
+    #[structopt(long, conflicts_with = "copy-freeze", help = "Wrap every N INSERT statements of a table in BEGIN/COMMIT, so a plain reload doesn't commit each row on its own (requires --data-format insert)")]
+    commit_every: Option<u64>,
+    
+    #[structopt(long, help = "Set session_replication_role = replica while loading data, skipping triggers and foreign key checks (superuser only)")]
+    disable_triggers: bool,
+    
+    #[structopt(long, conflicts_with = "disable-triggers", help = "Write no SET statements; the loading session's settings are left as they are")]
+    no_set: bool,
+    
+    #[structopt(long, help = "Leave out the descriptive -- comment lines, keeping only the SQL")]
+    no_comments: bool,
+    
+    #[structopt(long, help = "Mark the start and end of each section with -- >>> SECTION: / -- <<< END SECTION: comments for splitting the output downstream")]
+    section_markers: bool,
+    
+    #[structopt(long, help = "Start the reload script with \\set ON_ERROR_STOP on so psql aborts at the first failing statement after the roles")]
+    on_error_stop: bool,
+    
+    #[structopt(long, number_of_values = 1, help = "Only dump these columns of a table, given as table:col1,col2 (repeat for several tables)")]
+    columns: Vec<ColumnSelection>,
+    
+    #[structopt(long, number_of_values = 1, help = "Leave these columns of a table out of the dump, given as table:col1,col2 (repeat for several tables)")]
+    exclude_columns: Vec<ColumnSelection>,
+    
+    #[structopt(long, number_of_values = 1, conflicts_with_all = &["diff", "data-diff"], help = "Give a column another type in the dump, given as table.column:type, e.g. orders.id:bigint; INSERT values are cast to it (repeat for several columns)")]
+    retype: Vec<Retype>,
+    
+    #[structopt(long, help = "Maintenance appended after the data load: none, analyze or vacuum-analyze", default_value = "none", possible_values = &["none", "analyze", "vacuum-analyze"])]
+    post_maintenance: PostMaintenance,
+    
+    #[structopt(long, help = "Stream the rows of tables estimated to need more than this many bytes instead of fetching them all at once")]
+    max_memory: Option<u64>,
+    
+    #[structopt(long, help = "Rows fetched per round trip when a table's rows are streamed through a cursor; smaller uses less memory, larger fewer round trips", default_value = "10000")]
+    fetch_size: u64,
+    
+    #[structopt(long, help = "Largest value, in bytes of its text form, written to the dump; see --max-field-action")]
+    max_field_size: Option<usize>,
+    
+    #[structopt(long, help = "What to do with a value over --max-field-size: truncate (the default), null or abort", possible_values = &["truncate", "null", "abort"])]
+    max_field_action: Option<FieldAction>,
+    
+    #[structopt(long, help = "Order of the columns in INSERT and COPY column lists: attnum (table order) or alphabetical", default_value = "attnum", possible_values = &["attnum", "alphabetical"])]
+    sort_columns: ColumnOrder,
+    
+    #[structopt(long, help = "How string values in INSERT statements are quoted: standard, dollar, escape or auto", default_value = "standard", possible_values = &["standard", "dollar", "escape", "auto"])]
+    string_quoting: StringQuoting,
+    
+    #[structopt(long, help = "Case of SQL keywords and built-in type names: upper, lower or preserve", default_value = "preserve", possible_values = &["upper", "lower", "preserve"])]
+    keyword_case: KeywordCase,
+    
+    #[structopt(long, help = "SQL dialect of the output: postgres, generic for other engines' import tools (no psql meta-commands, SET statements or owners, CAST instead of :: in data values), mysql (experimental: tables, keys, indexes and rows for MySQL 8) or sqlite (experimental: the same for sqlite3)", default_value = "postgres", possible_values = &["postgres", "generic", "mysql", "sqlite"])]
+    dialect: Dialect,
+    
+    #[structopt(long, help = "Text that ends each statement instead of ; (plain output only)", default_value = ";")]
+    statement_terminator: String,
+    
+    #[structopt(long, help = "Don't dump column-level privileges (GRANT SELECT (col) ON table TO role)")]
+    exclude_column_privileges: bool,
+    
+    #[structopt(long, help = "Also dump table and column privileges granted to PUBLIC")]
+    include_acl_for_public: bool,
+    
+    #[structopt(long, help = "Write each table's size, estimated row count and number of indexes as a comment before its CREATE TABLE")]
+    annotate_sizes: bool,
+    
+    #[structopt(long, help = "Add CHECK and foreign key constraints after the data as NOT VALID, then validate them in a separate section")]
+    constraints_not_valid: bool,
+    
+    #[structopt(long, requires = "constraints-not-valid", help = "Leave out the VALIDATE CONSTRAINT section of --constraints-not-valid")]
+    no_validate: bool,
+    
+    #[structopt(long, conflicts_with_all = &["create", "diff", "comments-only", "sample-rows", "where-all"], help = "After dumping, load the dump into a scratch database and compare row counts with the source")]
+    verify_reload: bool,
+    
+    #[structopt(long, requires = "verify-reload", help = "Existing empty database to load into for --verify-reload (default: a temporary database that is created and dropped)")]
+    verify_dbname: Option<String>,
+    
+    #[structopt(long, help = "SQL predicate applied to the data of every table whose columns it names, e.g. \"tenant_id = 42\"")]
+    where_all: Option<String>,
+    
+    #[structopt(long, requires = "where-all", help = "Leave out the data of tables the --where-all predicate does not apply to instead of dumping them whole")]
+    where_all_skip_missing: bool,
 }
 
-async fn connect(opt: &Opt) -> Result<Client, Box<dyn Error>> {
-    let connection_string = format!(
-        "host={} port={} dbname={} user={} password={}",
-        opt.host, opt.port, opt.dbname, opt.user, opt.password
-    );
+// Connection settings are always present unless --list is used, which never connects
+impl Opt {
+    // Tables picked by --data-only-for, whose definitions the target already has
+    fn data_only_for(&self, table: &str) -> bool {
+        self.data_only_for.iter().any(|pattern| glob_matches(pattern, table))
+    }
+    
+    // Applies --sequence and --exclude-sequence
+    fn keeps_sequence(&self, name: &str) -> bool {
+        (self.sequences.is_empty() || self.sequences.iter().any(|pattern| glob_matches(pattern, name)))
+            && !self.exclude_sequence.iter().any(|pattern| glob_matches(pattern, name))
+    }
+    
+    // Whether no table is picked by name, with --table, --tables-from-file or
+    // an include rule of --filter, so the whole database is dumped
+    fn selects_all_tables(&self) -> bool {
+        self.tables.is_empty() && !self.object_filter.includes_tables()
+    }
+    
+    fn host(&self) -> &str {
+        self.host.as_deref().unwrap_or_default()
+    }
+    
+    fn dbname(&self) -> &str {
+        self.dbname.as_deref().unwrap_or_default()
+    }
+    
+    fn field_action(&self) -> FieldAction {
+        self.max_field_action.unwrap_or(FieldAction::Truncate)
+    }
+    
+    fn user(&self) -> &str {
+        self.user.as_deref().unwrap_or_default()
+    }
+    
+    // SET statements are left out with --no-set and outside the postgres dialect
+    fn writes_set(&self) -> bool {
+        !self.no_set && self.dialect == Dialect::Postgres
+    }
+    
+    // ALTER ... OWNER TO is PostgreSQL's own, so only that dialect writes it
+    fn writes_owners(&self) -> bool {
+        !self.no_owner && self.dialect == Dialect::Postgres
+    }
+    
+    fn writes_to_stdout(&self) -> bool {
+        self.output.as_deref() == Some("-")
+    }
+    
+    // Standard output carries SQL, through --output - or --tee -, so progress goes to stderr
+    fn stdout_taken(&self) -> bool {
+        self.writes_to_stdout() || self.tee.iter().any(|path| path == "-")
+    }
+    
+    // An explicit or PGPASSWORD password wins; otherwise consult the password file
+    fn password_for(&self, host: &str) -> Option<String> {
+        if let Some(password) = &self.password {
+            return Some(password.clone());
+        }
+        
+        let passfile = match &self.passfile {
+            Some(path) => expand_tilde(path),
+            None => pgpass::default_path()?,
+        };
+        
+        match pgpass::lookup(&passfile, host, self.port, self.dbname(), self.user()) {
+            Ok(password) => password,
+            Err(e) => {
+                eprintln!("Warning: could not read password file {}: {}", passfile.display(), e);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SessionAttrs {
+    Any,
+    ReadWrite,
+    ReadOnly,
+    Primary,
+    Standby,
+}
+
+impl std::str::FromStr for SessionAttrs {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(SessionAttrs::Any),
+            "read-write" => Ok(SessionAttrs::ReadWrite),
+            "read-only" => Ok(SessionAttrs::ReadOnly),
+            "primary" => Ok(SessionAttrs::Primary),
+            "standby" => Ok(SessionAttrs::Standby),
+            _ => Err(format!("Unknown target session attributes: {}", s)),
+        }
+    }
+}
+
+fn build_config(opt: &Opt, hosts: &[&str]) -> Config {
+    let mut config = Config::new();
+    for host in hosts {
+        config.host(*host);
+    }
+    config
+        .port(opt.port)
+        .dbname(opt.dbname())
+        .user(opt.user())
+        .application_name("database-dump")
+        .connect_timeout(Duration::from_secs(opt.connect_timeout))
+        // No TLS connector is compiled in, so never attempt an SSL handshake
+        .ssl_mode(SslMode::Disable);
+    
+    // Keep long, quiet phases of the dump from being reaped by NAT and firewalls
+    if let Some(idle) = opt.keepalives_idle {
+        config.keepalives_idle(Duration::from_secs(idle));
+    }
+    if let Some(interval) = opt.keepalives_interval {
+        config.keepalives_interval(Duration::from_secs(interval));
+    }
+    if let Some(timeout) = opt.tcp_user_timeout {
+        config.tcp_user_timeout(Duration::from_secs(timeout));
+    }
+    
+    // A Config holds one password, so hosts whose passwords differ get none
+    // here and are tried one at a time by connect_hosts
+    if passwords_agree(opt, hosts)
+        && let Some(password) = hosts.first().and_then(|host| opt.password_for(host)) {
+        config.password(password);
+    }
+    config
+}
+
+fn passwords_agree(opt: &Opt, hosts: &[&str]) -> bool {
+    let passwords: Vec<Option<String>> = hosts.iter().map(|host| opt.password_for(host)).collect();
+    passwords.windows(2).all(|pair| pair[0] == pair[1])
+}
+
+// Connects to the first of `hosts` that accepts the connection. The driver
+// walks the list itself when one password serves every host; otherwise each
+// host gets a config of its own with its own password.
+async fn connect_hosts(opt: &Opt, hosts: &[&str], configure: impl Fn(&mut Config)) -> Result<Session, Box<dyn Error>> {
+    if passwords_agree(opt, hosts) {
+        let mut config = build_config(opt, hosts);
+        configure(&mut config);
+        return connect_config(&config).await;
+    }
+    
+    let mut last_error: Option<Box<dyn Error>> = None;
+    for host in hosts {
+        let mut config = build_config(opt, &[host]);
+        configure(&mut config);
+        match connect_config(&config).await {
+            Ok(client) => return Ok(client),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(match last_error {
+        Some(e) => e,
+        None => Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "No database host given",
+        )),
+    })
+}
+
+// search_path set at the top of the dump. The session uses the same one so that
+// deparsed definitions qualify exactly the names the reload cannot resolve.
+const DUMP_SEARCH_PATH: &str = "public, pg_catalog";
+
+// A client together with the task driving its connection. Dropping it, for
+// instance when the future running a dump is dropped on a timeout, asks the
+// server to cancel whatever query is still running and aborts the task, so
+// neither the backend nor the socket outlives the dump.
+struct Session {
+    client: Client,
+    connection: JoinHandle<()>,
+    // server_version_num, for what depends on the server's version
+    server_version: u32,
+    // Work started through run and not yet finished; work whose future was
+    // dropped never finishes, which leaves its query in flight
+    running: Cell<u32>,
+}
+
+impl Session {
+    // Awaits work that queries the session, keeping it counted as running
+    // until it completes
+    async fn run<T>(&self, work: impl Future<Output = T>) -> T {
+        self.running.set(self.running.get() + 1);
+        let result = work.await;
+        self.running.set(self.running.get() - 1);
+        result
+    }
+}
+
+impl std::ops::Deref for Session {
+    type Target = Client;
     
-    let (client, connection) = tokio_postgres::connect(&connection_string, NoTls).await?;
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // Only work cut short on a connection that is still up can have left
+        // a query in flight
+        if self.running.get() > 0 && !self.connection.is_finished() {
+            // Sent from a thread of its own and waited for: a task spawned on
+            // the dump's runtime would be dropped unsent when the process
+            // exits right after, as it does on --deadline
+            let cancel = self.client.cancel_token();
+            let sender = std::thread::spawn(move || {
+                if let Ok(runtime) = tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                    let _ = runtime.block_on(cancel.cancel_query(NoTls));
+                }
+            });
+            let _ = sender.join();
+        }
+        self.connection.abort();
+    }
+}
+
+async fn connect_config(config: &Config) -> Result<Session, Box<dyn Error>> {
+    let (client, connection) = config.connect(NoTls).await?;
     
-    // Spawn the connection handler in the background
-    tokio::spawn(async move {
+    // Drive the connection in the background; the Session owns the task
+    let connection = tokio::spawn(async move {
         if let Err(e) = connection.await {
             eprintln!("Connection error: {}", e);
         }
     });
+    let server_version = check_server(&client).await?;
+    let session = Session { client, connection, server_version, running: Cell::new(0) };
     
-    Ok(client)
+    session.batch_execute(&format!("SET search_path = {}", DUMP_SEARCH_PATH)).await?;
+    
+    Ok(session)
+}
+
+// The oldest server the catalog queries are written for: they read
+// attgenerated, which arrived in PostgreSQL 12
+const MIN_SERVER_VERSION: u32 = 120000;
+
+// Servers that speak the protocol but aren't PostgreSQL, or are too old for
+// the catalog queries, are turned away with what was found instead of failing
+// on the first query that needs what they lack. A query that fails is passed
+// on as it is, so a dropped connection is retried like any other.
+async fn check_server(client: &Client) -> Result<u32, Box<dyn Error>> {
+    let unsupported = |message: String| -> Box<dyn Error> {
+        Box::new(std::io::Error::new(std::io::ErrorKind::Unsupported, message))
+    };
+    let version: String = client.query_one("SELECT pg_catalog.version()", &[]).await?.get(0);
+    if !version.starts_with("PostgreSQL ") {
+        return Err(unsupported(format!("The server is not PostgreSQL ({}); database-dump only reads PostgreSQL databases", version)));
+    }
+    let version_num: String = client.query_one("SHOW server_version_num", &[]).await?.get(0);
+    let version_num: u32 = version_num.parse()
+        .map_err(|_| format!("The server reports a server_version_num of {}, which isn't a number", version_num))?;
+    if version_num < MIN_SERVER_VERSION {
+        return Err(unsupported(format!(
+            "PostgreSQL {} is not supported; database-dump needs PostgreSQL {} or later",
+            version_name(version_num), version_name(MIN_SERVER_VERSION)
+        )));
+    }
+    Ok(version_num)
+}
+
+fn unsupported_server(e: &(dyn Error + 'static)) -> bool {
+    e.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::Unsupported)
+}
+
+// A server_version_num as its release: 150018 is 15.18, 90624 is 9.6.24
+fn version_name(version_num: u32) -> String {
+    if version_num >= 100000 {
+        format!("{}.{}", version_num / 10000, version_num % 10000)
+    } else {
+        format!("{}.{}.{}", version_num / 10000, version_num / 100 % 100, version_num % 100)
+    }
+}
+
+async fn connect(opt: &Opt) -> Result<Session, Box<dyn Error>> {
+    let hosts: Vec<&str> = opt.host().split(',').map(str::trim).filter(|h| !h.is_empty()).collect();
+    
+    let native_attrs = match opt.target_session_attrs {
+        SessionAttrs::Any => Some(TargetSessionAttrs::Any),
+        SessionAttrs::ReadWrite => Some(TargetSessionAttrs::ReadWrite),
+        SessionAttrs::ReadOnly => Some(TargetSessionAttrs::ReadOnly),
+        SessionAttrs::Primary | SessionAttrs::Standby => None,
+    };
+    
+    // The driver checks the attributes it understands on each host itself
+    if let Some(attrs) = native_attrs {
+        return connect_hosts(opt, &hosts, |config| {
+            config.target_session_attrs(attrs);
+        }).await;
+    }
+    
+    // primary/standby are decided by the server's recovery state, so probe each host in turn
+    let want_standby = opt.target_session_attrs == SessionAttrs::Standby;
+    let mut last_error: Option<Box<dyn Error>> = None;
+    
+    for host in &hosts {
+        let client = match connect_config(&build_config(opt, &[host])).await {
+            Ok(client) => client,
+            Err(e) => {
+                last_error = Some(e);
+                continue;
+            }
+        };
+        
+        let in_recovery: bool = match client.query_one("SELECT pg_catalog.pg_is_in_recovery()", &[]).await {
+            Ok(row) => row.get(0),
+            Err(e) => {
+                last_error = Some(Box::new(e));
+                continue;
+            }
+        };
+        if in_recovery == want_standby {
+            return Ok(client);
+        }
+        
+        last_error = Some(Box::new(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Host {} is not a {}", host, if want_standby { "standby" } else { "primary" }),
+        )));
+    }
+    
+    Err(match last_error {
+        Some(e) => e,
+        None => Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "No database host given",
+        )),
+    })
 }
 
-async fn connect_with_retry(opt: &Opt, max_retries: u32) -> Result<Client, Box<dyn Error>> {
+async fn connect_with_retry(opt: &Opt, max_retries: u32) -> Result<Session, Box<dyn Error>> {
     let mut retries = 0;
     let mut last_error = None;
 
     while retries < max_retries {
         match connect(opt).await {
             Ok(client) => return Ok(client),
+            // A server that isn't supported won't be on the next attempt
+            Err(e) if unsupported_server(e.as_ref()) => {
+                return Err(e);
+            }
             Err(e) => {
                 eprintln!("Connection attempt {} failed: {}", retries + 1, e);
                 last_error = Some(e);
                 retries += 1;
                 
                 if retries < max_retries {
-                    // Exponential backoff
-                    let delay = Duration::from_secs(2u64.pow(retries.min(4)));
-                    eprintln!("Retrying in {} seconds...", delay.as_secs());
-                    tokio::time::sleep(delay).await;
+                    back_off(opt, retries).await;
                 }
             }
         }
@@ -80,565 +690,5409 @@ async fn connect_with_retry(opt: &Opt, max_retries: u32) -> Result<Client, Box<d
     })
 }
 
-struct DumpTarget<'a> {
-    file: Option<&'a mut File>,
+// Waits before retry number `retries`: exponential backoff, jittered unless
+// --no-jitter is given
+async fn back_off(opt: &Opt, retries: u32) {
+    let mut delay = Duration::from_secs(2u64.pow(retries.min(4)));
+    if !opt.no_jitter {
+        delay = full_jitter(delay);
+    }
+    eprintln!("Retrying in {:.1} seconds...", delay.as_secs_f64());
+    tokio::time::sleep(delay).await;
 }
 
-impl<'a> DumpTarget<'a> {
-    fn new(file: Option<&'a mut File>) -> Self {
-        DumpTarget { file }
+// Catalog queries --warmup runs before the dump, one for each kind of catalog
+// access the dump depends on
+const WARMUP_PROBES: &[(&str, &str)] = &[
+    ("tables", "SELECT count(*) FROM pg_catalog.pg_class c JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace WHERE n.nspname = 'public'"),
+    ("columns", "SELECT count(*) FROM pg_catalog.pg_attribute a JOIN pg_catalog.pg_class c ON c.oid = a.attrelid JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace WHERE n.nspname = 'public' AND a.attnum > 0"),
+    ("constraints", "SELECT count(*) FROM pg_catalog.pg_constraint"),
+    ("indexes", "SELECT count(*) FROM pg_catalog.pg_index"),
+    ("roles", "SELECT count(*) FROM pg_catalog.pg_roles"),
+    ("dependencies", "SELECT count(*) FROM pg_catalog.pg_depend"),
+];
+
+// Runs each warmup probe with the connection retry policy, so flaky catalog
+// access fails the run now rather than hours into the dump
+async fn warm_up(client: &Client, opt: &Opt, max_retries: u32) -> Result<(), Box<dyn Error>> {
+    for (probe, query) in WARMUP_PROBES {
+        let mut retries = 0;
+        loop {
+            match client.query_one(*query, &[]).await {
+                Ok(_) => break,
+                Err(e) => {
+                    eprintln!("Warmup probe {} failed on attempt {}: {}", probe, retries + 1, e);
+                    retries += 1;
+                    // A closed connection won't recover by asking again
+                    if retries >= max_retries || client.is_closed() {
+                        return Err(format!("Warmup probe {} failed: {}", probe, e).into());
+                    }
+                    back_off(opt, retries).await;
+                }
+            }
+        }
     }
+    Ok(())
+}
+
+// A random delay between zero and `backoff`, so dumpers that lost the server
+// at the same moment don't all retry at the same moment. RandomState is seeded
+// randomly per process, which is all the randomness this needs.
+fn full_jitter(backoff: Duration) -> Duration {
+    let random = RandomState::new().hash_one(Instant::now());
+    backoff.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DataFormat {
+    Insert,
+    Copy,
+}
+
+impl std::str::FromStr for DataFormat {
+    type Err = String;
     
-    fn write_line(&mut self, line: &str) -> Result<(), Box<dyn Error>> {
-        match &mut self.file {
-            Some(file) => {
-                writeln!(file, "{}", line)?;
-            }
-            None => {
-                println!("{}", line);
-            }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "insert" => Ok(DataFormat::Insert),
+            "copy" => Ok(DataFormat::Copy),
+            _ => Err(format!("Unknown data format: {}", s)),
         }
-        Ok(())
     }
 }
 
-async fn dump_schema_to<'a>(client: &Client, target: &'a mut DumpTarget<'a>) -> Result<(), Box<dyn Error>> {
-    target.write_line("-- Database schema definition (sequences, types, tables, constraints)  ")?;
-    target.write_line("SET client_encoding = 'UTF8';")?;
-    target.write_line("SET standard_conforming_strings = on;")?;
-    target.write_line("SET check_function_bodies = false;")?;
-    target.write_line("SET client_min_messages = warning;")?;
-    target.write_line("SET search_path = public, pg_catalog;")?;
-    target.write_line("")?;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PostMaintenance {
+    None,
+    Analyze,
+    VacuumAnalyze,
+}
+
+impl std::str::FromStr for PostMaintenance {
+    type Err = String;
     
-    // Get and dump custom types first
-    target.write_line("-- Custom Types")?;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(PostMaintenance::None),
+            "analyze" => Ok(PostMaintenance::Analyze),
+            "vacuum-analyze" => Ok(PostMaintenance::VacuumAnalyze),
+            _ => Err(format!("Unknown post-load maintenance: {}", s)),
+        }
+    }
+}
+
+// What --max-field-size does with a value over the limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldAction {
+    Truncate,
+    Null,
+    Abort,
+}
+
+// What became of a value checked against --max-field-size
+enum FieldLimit {
+    Fits,
+    // Truncated, or NULL
+    Replaced(Option<String>),
+    // Over the limit, but of a type that can't be truncated
+    KeptWhole,
+}
+
+impl std::str::FromStr for FieldAction {
+    type Err = String;
     
-    let enum_types = client.query(
-        "SELECT t.typname 
-         FROM pg_catalog.pg_type t 
-         JOIN pg_catalog.pg_namespace n ON n.oid = t.typnamespace
-         WHERE t.typtype = 'e' 
-         AND n.nspname = 'public'
-         ORDER BY t.typname",
-        &[],
-    ).await?;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "truncate" => Ok(FieldAction::Truncate),
+            "null" => Ok(FieldAction::Null),
+            "abort" => Ok(FieldAction::Abort),
+            _ => Err(format!("Unknown field action: {}", s)),
+        }
+    }
+}
+
+// How --string-quoting writes string values that need escaping
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StringQuoting {
+    Standard,
+    Dollar,
+    Escape,
+    Auto,
+}
+
+impl std::str::FromStr for StringQuoting {
+    type Err = String;
     
-    for type_row in enum_types {
-        let type_name: String = type_row.get(0);
-        
-        // Get enum labels
-        let enum_values = client.query(
-            "SELECT e.enumlabel
-             FROM pg_catalog.pg_enum e
-             JOIN pg_catalog.pg_type t ON e.enumtypid = t.oid
-             WHERE t.typname = $1
-             ORDER BY e.enumsortorder",
-            &[&type_name],
-        ).await?;
-        
-        if enum_values.is_empty() {
-            continue;
-        }
-        
-        let values: Vec<String> = enum_values.iter()
-            .filter_map(|row| row.try_get::<_, String>(0).ok())
-            .map(|val| format!("'{}'", val.replace("'", "''")))
-            .collect();
-            
-        if !values.is_empty() {
-            target.write_line(&format!("CREATE TYPE {} AS ENUM ({});", 
-                type_name, values.join(", ")))?;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(StringQuoting::Standard),
+            "dollar" => Ok(StringQuoting::Dollar),
+            "escape" => Ok(StringQuoting::Escape),
+            "auto" => Ok(StringQuoting::Auto),
+            _ => Err(format!("Unknown string quoting: {}", s)),
         }
     }
+}
+
+// How --counts-only prints the counts
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CountsFormat {
+    Csv,
+    Json,
+}
+
+impl std::str::FromStr for CountsFormat {
+    type Err = String;
     
-    target.write_line("")?;
-    
-    // Get and dump sequences
-    target.write_line("-- Sequences")?;
-    
-    let sequences = client.query(
-        "SELECT c.relname
-         FROM pg_catalog.pg_class c
-         JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
-         WHERE c.relkind = 'S'
-         AND n.nspname = 'public'
-         ORDER BY c.relname",
-        &[],
-    ).await?;
-    
-    for seq_row in sequences {
-        let seq_name: String = seq_row.get(0);
-        target.write_line(&format!("CREATE SEQUENCE {};", seq_name))?;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(CountsFormat::Csv),
+            "json" => Ok(CountsFormat::Json),
+            _ => Err(format!("Unknown counts format: {}", s)),
+        }
     }
+}
+
+// Order of the column lists of table data, for --sort-columns
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnOrder {
+    Attnum,
+    Alphabetical,
+}
+
+impl std::str::FromStr for ColumnOrder {
+    type Err = String;
     
-    target.write_line("")?;
-    
-    // Get table list
-    let tables = client.query(
-        "SELECT c.relname
-         FROM pg_catalog.pg_class c
-         JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
-         WHERE c.relkind = 'r' 
-         AND n.nspname = 'public'
-         ORDER BY c.relname",
-        &[],
-    ).await?;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "attnum" => Ok(ColumnOrder::Attnum),
+            "alphabetical" => Ok(ColumnOrder::Alphabetical),
+            _ => Err(format!("Unknown column order: {}", s)),
+        }
+    }
+}
+
+// How --keyword-case writes SQL keywords
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum KeywordCase {
+    Upper,
+    Lower,
+    Preserve,
+}
+
+impl std::str::FromStr for KeywordCase {
+    type Err = String;
     
-    // Store table names for later
-    let table_names: Vec<String> = tables.iter()
-        .map(|row| row.get::<_, String>(0))
-        .collect();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "upper" => Ok(KeywordCase::Upper),
+            "lower" => Ok(KeywordCase::Lower),
+            "preserve" => Ok(KeywordCase::Preserve),
+            _ => Err(format!("Unknown keyword case: {}", s)),
+        }
+    }
+}
+
+// Which SQL dialect --dialect writes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dialect {
+    Postgres,
+    // ANSI-leaning SQL for other engines' import tools: no psql meta-commands,
+    // SET statements or owners, and CAST(... AS ...) instead of :: in data values
+    Generic,
+    // Tables, keys and rows for MySQL 8, written by the mysql module
+    Mysql,
+    // The same for sqlite3, written by the sqlite module
+    Sqlite,
+}
+
+impl std::str::FromStr for Dialect {
+    type Err = String;
     
-    // Create tables
-    target.write_line("-- Tables")?;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "postgres" => Ok(Dialect::Postgres),
+            "generic" => Ok(Dialect::Generic),
+            "mysql" => Ok(Dialect::Mysql),
+            "sqlite" => Ok(Dialect::Sqlite),
+            _ => Err(format!("Unknown dialect: {}", s)),
+        }
+    }
+}
+
+// Keywords and built-in type names that --keyword-case recases, sorted for
+// binary search. Anything else, such as an unquoted identifier, is left as is.
+const SQL_KEYWORDS: &[&str] = &[
+    "ADD", "AFTER", "ALL", "ALTER", "ALWAYS", "ANALYZE", "AND", "ARRAY", "AS", "ASC",
+    "BEFORE", "BEGIN", "BIGINT", "BIT", "BOOLEAN", "BY", "BYPASSRLS", "CACHE", "CASCADE",
+    "CASE", "CHARACTER", "CHECK", "COLLATE", "COLLATION", "COLUMN", "COMMENT", "COMMIT",
+    "CONNECT", "CONNECTION", "CONSTRAINT", "COPY", "CREATE", "CREATEDB", "CREATEROLE",
+    "CYCLE", "DATABASE", "DATE", "DEFAULT", "DEFERRABLE", "DEFERRED", "DELETE", "DESC",
+    "DISABLE", "DOUBLE", "DROP", "EACH", "ELSE", "ENABLE", "ENCRYPTED", "END", "EXCLUDE",
+    "EXECUTE", "EXISTS", "EXTENSION", "FALSE", "FIRST", "FOR", "FOREIGN", "FORMAT", "FREEZE", "FROM",
+    "FULL", "FUNCTION", "GENERATED", "GRANT", "IDENTITY", "IF", "IMMEDIATE", "IN",
+    "INCREMENT", "INDEX", "INHERIT", "INITIALLY", "INSERT", "INTEGER", "INTERVAL", "INTO",
+    "IS", "KEY", "LABEL", "LAST", "LIMIT", "LOGIN", "MAINTAIN", "MATCH", "MAXVALUE", "MINVALUE",
+    "NO", "NOBYPASSRLS", "NOCREATEDB", "NOCREATEROLE", "NOINHERIT", "NOLOGIN",
+    "NOREPLICATION", "NOSUPERUSER", "NOT", "NULL", "NULLS", "NUMERIC", "OF", "ON", "ONLY",
+    "OPTION", "OPTIONS", "OR", "OVERRIDING", "OWNED", "OWNER", "PARTIAL", "PASSWORD",
+    "PRECISION", "PRIMARY", "PRIVILEGES", "PROCEDURE", "PUBLIC", "REAL", "REFERENCES",
+    "RENAME", "REPLICA", "REPLICATION", "RESTART", "RESTRICT", "REVOKE", "ROLE", "ROW",
+    "SCHEMA", "SECURITY", "SELECT", "SEQUENCE", "SERVER", "SET", "SIMPLE", "SMALLINT", "START",
+    "STATEMENT", "STORED", "SUPERUSER", "SYSTEM", "TABLE", "TABLESPACE", "TEMP",
+    "TEMPORARY", "TEXT", "THEN", "TIME", "TIMESTAMP", "TO", "TRIGGER", "TRUE", "TRUNCATE",
+    "TYPE", "UNIQUE", "UPDATE", "USAGE", "USING", "VACUUM", "VALID", "VALIDATE", "VALUE",
+    "VALUES", "VARYING", "VIEW", "WHEN", "WHERE", "WITH", "WITHOUT", "ZONE",
+];
+
+// Length of the string literal, quoted identifier (MySQL's backticks too),
+// dollar-quoted text or trailing comment that `rest` starts with, if it starts with one
+fn quoted_len(rest: &str) -> Option<usize> {
+    let c = rest.chars().next()?;
+    if c == '\'' || c == '"' || c == '`' {
+        Some(rest[1..].find(c).map_or(rest.len(), |close| close + 2))
+    } else if c == '$' {
+        // $tag$ opens dollar-quoted text running to the next $tag$
+        let after = &rest[1..];
+        let tag_len = after.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(after.len());
+        if after[tag_len..].starts_with('$') && !after.starts_with(|c: char| c.is_ascii_digit()) {
+            let tag = &rest[..tag_len + 2];
+            Some(rest[tag.len()..].find(tag).map_or(rest.len(), |close| close + 2 * tag.len()))
+        } else {
+            None
+        }
+    } else if rest.starts_with("--") {
+        Some(rest.find('\n').unwrap_or(rest.len()))
+    } else {
+        None
+    }
+}
+
+// Recases the keywords of a line of SQL, leaving string literals, quoted
+// identifiers, dollar-quoted text and trailing comments untouched. psql
+// meta-commands are case-sensitive and pass through as they are.
+fn recase_keywords(line: &str, case: KeywordCase) -> String {
+    if case == KeywordCase::Preserve || line.starts_with('\\') {
+        return line.to_string();
+    }
     
-    for table_name in &table_names {
-        target.write_line(&format!("-- Table: {}", table_name))?;
-        
-        // Get columns
-        let columns = client.query(
-            "SELECT 
-                a.attname as column_name,
-                pg_catalog.format_type(a.atttypid, a.atttypmod) as data_type,
-                a.attnotnull as not_null,
-                pg_catalog.pg_get_expr(d.adbin, d.adrelid) as column_default
-             FROM pg_catalog.pg_attribute a
-             LEFT JOIN pg_catalog.pg_attrdef d ON (d.adrelid = a.attrelid AND d.adnum = a.attnum)
-             JOIN pg_catalog.pg_class c ON c.oid = a.attrelid
-             WHERE c.relname = $1
-             AND a.attnum > 0
-             AND NOT a.attisdropped
-             ORDER BY a.attnum",
-            &[&table_name],
-        ).await?;
-        
-        target.write_line(&format!("CREATE TABLE {} (", table_name))?;
-        
-        let mut column_defs = Vec::new();
-        
-        for column in &columns {
-            let column_name: String = column.get(0);
-            let data_type: String = column.get(1);
-            let not_null: bool = column.get(2);
-            let default_val: Option<String> = column.get(3);
-            
-            let mut col_def = format!("  {}", column_name);
-            col_def.push_str(&format!(" {}", data_type));
-            
-            if not_null {
-                col_def.push_str(" NOT NULL");
+    let mut recased = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let end = if let Some(len) = quoted_len(rest) {
+            len
+        } else if c.is_alphabetic() || c == '_' {
+            let word_len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$')).unwrap_or(rest.len());
+            let word = &rest[..word_len];
+            if SQL_KEYWORDS.binary_search(&word.to_ascii_uppercase().as_str()).is_ok() {
+                recased.push_str(&match case {
+                    KeywordCase::Upper => word.to_ascii_uppercase(),
+                    _ => word.to_ascii_lowercase(),
+                });
+                rest = &rest[word_len..];
+                continue;
             }
-            
-            if let Some(def) = default_val {
-                col_def.push_str(&format!(" DEFAULT {}", def));
+            word_len
+        } else {
+            c.len_utf8()
+        };
+        recased.push_str(&rest[..end]);
+        rest = &rest[end..];
+    }
+    recased
+}
+
+// Swaps the ; that ends the statement on a line, if any, for --statement-terminator.
+// Semicolons in literals, quoted names, dollar-quoted bodies and comments stay.
+fn replace_terminator(line: &str, terminator: &str) -> String {
+    if terminator == ";" {
+        return line.to_string();
+    }
+    
+    let mut end = None;
+    let mut position = 0;
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let len = match quoted_len(rest) {
+            // A trailing comment may follow the statement's end
+            Some(len) if rest.starts_with("--") => len,
+            Some(len) => {
+                end = None;
+                len
             }
-            
-            column_defs.push(col_def);
-        }
-        
-        // Get primary key
-        let pk_query = client.query(
-            "SELECT a.attname
-             FROM pg_catalog.pg_index i
-             JOIN pg_catalog.pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey)
-             JOIN pg_catalog.pg_class c ON c.oid = i.indrelid
-             WHERE c.relname = $1
-             AND i.indisprimary",
-            &[&table_name],
-        ).await?;
-        
-        if !pk_query.is_empty() {
-            let pk_cols: Vec<String> = pk_query.iter()
-                .map(|row| row.get::<_, String>(0))
-                .collect();
-                
-            if !pk_cols.is_empty() {
-                column_defs.push(format!("  PRIMARY KEY ({})", pk_cols.join(", ")));
+            None => {
+                if c == ';' {
+                    end = Some(position);
+                } else if !c.is_whitespace() {
+                    end = None;
+                }
+                c.len_utf8()
             }
+        };
+        position += len;
+        rest = &rest[len..];
+    }
+    match end {
+        Some(end) => format!("{}{}{}", &line[..end], terminator, &line[end + 1..]),
+        None => line.to_string(),
+    }
+}
+
+// Matches a name against a pattern where * stands for any run of characters
+// and ? for a single character
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position after the last * and the name position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+    
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p + 1, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            // Let the last * swallow one more character
+            p = star_p;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
         }
-        
-        target.write_line(&column_defs.join(",\n"))?;
-        target.write_line(");")?;
-        target.write_line("")?;
     }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// One --map-role rename
+#[derive(Debug, Clone)]
+struct RoleMapping {
+    from: String,
+    to: String,
+}
+
+impl std::str::FromStr for RoleMapping {
+    type Err = String;
     
-    // Add indices and constraints
-    target.write_line("-- Indexes and constraints")?;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((from, to)) if !from.is_empty() && !to.is_empty() => Ok(RoleMapping {
+                from: from.to_string(),
+                to: to.to_string(),
+            }),
+            _ => Err(format!("Expected a role mapping of the form old=new, got: {}", s)),
+        }
+    }
+}
+
+// The name a role is written under; roles without a mapping keep their own name
+fn map_role<'a>(mappings: &'a [RoleMapping], name: &'a str) -> &'a str {
+    mappings.iter()
+        .find(|mapping| mapping.from == name)
+        .map_or(name, |mapping| mapping.to.as_str())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FormatStyle {
+    Pretty,
+    Compact,
+}
+
+impl std::str::FromStr for FormatStyle {
+    type Err = String;
     
-    for table_name in &table_names {
-        // Add indexes
-        let indexes = client.query(
-            "SELECT indexdef 
-             FROM pg_catalog.pg_indexes 
-             WHERE tablename = $1
-             AND schemaname = 'public'
-             AND indexname NOT LIKE '%_pkey'",
-            &[&table_name],
-        ).await?;
-        
-        for idx in indexes {
-            let index_def: String = idx.get(0);
-            target.write_line(&format!("{};\n", index_def))?;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(FormatStyle::Pretty),
+            "compact" => Ok(FormatStyle::Compact),
+            _ => Err(format!("Unknown format style: {}", s)),
         }
-        
-        // Add foreign key constraints
-        let fk_constraints = client.query(
-            "SELECT
-                 conname,
-                 pg_catalog.pg_get_constraintdef(oid)
-             FROM pg_catalog.pg_constraint
-             WHERE conrelid = (
-                 SELECT oid FROM pg_catalog.pg_class WHERE relname = $1
-                 AND relnamespace = (SELECT oid FROM pg_catalog.pg_namespace WHERE nspname = 'public')
-             )
-             AND contype = 'f'",
-            &[&table_name],
-        ).await?;
-        
-        for fk in fk_constraints {
-            let constraint_def: String = fk.get(1);
-            target.write_line(&format!("ALTER TABLE {} ADD {};", table_name, constraint_def))?;
+    }
+}
+
+// Counters collected while dumping, reported by --timing
+#[derive(Default)]
+struct DumpStats {
+    tables: usize,
+    rows: u64,
+    bytes: u64,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl DumpStats {
+    // Adds to the phase's running total so per-table phases add up
+    fn record(&mut self, phase: &'static str, started: Instant) {
+        let elapsed = started.elapsed();
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => self.phases.push((phase, elapsed)),
         }
     }
     
-    // Add table data
-    target.write_line("\n-- Table data")?;
+    fn merge(&mut self, other: DumpStats) {
+        self.tables += other.tables;
+        self.rows += other.rows;
+        self.bytes += other.bytes;
+        for (phase, elapsed) in other.phases {
+            match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+                Some((_, total)) => *total += elapsed,
+                None => self.phases.push((phase, elapsed)),
+            }
+        }
+    }
     
-    for table_name in &table_names {
-        target.write_line(&format!("-- Data for table: {}", table_name))?;
-        
-        // Get column information
-        let columns = client.query(
-            "SELECT 
-                a.attname, 
-                pg_catalog.format_type(a.atttypid, a.atttypmod)
-             FROM pg_catalog.pg_attribute a
-             JOIN pg_catalog.pg_class c ON c.oid = a.attrelid
-             WHERE c.relname = $1
-             AND a.attnum > 0
-             AND NOT a.attisdropped
-             ORDER BY a.attnum",
-            &[&table_name],
-        ).await?;
-        
-        // Only dump data if we have columns
-        if columns.is_empty() {
-            continue;
+    fn print_summary(&self, total: Duration, connect: Duration) {
+        eprintln!("Timing summary:");
+        eprintln!("  {:<24} {:>10.3}s", "total", total.as_secs_f64());
+        eprintln!("  {:<24} {:>10.3}s", "connect", connect.as_secs_f64());
+        for (phase, elapsed) in &self.phases {
+            eprintln!("  {:<24} {:>10.3}s", phase, elapsed.as_secs_f64());
         }
-        
-        // Get column names
-        let column_names: Vec<String> = columns.iter()
-            .map(|col| col.get::<_, String>(0))
+        eprintln!("  {:<24} {:>10}", "tables", self.tables);
+        eprintln!("  {:<24} {:>10}", "rows", self.rows);
+        eprintln!("  {:<24} {:>10}", "bytes written", self.bytes);
+    }
+}
+
+// --verbose reporting of the data sections. Progress is measured in bytes on
+// disk rather than tables, since one large table can take longer than all
+// the others together; the time left assumes the rate so far holds.
+struct Progress {
+    sizes: Vec<(String, i64)>,
+    total: i64,
+    done: i64,
+    tables_done: usize,
+    started: Instant,
+    // The estimate changes from line to line, which only helps someone watching
+    show_eta: bool,
+}
+
+impl Progress {
+    async fn start(client: &Client, sections: &[Section]) -> Result<Self, Box<dyn Error>> {
+        let table_names: Vec<String> = sections.iter()
+            .filter_map(|section| match section {
+                Section::Data(table_name) => Some(table_name.clone()),
+                _ => None,
+            })
             .collect();
-            
-        let column_names_str = column_names.join(", ");
+        let sizes: Vec<(String, i64)> = client.query(
+            "SELECT c.relname::text, pg_catalog.pg_total_relation_size(c.oid)
+             FROM pg_catalog.pg_class c
+             WHERE c.relnamespace = 'public'::regnamespace
+             AND c.relname = ANY($1)",
+            &[&table_names],
+        ).await?.iter().map(|row| (row.get(0), row.get(1))).collect();
         
-        // Get table data
-        let select_query = format!("SELECT * FROM {}", table_name);
-        let rows = client.query(&select_query, &[]).await?;
+        Ok(Progress {
+            total: sizes.iter().map(|(_, size)| size).sum(),
+            sizes,
+            done: 0,
+            tables_done: 0,
+            started: Instant::now(),
+            show_eta: std::io::stderr().is_terminal(),
+        })
+    }
+    
+    fn table_done(&mut self, table_name: &str) {
+        self.done += self.sizes.iter().find(|(name, _)| name == table_name).map(|(_, size)| *size).unwrap_or(0);
+        self.tables_done += 1;
         
-        for row in rows {
-            let mut values = Vec::new();
-            
-            for (i, _) in columns.iter().enumerate() {
-                
-                // Handle different data types
-                let value = if let Ok(Some(val)) = row.try_get::<_, Option<String>>(i) {
-                    // String types
-                    format!("'{}'", val.replace("'", "''"))
-                } else if let Ok(Some(val)) = row.try_get::<_, Option<&str>>(i) {
-                    // String types
-                    format!("'{}'", val.replace("'", "''"))
-                } else if let Ok(val) = row.try_get::<_, i32>(i) {
-                    // Integer
-                    val.to_string()
-                } else if let Ok(val) = row.try_get::<_, i64>(i) {
-                    // Big integer
-                    val.to_string()
-                } else if let Ok(val) = row.try_get::<_, f64>(i) {
-                    // Float
-                    val.to_string()
-                } else if let Ok(val) = row.try_get::<_, bool>(i) {
-                    // Boolean
-                    if val { "TRUE".to_string() } else { "FALSE".to_string() }
-                } else {
-                    // NULL or other types
-                    "NULL".to_string()
-                };
-                
-                values.push(value);
-            }
-            
-            target.write_line(&format!(
-                "INSERT INTO {} ({}) VALUES ({});",
-                table_name, column_names_str, values.join(", ")
-            ))?;
+        let share = if self.total > 0 { self.done as f64 / self.total as f64 } else { 1.0 };
+        let mut line = format!(
+            "Dumped {} ({}/{} tables, {}% of {})",
+            table_name, self.tables_done, self.sizes.len().max(self.tables_done), (share * 100.0).floor(), human_size(self.total)
+        );
+        let left = if share > 0.0 { self.started.elapsed().as_secs_f64() * (1.0 - share) / share } else { 0.0 };
+        if self.show_eta && left >= 1.0 {
+            line.push_str(&format!(", about {} left", human_duration(left)));
         }
-        
-        target.write_line("")?;
+        eprintln!("{}", line);
     }
-    
-    Ok(())
 }
 
-async fn dump_users_and_roles_to<'a>(client: &Client, target: &'a mut DumpTarget<'a>, db_name: &str) -> Result<(), Box<dyn Error>> {
-    target.write_line("-- Users, roles and permissions")?;
-    target.write_line("")?;
+fn human_duration(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+// Output is buffered so a large table doesn't cost a write syscall per row;
+// call flush() before reporting the dump as complete
+struct DumpTarget<'a> {
+    file: BufWriter<&'a mut dyn Write>,
+    style: FormatStyle,
+    // Off with --no-comments, which drops the descriptive -- lines
+    comments: bool,
+    keyword_case: KeywordCase,
+    // What ends each statement, ; unless --statement-terminator says otherwise
+    terminator: String,
+    stats: DumpStats,
+}
+
+impl<'a> DumpTarget<'a> {
+    fn new(file: &'a mut dyn Write, opt: &Opt) -> Self {
+        DumpTarget {
+            file: BufWriter::new(file),
+            style: opt.format_style,
+            comments: !opt.no_comments,
+            keyword_case: opt.keyword_case,
+            terminator: opt.statement_terminator.clone(),
+            stats: DumpStats::default(),
+        }
+    }
     
-    // Check if we have access to role-related information
-    let has_role_access = client.query_one(
-        "SELECT COUNT(*) FROM pg_catalog.pg_roles LIMIT 1",
-        &[],
-    ).await.is_ok();
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.file.flush()?;
+        Ok(())
+    }
     
-    if !has_role_access {
-        target.write_line("-- Warning: No access to role information. Skipping user and role dump.")?;
-        target.write_line("-- You may need superuser privileges to dump roles.")?;
-        return Ok(());
+    // Writes a statement whose items are laid out one per line (pretty) or inline (compact)
+    fn write_block(&mut self, header: &str, items: &[String], footer: &str) -> Result<(), Box<dyn Error>> {
+        match self.style {
+            FormatStyle::Pretty => {
+                self.write_line(header)?;
+                if !items.is_empty() {
+                    let body: Vec<String> = items.iter().map(|item| format!("  {}", item)).collect();
+                    self.write_line(&body.join(",\n"))?;
+                }
+                self.write_line(footer)
+            }
+            FormatStyle::Compact => {
+                self.write_line(&format!("{}{}{}", header, items.join(", "), footer))
+            }
+        }
     }
     
-    // First get the owner of the database
-    let db_owner_query = client.query_one(
-        "SELECT r.rolname 
-         FROM pg_catalog.pg_database d 
-         JOIN pg_catalog.pg_roles r ON d.datdba = r.oid 
-         WHERE d.datname = $1",
-        &[&db_name],
-    ).await;
+    fn write_line(&mut self, line: &str) -> Result<(), Box<dyn Error>> {
+        // Blank separator lines are dropped in compact output
+        if line.is_empty() && self.style == FormatStyle::Compact {
+            return Ok(());
+        }
+        
+        if !self.comments && line.starts_with("--") {
+            return Ok(());
+        }
+        
+        let line = recase_keywords(line, self.keyword_case);
+        self.write_raw_line(&replace_terminator(&line, &self.terminator))
+    }
     
-    let mut db_owner = String::new();
-    if let Ok(owner_row) = db_owner_query {
-        db_owner = owner_row.get(0);
-        target.write_line(&format!("-- Database owner: {}", db_owner))?;
+    // Writes a line verbatim, even when empty; used for COPY rows, where an
+    // empty line is a row holding one empty string
+    fn write_raw_line(&mut self, line: &str) -> Result<(), Box<dyn Error>> {
+        self.stats.bytes += line.len() as u64 + 1;
+        writeln!(self.file, "{}", line)?;
+        Ok(())
     }
+}
+
+// One --columns or --exclude-columns entry
+#[derive(Debug, Clone)]
+struct ColumnSelection {
+    table: String,
+    columns: Vec<String>,
+}
+
+impl std::str::FromStr for ColumnSelection {
+    type Err = String;
     
-    // Get the active user too
-    let current_user_query = client.query_one("SELECT current_user", &[]).await;
-    let mut current_user = String::new();
-    if let Ok(user_row) = current_user_query {
-        current_user = user_row.get(0);
-        target.write_line(&format!("-- Current connection user: {}", current_user))?;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (table, columns) = s.split_once(':')
+            .ok_or_else(|| format!("Expected table:col1,col2, got: {}", s))?;
+        let columns: Vec<String> = columns.split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(String::from)
+            .collect();
+        if table.is_empty() || columns.is_empty() {
+            return Err(format!("Expected table:col1,col2, got: {}", s));
+        }
+        Ok(ColumnSelection { table: table.to_string(), columns })
     }
+}
+
+// One --retype entry
+#[derive(Debug, Clone)]
+struct Retype {
+    table: String,
+    column: String,
+    new_type: String,
+}
+
+impl std::str::FromStr for Retype {
+    type Err = String;
     
-    // Get tables in the database to find owners
-    let table_owners = client.query(
-        "SELECT DISTINCT r.rolname
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = s.split_once(':')
+            .and_then(|(column, new_type)| Some((column.split_once('.')?, new_type.trim())));
+        match parsed {
+            Some(((table, column), new_type)) if !table.is_empty() && !column.is_empty() && !new_type.is_empty() => Ok(Retype {
+                table: table.to_string(),
+                column: column.to_string(),
+                new_type: new_type.to_string(),
+            }),
+            _ => Err(format!("Expected table.column:type, got: {}", s)),
+        }
+    }
+}
+
+// The type --retype gives a column, if it names it
+fn retyped<'a>(retypes: &'a [Retype], table_name: &str, column_name: &str) -> Option<&'a str> {
+    retypes.iter()
+        .find(|retype| retype.table == table_name && retype.column == column_name)
+        .map(|retype| retype.new_type.as_str())
+}
+
+// Whether a column of `old_type` (as regtype names it; `old_formatted` as
+// format_type does, with its length or precision) keeps all its values as
+// `new_type`, which is `new_base` without its own. These are the conversions
+// --retype is usually asked for, which the column does on assignment; any
+// other may round, truncate or not load at all.
+fn keeps_values(old_type: &str, old_formatted: &str, new_base: &str, new_type: &str) -> bool {
+    (!new_type.contains('(') || new_type == old_formatted) && lossless_retype(old_type, new_base)
+}
+
+fn lossless_retype(from: &str, to: &str) -> bool {
+    let widened: &[&str] = match from {
+        "smallint" => &["integer", "bigint", "numeric", "real", "double precision"],
+        "integer" => &["bigint", "numeric", "double precision"],
+        "bigint" => &["numeric"],
+        "real" => &["double precision"],
+        "date" => &["timestamp without time zone", "timestamp with time zone"],
+        "timestamp without time zone" => &["timestamp with time zone"],
+        _ => &[],
+    };
+    from == to || to == "text" || to == "character varying" || widened.contains(&to)
+}
+
+// Which columns of each table make it into the table definitions and data
+struct ColumnFilter {
+    include: Vec<ColumnSelection>,
+    exclude: Vec<ColumnSelection>,
+}
+
+impl ColumnFilter {
+    fn keeps(&self, table_name: &str, column_name: &str) -> bool {
+        let column = column_name.to_string();
+        let included = self.include.iter()
+            .filter(|selection| selection.table == table_name)
+            .all(|selection| selection.columns.contains(&column));
+        let excluded = self.exclude.iter()
+            .any(|selection| selection.table == table_name && selection.columns.contains(&column));
+        included && !excluded
+    }
+    
+    fn selections(&self) -> impl Iterator<Item = &ColumnSelection> {
+        self.include.iter().chain(self.exclude.iter())
+    }
+}
+
+// Row selection applied to each table's data query: sampling and the
+// --where-all predicate
+struct RowFilter {
+    rows: Option<u64>,
+    random: bool,
+    predicate: Option<String>,
+    skip_missing: bool,
+    // The conditions of extension configuration tables, for --extension-data
+    extension_conditions: Vec<(String, String)>,
+}
+
+impl RowFilter {
+    // The WHERE clause for a table's rows, from --where-all's predicate if it
+    // applies and the condition of an extension configuration table
+    fn where_clause(&self, table_name: &str, predicate: Option<&str>) -> String {
+        let condition = self.extension_condition(table_name);
+        match (predicate, condition) {
+            (Some(expr), Some(condition)) => format!(" WHERE ({}) AND ({})", expr, condition),
+            (Some(expr), None) | (None, Some(expr)) => format!(" WHERE ({})", expr),
+            (None, None) => String::new(),
+        }
+    }
+    
+    fn extension_condition(&self, table_name: &str) -> Option<&str> {
+        self.extension_conditions.iter()
+            .find(|(table, _)| table == table_name)
+            .map(|(_, condition)| condition.as_str())
+    }
+}
+
+// Renders a single column value of a row as an SQL literal
+fn format_value(row: &Row, i: usize, quoting: StringQuoting) -> String {
+    // Booleans are decided by the column type so they are always unquoted,
+    // whatever representation the driver would otherwise fall back to
+    if *row.columns()[i].type_() == Type::BOOL {
+        return match row.try_get::<_, Option<bool>>(i) {
+            Ok(Some(true)) => "TRUE".to_string(),
+            Ok(Some(false)) => "FALSE".to_string(),
+            _ => "NULL".to_string(),
+        };
+    }
+    
+    // Handle different data types
+    if let Ok(Some(val)) = row.try_get::<_, Option<String>>(i) {
+        // String types
+        quote_string(&val, quoting)
+    } else if let Ok(Some(val)) = row.try_get::<_, Option<&str>>(i) {
+        // String types
+        quote_string(val, quoting)
+    } else if let Ok(val) = row.try_get::<_, i16>(i) {
+        // Small integer
+        val.to_string()
+    } else if let Ok(val) = row.try_get::<_, i32>(i) {
+        // Integer
+        val.to_string()
+    } else if let Ok(val) = row.try_get::<_, i64>(i) {
+        // Big integer
+        val.to_string()
+    } else if let Ok(val) = row.try_get::<_, f32>(i) {
+        // Single precision float; Debug switches to exponent notation for very
+        // large or small values rather than writing out every digit
+        float_literal(val as f64, format!("{:?}", val))
+    } else if let Ok(val) = row.try_get::<_, f64>(i) {
+        // Float
+        float_literal(val, format!("{:?}", val))
+    } else {
+        // NULL or other types
+        "NULL".to_string()
+    }
+}
+
+// Quotes a string value for an INSERT. Values without quotes, backslashes or
+// control characters are always written as plain '...' literals; the others
+// follow --string-quoting. Auto uses an E'...' string for values with control
+// characters, so line breaks stay on one line, and dollar quoting for values
+// with quotes.
+fn quote_string(value: &str, quoting: StringQuoting) -> String {
+    let has_quote = value.contains('\'');
+    let has_control = value.chars().any(char::is_control);
+    let quoting = match quoting {
+        StringQuoting::Auto if has_control => StringQuoting::Escape,
+        StringQuoting::Auto if has_quote => StringQuoting::Dollar,
+        StringQuoting::Dollar if has_quote => StringQuoting::Dollar,
+        StringQuoting::Escape if has_quote || has_control || value.contains('\\') => StringQuoting::Escape,
+        _ => StringQuoting::Standard,
+    };
+    
+    match quoting {
+        StringQuoting::Dollar => {
+            // The tag must not occur in the value, not even completed by the
+            // closing tag, or the literal would end early
+            let mut tag = "$pgdump$".to_string();
+            let mut n = 1;
+            while format!("{}{}", value, tag).find(&tag) != Some(value.len()) {
+                tag = format!("$pgdump{}$", n);
+                n += 1;
+            }
+            format!("{}{}{}", tag, value, tag)
+        }
+        StringQuoting::Escape => {
+            let mut literal = String::with_capacity(value.len() + 3);
+            literal.push_str("E'");
+            for c in value.chars() {
+                match c {
+                    '\\' => literal.push_str("\\\\"),
+                    '\'' => literal.push_str("''"),
+                    '\n' => literal.push_str("\\n"),
+                    '\r' => literal.push_str("\\r"),
+                    '\t' => literal.push_str("\\t"),
+                    c if c.is_ascii_control() => literal.push_str(&format!("\\x{:02X}", c as u32)),
+                    c => literal.push(c),
+                }
+            }
+            literal.push('\'');
+            literal
+        }
+        _ => quote_literal(value),
+    }
+}
+
+// NaN and the infinities have no numeric literal; PostgreSQL reads them from
+// the quoted words. Finite values keep their Rust rendering.
+fn float_literal(val: f64, finite: String) -> String {
+    if val.is_nan() {
+        "'NaN'".to_string()
+    } else if val.is_infinite() {
+        if val > 0.0 { "'Infinity'" } else { "'-Infinity'" }.to_string()
+    } else {
+        finite
+    }
+}
+
+// Renders a text value for a COPY row: NULL is the marker (\N unless
+// --null-as sets another) and the characters COPY treats specially are
+// backslash-escaped
+fn copy_field(value: Option<&str>, null_marker: &str) -> String {
+    let Some(value) = value else {
+        return null_marker.to_string();
+    };
+    
+    let mut field = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => field.push_str("\\\\"),
+            '\t' => field.push_str("\\t"),
+            '\n' => field.push_str("\\n"),
+            '\r' => field.push_str("\\r"),
+            _ => field.push(c),
+        }
+    }
+    
+    // COPY compares the marker with the field before undoing escapes, so a
+    // value that reads like the marker loads as itself once its first
+    // character is escaped; octal keeps letters like n from turning special
+    if field == null_marker
+        && let Some(first) = field.chars().next() {
+        let escaped = if first.is_ascii() { format!("\\{:03o}", first as u32) } else { format!("\\{}", first) };
+        field.replace_range(..first.len_utf8(), &escaped);
+    }
+    field
+}
+
+// A CSV field of COPY ... WITH (FORMAT csv, NULL ''): NULL is the empty
+// unquoted field and every value is quoted, so empty strings stay distinct.
+// None if an inner line of the value is just \., which ends the data even
+// inside quotes; the first and last lines carry a quote character.
+fn csv_field(value: Option<&str>) -> Option<String> {
+    let Some(value) = value else {
+        return Some(String::new());
+    };
+    let lines: Vec<&str> = value.split('\n').collect();
+    if lines.len() > 2 && lines[1..lines.len() - 1].iter().any(|line| line.trim_end_matches('\r') == "\\.") {
+        return None;
+    }
+    Some(format!("\"{}\"", value.replace('"', "\"\"")))
+}
+
+// Cuts a value over --max-field-size down to the limit. Only character
+// strings and bytea can be cut and still load; for any other type the result
+// is None and the value is kept whole.
+fn truncate_field(value: &str, type_oid: u32, limit: usize) -> Option<&str> {
+    match Type::from_oid(type_oid) {
+        Some(Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME) => {
+            let mut cut = limit;
+            while !value.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            Some(&value[..cut])
+        }
+        // Keep the \x prefix and whole bytes of the hex form
+        Some(Type::BYTEA) => {
+            let cut = 2 + limit.saturating_sub(2) / 2 * 2;
+            Some(&value[..cut.min(value.len())])
+        }
+        _ => None,
+    }
+}
+
+// Writes the literal of a value whose text form is ambiguous as SQL input:
+// money, read through numeric, gets a cast so it doesn't depend on the
+// loading session's lc_monetary, bit strings become B'...' literals, and
+// intervals are cast to interval. The cast doesn't fix how their text reads:
+// it is in the dump session's intervalstyle, which the SET at the top of the
+// dump carries over. Without it, sql_standard text such as '-1 2:03:04' is a
+// different value to a session in the postgres style.
+// Outside the postgres dialect the casts are written as CAST(... AS ...).
+fn typed_literal(type_oid: u32, literal: String, dialect: Dialect) -> String {
+    if literal == "NULL" {
+        return literal;
+    }
+    match Type::from_oid(type_oid) {
+        Some(Type::MONEY) => cast_literal(cast_literal(literal, "numeric", dialect), "money", dialect),
+        Some(Type::INTERVAL) => cast_literal(literal, "interval", dialect),
+        Some(Type::BIT | Type::VARBIT) => format!("B{}", literal),
+        _ => literal,
+    }
+}
+
+// A value cast to `type_name`, written with :: or, for other engines, CAST
+fn cast_literal(literal: String, type_name: &str, dialect: Dialect) -> String {
+    if literal == "NULL" {
+        return literal;
+    }
+    match dialect {
+        Dialect::Postgres => format!("{}::{}", literal, type_name),
+        _ => format!("CAST({} AS {})", literal, type_name),
+    }
+}
+
+// Types format_value reads in the driver's binary format; every other column
+// (numeric, dates, json, arrays, extension types like hstore or geometry) is
+// selected as ::text and quoted, since any type accepts its text form as input.
+// Numbers read natively are formatted by Rust, which always writes a `.`
+// decimal point and no grouping whatever the server's lc_numeric.
+fn has_native_format(type_oid: u32) -> bool {
+    matches!(
+        Type::from_oid(type_oid),
+        Some(Type::BOOL | Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME | Type::INT2 | Type::INT4 | Type::INT8 | Type::FLOAT4 | Type::FLOAT8)
+    )
+}
+
+// Indexes and constraints, as (table, name) pairs, that depend on a column the
+// filter leaves out. pg_depend records these per column, including expression
+// index columns and foreign keys of other tables referencing the column.
+async fn objects_on_left_out_columns(client: &Client, table_names: &[String], column_filter: &ColumnFilter) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut objects = Vec::new();
+    
+    for table_name in table_names {
+        let left_out: Vec<String> = fetch_columns(client, table_name).await?
+            .into_iter()
+            .filter(|column| !column_filter.keeps(table_name, &column.name))
+            .map(|column| column.name)
+            .collect();
+        if left_out.is_empty() {
+            continue;
+        }
+        
+        let dependents = client.query(
+            "SELECT DISTINCT
+                 COALESCE(it.relname, ct.relname),
+                 COALESCE(ic.relname, con.conname)
+             FROM pg_catalog.pg_depend d
+             JOIN pg_catalog.pg_attribute a ON a.attrelid = d.refobjid AND a.attnum = d.refobjsubid
+             JOIN pg_catalog.pg_class t ON t.oid = a.attrelid
+             LEFT JOIN pg_catalog.pg_class ic ON d.classid = 'pg_catalog.pg_class'::regclass AND ic.oid = d.objid
+             LEFT JOIN pg_catalog.pg_index i ON i.indexrelid = ic.oid
+             LEFT JOIN pg_catalog.pg_class it ON it.oid = i.indrelid
+             LEFT JOIN pg_catalog.pg_constraint con ON d.classid = 'pg_catalog.pg_constraint'::regclass AND con.oid = d.objid
+             LEFT JOIN pg_catalog.pg_class ct ON ct.oid = con.conrelid
+             WHERE d.refclassid = 'pg_catalog.pg_class'::regclass
+             AND t.relname = $1
+             AND t.relnamespace = 'public'::regnamespace
+             AND a.attname = ANY($2)
+             AND (i.indexrelid IS NOT NULL OR con.oid IS NOT NULL)",
+            &[table_name, &left_out],
+        ).await?;
+        
+        for row in dependents {
+            let object = (row.get::<_, String>(0), row.get::<_, String>(1));
+            if table_names.contains(&object.0) && !objects.contains(&object) {
+                objects.push(object);
+            }
+        }
+    }
+    
+    Ok(objects)
+}
+
+// Adds the foreign keys that the schema left out because they lie on a cycle
+// of references. Rows on both sides of the cycle are loaded by now, so the
+// constraints are checked against the complete data.
+async fn dump_cyclic_foreign_keys_to(client: &Client, target: &mut DumpTarget<'_>, table_names: &[String], column_filter: &ColumnFilter) -> Result<(), Box<dyn Error>> {
+    let on_left_out_columns = objects_on_left_out_columns(client, table_names, column_filter).await?;
+    let cyclic = cyclic_edges(&list_foreign_key_edges(client).await?);
+    
+    target.write_line("-- Foreign keys on a reference cycle, added after the data")?;
+    for table_name in table_names {
+        for constraint in fetch_constraints(client, table_name, &["f"]).await? {
+            if on_left_out_columns.contains(&(table_name.clone(), constraint.name.clone())) {
+                continue;
+            }
+            let Some(referenced) = &constraint.references else { continue };
+            if !cyclic.contains(&(table_name.clone(), referenced.clone())) {
+                continue;
+            }
+            target.write_line(&format!("ALTER TABLE public.{} ADD CONSTRAINT {} {};", table_name, constraint.name, constraint.definition))?;
+            if let Some(comment) = constraint.comment_statement(table_name) {
+                target.write_line(&comment)?;
+            }
+        }
+    }
+    
+    target.write_line("")?;
+    Ok(())
+}
+
+// Adds the check and foreign key constraints after the data as NOT VALID, which
+// skips scanning the loaded rows, or validates them afterwards. Constraints that
+// were already NOT VALID in the source are left unvalidated.
+async fn dump_deferred_constraints_to(client: &Client, target: &mut DumpTarget<'_>, table_names: &[String], column_filter: &ColumnFilter, validate: bool) -> Result<(), Box<dyn Error>> {
+    let on_left_out_columns = objects_on_left_out_columns(client, table_names, column_filter).await?;
+    
+    if validate {
+        target.write_line("-- Validate constraints added as NOT VALID")?;
+    } else {
+        target.write_line("-- Check and foreign key constraints, not validated against the loaded data")?;
+    }
+    
+    for table_name in table_names {
+        for constraint in fetch_constraints(client, table_name, &["c", "f"]).await? {
+            if on_left_out_columns.contains(&(table_name.clone(), constraint.name.clone())) {
+                continue;
+            }
+            
+            let valid_in_source = !constraint.definition.ends_with(" NOT VALID");
+            if validate && valid_in_source {
+                target.write_line(&format!("ALTER TABLE public.{} VALIDATE CONSTRAINT {};", table_name, constraint.name))?;
+            } else if !validate {
+                let not_valid = if valid_in_source { " NOT VALID" } else { "" };
+                target.write_line(&format!("ALTER TABLE public.{} ADD CONSTRAINT {} {}{};", table_name, constraint.name, constraint.definition, not_valid))?;
+                if let Some(comment) = constraint.comment_statement(table_name) {
+                    target.write_line(&comment)?;
+                }
+            }
+        }
+    }
+    
+    target.write_line("")?;
+    Ok(())
+}
+
+// Foreign keys between tables in the public schema, as (referencing, referenced) pairs
+async fn list_foreign_key_edges(client: &Client) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let edges = client.query(
+        "SELECT DISTINCT c.relname, p.relname
+         FROM pg_catalog.pg_constraint con
+         JOIN pg_catalog.pg_class c ON c.oid = con.conrelid
+         JOIN pg_catalog.pg_namespace cn ON cn.oid = c.relnamespace
+         JOIN pg_catalog.pg_class p ON p.oid = con.confrelid
+         JOIN pg_catalog.pg_namespace pn ON pn.oid = p.relnamespace
+         WHERE con.contype = 'f'
+         AND cn.nspname = 'public'
+         AND pn.nspname = 'public'",
+        &[],
+    ).await?;
+    
+    Ok(edges.iter()
+        .map(|row| (row.get::<_, String>(0), row.get::<_, String>(1)))
+        .collect())
+}
+
+// The tables referenced by `selected`, directly or through other referenced tables
+fn referenced_tables(selected: &[String], edges: &[(String, String)]) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    let mut pending: Vec<&str> = selected.iter().map(String::as_str).collect();
+    
+    while let Some(table) = pending.pop() {
+        for (child, parent) in edges {
+            if child == table && !selected.contains(parent) && !found.contains(parent) {
+                found.push(parent.clone());
+                pending.push(parent);
+            }
+        }
+    }
+    
+    found
+}
+
+// Foreign key edges that lie on a cycle: the referenced table leads back to the
+// referencing one, or is the same table. No load order satisfies these, so their
+// constraints are added after the data.
+fn cyclic_edges(edges: &[(String, String)]) -> Vec<(String, String)> {
+    edges.iter()
+        .filter(|(child, parent)| child == parent || referenced_tables(std::slice::from_ref(parent), edges).contains(child))
+        .cloned()
+        .collect()
+}
+
+// Groups tables into load levels: each table comes after the levels holding
+// the tables it references, and tables within a level don't reference each
+// other, keeping their given order. Edges in a cycle are ignored, which
+// leaves the rest of the graph acyclic.
+fn load_levels(tables: &[String], edges: &[(String, String)]) -> Vec<Vec<String>> {
+    let cyclic = cyclic_edges(edges);
+    let mut pending: Vec<String> = tables.to_vec();
+    let mut levels = Vec::new();
+    
+    while !pending.is_empty() {
+        let (ready, waiting): (Vec<String>, Vec<String>) = pending.iter().cloned()
+            .partition(|table| !edges.iter().any(|edge| {
+                &edge.0 == table && &edge.1 != table && pending.contains(&edge.1) && !cyclic.contains(edge)
+            }));
+        levels.push(ready);
+        pending = waiting;
+    }
+    
+    levels
+}
+
+async fn list_tables(client: &Client) -> Result<Vec<String>, Box<dyn Error>> {
+    let tables = client.query(
+        "SELECT c.relname
          FROM pg_catalog.pg_class c
-         JOIN pg_catalog.pg_roles r ON c.relowner = r.oid
-         JOIN pg_catalog.pg_namespace n ON c.relnamespace = n.oid
-         WHERE c.relkind IN ('r', 'S', 'v')
-         AND n.nspname NOT IN ('pg_catalog', 'information_schema')
-         AND n.nspname NOT LIKE 'pg_%'",
+         JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+         WHERE c.relkind = 'r' 
+         AND n.nspname = 'public'
+         ORDER BY c.relname",
+        &[],
+    ).await?;
+    
+    Ok(tables.iter()
+        .map(|row| row.get::<_, String>(0))
+        .collect())
+}
+
+// A condition that leaves out objects created by an extension, with
+// --exclude-extension-owned-objects: `catalog` is the system catalog of the
+// object and `oid` its oid column in the query
+fn not_extension_member(opt: &Opt, catalog: &str, oid: &str) -> String {
+    if !opt.exclude_extension_owned_objects {
+        return String::new();
+    }
+    format!(
+        "AND NOT EXISTS (
+             SELECT 1 FROM pg_catalog.pg_depend ext
+             WHERE ext.classid = 'pg_catalog.{}'::regclass
+             AND ext.objid = {}
+             AND ext.deptype = 'e'
+         )",
+        catalog, oid
+    )
+}
+
+// A table in the public schema that an extension created. Tables the
+// extension marked with pg_extension_config_dump hold user rows as well, and
+// come with the condition that picks those rows ('' for all of them).
+struct ExtensionTable {
+    name: String,
+    condition: Option<String>,
+}
+
+async fn list_extension_tables(client: &Client) -> Result<Vec<ExtensionTable>, Box<dyn Error>> {
+    let tables = client.query(
+        "SELECT c.relname::text, x.extcondition[pg_catalog.array_position(x.extconfig, c.oid)]
+         FROM pg_catalog.pg_depend d
+         JOIN pg_catalog.pg_class c ON c.oid = d.objid
+         JOIN pg_catalog.pg_extension x ON x.oid = d.refobjid
+         WHERE d.classid = 'pg_catalog.pg_class'::regclass
+         AND d.refclassid = 'pg_catalog.pg_extension'::regclass
+         AND d.deptype = 'e'
+         AND c.relkind = 'r'
+         AND c.relnamespace = 'public'::regnamespace
+         ORDER BY 1",
+        &[],
+    ).await?;
+    Ok(tables.iter()
+        .map(|row| ExtensionTable { name: row.get(0), condition: row.get(1) })
+        .collect())
+}
+
+// CREATE EXTENSION IF NOT EXISTS for every extension but plpgsql, in the
+// schema it was created in, for --exclude-extension-owned-objects
+async fn dump_extensions_to(client: &Client, target: &mut DumpTarget<'_>) -> Result<(), Box<dyn Error>> {
+    let extensions = client.query(
+        "SELECT pg_catalog.quote_ident(x.extname), pg_catalog.quote_ident(n.nspname)
+         FROM pg_catalog.pg_extension x
+         JOIN pg_catalog.pg_namespace n ON n.oid = x.extnamespace
+         WHERE x.extname <> 'plpgsql'
+         ORDER BY x.extname",
+        &[],
+    ).await?;
+    
+    target.write_line("-- Extensions")?;
+    for extension in extensions {
+        let name: String = extension.get(0);
+        let schema: String = extension.get(1);
+        if schema != "public" {
+            target.write_line(&format!("CREATE SCHEMA IF NOT EXISTS {};", schema))?;
+        }
+        target.write_line(&format!("CREATE EXTENSION IF NOT EXISTS {} WITH SCHEMA {};", name, schema))?;
+    }
+    target.write_line("")?;
+    Ok(())
+}
+
+struct ColumnInfo {
+    name: String,
+    data_type: String,
+    not_null: bool,
+    default: Option<String>,
+    // Set when the column's collation differs from its type's default
+    collation: Option<String>,
+    // GENERATED ... AS IDENTITY with the options of the identity sequence
+    identity: Option<String>,
+    // The identity sequence's last value, once it has handed out one
+    identity_last_value: Option<i64>,
+    // A statistics target set with ALTER COLUMN ... SET STATISTICS
+    statistics: Option<i32>,
+}
+
+impl ColumnInfo {
+    // Column definition as used in CREATE TABLE, with the name padded to `width`
+    fn definition(&self, width: usize) -> String {
+        let mut col_def = format!("{:width$} {}", self.name, self.data_type, width = width);
+        
+        if let Some(collation) = &self.collation {
+            col_def.push_str(&format!(" COLLATE {}", collation));
+        }
+        
+        if self.not_null {
+            col_def.push_str(" NOT NULL");
+        }
+        
+        if let Some(def) = &self.default {
+            col_def.push_str(&format!(" DEFAULT {}", def));
+        }
+        
+        if let Some(identity) = &self.identity {
+            col_def.push_str(&format!(" {}", identity));
+        }
+        
+        col_def
+    }
+    
+    // Moves the identity sequence to where the source's is, as rows loaded
+    // with explicit values don't advance it. setval keeps is_called, so a
+    // sequence at its limit cycles or runs out just as the source's would.
+    fn identity_setval(&self, table_name: &str) -> Option<String> {
+        self.identity_last_value.map(|last_value| format!(
+            "SELECT pg_catalog.setval(pg_catalog.pg_get_serial_sequence({}, {}), {}, true);",
+            quote_literal(table_name), quote_literal(&self.name), last_value
+        ))
+    }
+}
+
+struct IndexInfo {
+    name: String,
+    definition: String,
+    comment: Option<String>,
+}
+
+impl IndexInfo {
+    fn comment_statement(&self) -> Option<String> {
+        self.comment.as_ref()
+            .map(|comment| format!("COMMENT ON INDEX {} IS {};", self.name, quote_literal(comment)))
+    }
+}
+
+struct ConstraintInfo {
+    name: String,
+    kind: String,
+    definition: String,
+    comment: Option<String>,
+    // The public table a foreign key references
+    references: Option<String>,
+}
+
+impl ConstraintInfo {
+    fn comment_statement(&self, table_name: &str) -> Option<String> {
+        self.comment.as_ref()
+            .map(|comment| format!("COMMENT ON CONSTRAINT {} ON public.{} IS {};", self.name, table_name, quote_literal(comment)))
+    }
+}
+
+async fn fetch_columns(client: &Client, table_name: &str) -> Result<Vec<ColumnInfo>, Box<dyn Error>> {
+    let columns = client.query(
+        "SELECT 
+            a.attname as column_name,
+            pg_catalog.format_type(a.atttypid, a.atttypmod) as data_type,
+            a.attnotnull as not_null,
+            pg_catalog.pg_get_expr(d.adbin, d.adrelid) as column_default,
+            CASE WHEN a.attcollation <> t.typcollation THEN
+                CASE WHEN cn.nspname IN ('public', 'pg_catalog') THEN pg_catalog.quote_ident(co.collname)
+                ELSE pg_catalog.quote_ident(cn.nspname) || '.' || pg_catalog.quote_ident(co.collname) END
+            END as collation,
+            a.attidentity::text,
+            s.seqstart, s.seqincrement, s.seqmin, s.seqmax, s.seqcache, s.seqcycle,
+            pg_catalog.pg_sequence_last_value(s.seqrelid),
+            NULLIF(a.attstattarget, -1)::int4
+         FROM pg_catalog.pg_attribute a
+         LEFT JOIN pg_catalog.pg_attrdef d ON (d.adrelid = a.attrelid AND d.adnum = a.attnum)
+         JOIN pg_catalog.pg_class c ON c.oid = a.attrelid
+         JOIN pg_catalog.pg_type t ON t.oid = a.atttypid
+         LEFT JOIN pg_catalog.pg_collation co ON co.oid = a.attcollation
+         LEFT JOIN pg_catalog.pg_namespace cn ON cn.oid = co.collnamespace
+         LEFT JOIN pg_catalog.pg_depend dep ON dep.refclassid = 'pg_catalog.pg_class'::regclass
+             AND dep.refobjid = a.attrelid
+             AND dep.refobjsubid = a.attnum
+             AND dep.classid = 'pg_catalog.pg_class'::regclass
+             AND dep.deptype = 'i'
+         LEFT JOIN pg_catalog.pg_sequence s ON s.seqrelid = dep.objid
+         WHERE c.relname = $1
+         AND c.relnamespace = 'public'::regnamespace
+         AND a.attnum > 0
+         AND NOT a.attisdropped
+         ORDER BY a.attnum",
+        &[&table_name],
+    ).await?;
+    
+    Ok(columns.iter()
+        .map(|column| {
+            let generated = match column.get::<_, &str>(5) {
+                "a" => Some("ALWAYS"),
+                "d" => Some("BY DEFAULT"),
+                _ => None,
+            };
+            // The sequence options are spelled out so a non-default start,
+            // step or range survives the reload
+            let (identity, identity_last_value) = match (generated, column.get::<_, Option<i64>>(6)) {
+                (Some(generated), Some(start)) => {
+                    let identity = format!(
+                        "GENERATED {} AS IDENTITY (START WITH {} INCREMENT BY {} MINVALUE {} MAXVALUE {} CACHE {}{})",
+                        generated, start, column.get::<_, i64>(7), column.get::<_, i64>(8), column.get::<_, i64>(9),
+                        column.get::<_, i64>(10), if column.get(11) { " CYCLE" } else { "" }
+                    );
+                    (Some(identity), column.get(12))
+                }
+                (Some(generated), None) => (Some(format!("GENERATED {} AS IDENTITY", generated)), None),
+                _ => (None, None),
+            };
+            ColumnInfo {
+                name: column.get(0),
+                data_type: column.get(1),
+                not_null: column.get(2),
+                default: column.get(3),
+                collation: column.get(4),
+                identity,
+                identity_last_value,
+                statistics: column.get(13),
+            }
+        })
+        .collect())
+}
+
+// Indexes of a table that stand on their own. Indexes backing a primary key,
+// unique or exclusion constraint are created by the constraint itself, so they
+// are recognised through pg_constraint.conindid rather than by name.
+async fn fetch_indexes(client: &Client, table_name: &str) -> Result<Vec<IndexInfo>, Box<dyn Error>> {
+    let indexes = client.query(
+        "SELECT ic.relname, pg_catalog.pg_get_indexdef(i.indexrelid), pg_catalog.obj_description(i.indexrelid, 'pg_class')
+         FROM pg_catalog.pg_index i
+         JOIN pg_catalog.pg_class ic ON ic.oid = i.indexrelid
+         JOIN pg_catalog.pg_class c ON c.oid = i.indrelid
+         JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+         WHERE c.relname = $1
+         AND n.nspname = 'public'
+         AND NOT EXISTS (
+             SELECT 1
+             FROM pg_catalog.pg_constraint con
+             WHERE con.conindid = i.indexrelid
+             AND con.conrelid = i.indrelid
+             AND con.contype IN ('p', 'u', 'x')
+         )
+         ORDER BY ic.relname",
+        &[&table_name],
+    ).await?;
+    
+    Ok(indexes.iter()
+        .map(|index| IndexInfo {
+            name: index.get(0),
+            definition: index.get(1),
+            comment: index.get(2),
+        })
+        .collect())
+}
+
+// A replica identity other than the default, the primary key
+enum ReplicaIdentity {
+    Full,
+    Nothing,
+    // The index by name, and quoted for the statement
+    Index(String, String),
+}
+
+impl ReplicaIdentity {
+    fn clause(&self) -> String {
+        match self {
+            ReplicaIdentity::Full => "FULL".to_string(),
+            ReplicaIdentity::Nothing => "NOTHING".to_string(),
+            ReplicaIdentity::Index(_, quoted) => format!("USING INDEX {}", quoted),
+        }
+    }
+}
+
+async fn fetch_replica_identity(client: &Client, table_name: &str) -> Result<Option<ReplicaIdentity>, Box<dyn Error>> {
+    let row = client.query_one(
+        "SELECT c.relreplident::text, ic.relname::text, pg_catalog.quote_ident(ic.relname)
+         FROM pg_catalog.pg_class c
+         LEFT JOIN pg_catalog.pg_index i ON i.indrelid = c.oid AND i.indisreplident
+         LEFT JOIN pg_catalog.pg_class ic ON ic.oid = i.indexrelid
+         WHERE c.relname = $1
+         AND c.relnamespace = 'public'::regnamespace",
+        &[&table_name],
+    ).await?;
+    
+    Ok(match (row.get::<_, &str>(0), row.get::<_, Option<String>>(1), row.get::<_, Option<String>>(2)) {
+        ("f", _, _) => Some(ReplicaIdentity::Full),
+        ("n", _, _) => Some(ReplicaIdentity::Nothing),
+        ("i", Some(index), Some(quoted)) => Some(ReplicaIdentity::Index(index, quoted)),
+        _ => None,
+    })
+}
+
+// Constraints of a table whose contype is one of `kinds` ('p', 'u', 'f', 'c', ...)
+async fn fetch_constraints(client: &Client, table_name: &str, kinds: &[&str]) -> Result<Vec<ConstraintInfo>, Box<dyn Error>> {
+    let constraints = client.query(
+        "SELECT
+             conname,
+             contype::text,
+             pg_catalog.pg_get_constraintdef(oid),
+             pg_catalog.obj_description(oid, 'pg_constraint'),
+             (SELECT c.relname FROM pg_catalog.pg_class c
+              WHERE c.oid = confrelid AND c.relnamespace = 'public'::regnamespace)
+         FROM pg_catalog.pg_constraint
+         WHERE conrelid = (
+             SELECT oid FROM pg_catalog.pg_class WHERE relname = $1
+             AND relnamespace = (SELECT oid FROM pg_catalog.pg_namespace WHERE nspname = 'public')
+         )
+         AND contype::text = ANY($2)
+         ORDER BY contype DESC, conname",
+        &[&table_name, &kinds],
+    ).await?;
+    
+    Ok(constraints.iter()
+        .map(|constraint| ConstraintInfo {
+            name: constraint.get(0),
+            kind: constraint.get(1),
+            definition: constraint.get(2),
+            comment: constraint.get(3),
+            references: constraint.get(4),
+        })
+        .collect())
+}
+
+// Settings of the dump's own session that decide how its values and
+// expressions read back: the text form of dates, intervals and money (in
+// COPY data), and the configuration of one-argument text search functions
+const SESSION_SETTINGS: &[&str] = &["timezone", "datestyle", "intervalstyle", "lc_monetary", "default_text_search_config"];
+
+// SET statements that make the loading session match the dump. The fixed ones
+// follow from how the dump is written (UTF-8, standard strings, unqualified
+// names in public); the others are copied from the source.
+async fn dump_settings_to(client: &Client, target: &mut DumpTarget<'_>) -> Result<(), Box<dyn Error>> {
+    // The database locale can't be SET; it is recorded for reference and
+    // reproduced by --create
+    let locale = client.query_one(
+        "SELECT datcollate, datctype FROM pg_catalog.pg_database WHERE datname = current_database()",
+        &[],
+    ).await?;
+    target.write_line(&format!(
+        "-- Source locale: LC_COLLATE {}, LC_CTYPE {}",
+        locale.get::<_, String>(0), locale.get::<_, String>(1)
+    ))?;
+    
+    target.write_line("SET client_encoding = 'UTF8';")?;
+    target.write_line("SET standard_conforming_strings = on;")?;
+    target.write_line("SET check_function_bodies = false;")?;
+    target.write_line("SET client_min_messages = warning;")?;
+    target.write_line(&format!("SET search_path = {};", DUMP_SEARCH_PATH))?;
+    for name in SESSION_SETTINGS {
+        let value: String = client.query_one("SELECT pg_catalog.current_setting($1)", &[name]).await?.get(0);
+        target.write_line(&format!("SET {} = {};", name, quote_literal(&value)))?;
+    }
+    Ok(())
+}
+
+async fn dump_schema_to(client: &Client, target: &mut DumpTarget<'_>, table_names: &[String], column_filter: &ColumnFilter, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    target.write_line("-- Database schema definition (sequences, types, tables, constraints)  ")?;
+    if opt.writes_set() {
+        dump_settings_to(client, target).await?;
+    }
+    target.write_line("")?;
+    
+    dump_public_schema_to(client, target, &opt.map_role).await?;
+    
+    // The extensions bring their own types, tables and functions
+    if opt.exclude_extension_owned_objects {
+        let started = Instant::now();
+        dump_extensions_to(client, target).await?;
+        target.stats.record("extensions", started);
+    }
+    
+    // Get and dump custom types first
+    let started = Instant::now();
+    target.write_line("-- Custom Types")?;
+    
+    let enum_types = client.query(
+        &format!(
+            "SELECT t.typname 
+             FROM pg_catalog.pg_type t 
+             JOIN pg_catalog.pg_namespace n ON n.oid = t.typnamespace
+             WHERE t.typtype = 'e' 
+             AND n.nspname = 'public'
+             {}
+             ORDER BY t.typname",
+            not_extension_member(opt, "pg_type", "t.oid")
+        ),
         &[],
     ).await?;
     
-    let mut role_names = Vec::new();
+    for type_row in enum_types {
+        let type_name: String = type_row.get(0);
+        
+        // Get enum labels
+        let enum_values = client.query(
+            "SELECT e.enumlabel
+             FROM pg_catalog.pg_enum e
+             JOIN pg_catalog.pg_type t ON e.enumtypid = t.oid
+             WHERE t.typname = $1
+             ORDER BY e.enumsortorder",
+            &[&type_name],
+        ).await?;
+        
+        if enum_values.is_empty() {
+            continue;
+        }
+        
+        let values: Vec<String> = enum_values.iter()
+            .filter_map(|row| row.try_get::<_, String>(0).ok())
+            .map(|val| format!("'{}'", val.replace("'", "''")))
+            .collect();
+            
+        if !values.is_empty() {
+            target.write_line(&format!("CREATE TYPE {} AS ENUM ({});", 
+                type_name, values.join(", ")))?;
+        }
+    }
+    
+    target.write_line("")?;
+    target.stats.record("types", started);
+    
+    // Collations and text search objects are referenced by column definitions,
+    // indexes and generated tsvector columns, so they precede the tables
+    let started = Instant::now();
+    dump_collations_to(client, target, opt).await?;
+    target.stats.record("collations", started);
+    
+    let started = Instant::now();
+    dump_text_search_to(client, target, opt).await?;
+    target.stats.record("text search", started);
+    
+    // Get and dump sequences
+    let started = Instant::now();
+    target.write_line("-- Sequences")?;
+    
+    let sequences = client.query(
+        &format!(
+            "SELECT c.relname, owner.relname
+             FROM pg_catalog.pg_class c
+             JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+             LEFT JOIN pg_catalog.pg_depend od ON od.classid = 'pg_catalog.pg_class'::regclass
+                 AND od.objid = c.oid
+                 AND od.refclassid = 'pg_catalog.pg_class'::regclass
+                 AND od.deptype = 'a'
+             LEFT JOIN pg_catalog.pg_class owner ON owner.oid = od.refobjid
+             WHERE c.relkind = 'S'
+             AND n.nspname = 'public'
+             AND NOT EXISTS (
+                 SELECT 1 FROM pg_catalog.pg_depend d
+                 WHERE d.classid = 'pg_catalog.pg_class'::regclass
+                 AND d.objid = c.oid
+                 AND d.deptype = 'i'
+             )
+             {}
+             ORDER BY c.relname",
+            not_extension_member(opt, "pg_class", "c.oid")
+        ),
+        &[],
+    ).await?;
+    
+    for seq_row in sequences {
+        let seq_name: String = seq_row.get(0);
+        if !opt.keeps_sequence(&seq_name) {
+            continue;
+        }
+        // The target already has the tables --data-only-for picks, and the
+        // sequences they own with them
+        if seq_row.get::<_, Option<&str>>(1).is_some_and(|owner| opt.data_only_for(owner)) {
+            continue;
+        }
+        target.write_line(&format!("CREATE SEQUENCE {};", seq_name))?;
+    }
+    
+    target.write_line("")?;
+    target.stats.record("sequences", started);
+    
+    // Indexes and constraints can't be recreated without the columns they use
+    let on_left_out_columns = objects_on_left_out_columns(client, table_names, column_filter).await?;
+    for (table_name, object_name) in &on_left_out_columns {
+        eprintln!("Warning: skipping {} on table {}, it uses columns left out of the dump", object_name, table_name);
+    }
+    
+    // Create tables
+    let started = Instant::now();
+    target.write_line("-- Tables")?;
+    
+    for table_name in table_names {
+        target.write_line(&format!("-- Table: {}", table_name))?;
+        
+        let mut columns = fetch_columns(client, table_name).await?;
+        columns.retain(|column| column_filter.keeps(table_name, &column.name));
+        
+        // A retyped column keeps its collation only if the new type takes one
+        for column in &mut columns {
+            if let Some(new_type) = retyped(&opt.retype, table_name, &column.name) {
+                column.data_type = new_type.to_string();
+                if column.collation.is_some() {
+                    let collatable: bool = client.query_one(
+                        "SELECT t.typcollation <> 0 FROM pg_catalog.pg_type t WHERE t.oid = pg_catalog.to_regtype($1)",
+                        &[&new_type],
+                    ).await?.get(0);
+                    if !collatable {
+                        column.collation = None;
+                    }
+                }
+            }
+        }
+        
+        // Pad column names so the types line up in pretty output; the width
+        // counts characters, as the padding does, so names like naïve line up
+        let name_width = if target.style == FormatStyle::Pretty {
+            columns.iter().map(|c| c.name.chars().count()).max().unwrap_or(0)
+        } else {
+            0
+        };
+        
+        let mut column_defs: Vec<String> = columns.iter()
+            .map(|column| column.definition(name_width))
+            .collect();
+        
+        // Get primary key. pg_get_constraintdef keeps the key's column order,
+        // INCLUDE columns and deferrability; a non-default name is kept as well.
+        let pk = fetch_constraints(client, table_name, &["p"]).await?.into_iter().next()
+            .filter(|pk| !on_left_out_columns.contains(&(table_name.clone(), pk.name.clone())));
+        if let Some(pk) = &pk {
+            if pk.name == format!("{}_pkey", table_name) {
+                column_defs.push(pk.definition.clone());
+            } else {
+                column_defs.push(format!("CONSTRAINT {} {}", pk.name, pk.definition));
+            }
+        }
+        
+        if opt.annotate_sizes {
+            target.write_line(&table_size_comment(client, table_name).await?)?;
+        }
+        target.write_block(&format!("CREATE TABLE {} (", table_name), &column_defs, ");")?;
+        if let Some(comment) = pk.and_then(|pk| pk.comment_statement(table_name)) {
+            target.write_line(&comment)?;
+        }
+        // setval is PostgreSQL's own
+        if opt.dialect == Dialect::Postgres {
+            for setval in columns.iter().filter_map(|column| column.identity_setval(table_name)) {
+                target.write_line(&setval)?;
+            }
+        }
+        // Statistics targets tuned per column; the default (-1, NULL since
+        // PostgreSQL 17) follows default_statistics_target
+        for column in &columns {
+            if let Some(statistics) = column.statistics {
+                target.write_line(&format!("ALTER TABLE {} ALTER COLUMN {} SET STATISTICS {};", table_name, column.name, statistics))?;
+            }
+        }
+        target.write_line("")?;
+    }
+    target.stats.record("table definitions", started);
+    
+    // --table selects regular tables only
+    if opt.selects_all_tables() {
+        let started = Instant::now();
+        dump_foreign_tables_to(client, target, column_filter, opt).await?;
+        target.stats.record("foreign tables", started);
+    }
+    dump_skipped_relations_to(client, target).await?;
+    
+    let started = Instant::now();
+    dump_sequence_owners_to(client, target, table_names, column_filter, opt).await?;
+    target.stats.record("sequence ownership", started);
+    
+    if opt.writes_owners() {
+        let started = Instant::now();
+        dump_object_owners_to(client, target, table_names, opt).await?;
+        target.stats.record("object owners", started);
+    }
+    
+    // Add indices and constraints
+    let started = Instant::now();
+    target.write_line("-- Indexes and constraints")?;
+    
+    for table_name in table_names {
+        // Add indexes, skipping those that back a constraint (emitted below)
+        for index in fetch_indexes(client, table_name).await? {
+            if on_left_out_columns.contains(&(table_name.clone(), index.name.clone())) || !opt.object_filter.keeps_index(&index.name) {
+                continue;
+            }
+            target.write_line(&format!("{};", index.definition))?;
+            if let Some(comment) = index.comment_statement() {
+                target.write_line(&comment)?;
+            }
+            target.write_line("")?;
+        }
+        
+        // Add unique and exclusion constraints. pg_get_constraintdef already renders
+        // DEFERRABLE / INITIALLY DEFERRED, so deferral survives the reload, and
+        // schema-qualifies referenced tables outside the dump's search_path.
+        for constraint in fetch_constraints(client, table_name, &["u", "x"]).await? {
+            if on_left_out_columns.contains(&(table_name.clone(), constraint.name.clone())) {
+                continue;
+            }
+            target.write_line(&format!("ALTER TABLE public.{} ADD CONSTRAINT {} {};", table_name, constraint.name, constraint.definition))?;
+            if let Some(comment) = constraint.comment_statement(table_name) {
+                target.write_line(&comment)?;
+            }
+        }
+        
+        // The replica identity comes once the index it may name exists; one
+        // naming an index left out of the dump is left at the default
+        match fetch_replica_identity(client, table_name).await? {
+            Some(ReplicaIdentity::Index(index, _))
+                if on_left_out_columns.contains(&(table_name.clone(), index.clone())) || !opt.object_filter.keeps_index(&index) => {}
+            Some(identity) => target.write_line(&format!("ALTER TABLE {} REPLICA IDENTITY {};", table_name, identity.clause()))?,
+            None => {}
+        }
+    }
+    
+    // Check and foreign key constraints follow once every table has its keys, since
+    // a foreign key needs the unique constraint it references. With
+    // --constraints-not-valid they all come after the data; otherwise only the
+    // foreign keys on a cycle do.
+    if !opt.constraints_not_valid {
+        let cyclic = cyclic_edges(&list_foreign_key_edges(client).await?);
+        for table_name in table_names {
+            for constraint in fetch_constraints(client, table_name, &["c", "f"]).await? {
+                if on_left_out_columns.contains(&(table_name.clone(), constraint.name.clone())) {
+                    continue;
+                }
+                if let Some(referenced) = &constraint.references
+                    && cyclic.contains(&(table_name.clone(), referenced.clone())) {
+                    continue;
+                }
+                target.write_line(&format!("ALTER TABLE public.{} ADD CONSTRAINT {} {};", table_name, constraint.name, constraint.definition))?;
+                if let Some(comment) = constraint.comment_statement(table_name) {
+                    target.write_line(&comment)?;
+                }
+            }
+        }
+    }
+    
+    target.write_line("")?;
+    target.stats.record("indexes and constraints", started);
+    
+    let started = Instant::now();
+    dump_table_privileges_to(client, target, table_names, &opt.map_role, opt.include_acl_for_public).await?;
+    target.stats.record("table privileges", started);
+    
+    if !opt.exclude_column_privileges {
+        let started = Instant::now();
+        dump_column_privileges_to(client, target, table_names, &opt.map_role, column_filter, opt.include_acl_for_public).await?;
+        target.stats.record("column privileges", started);
+    }
+    
+    Ok(())
+}
+
+// A comment with the table's size on disk (indexes and TOAST included, then
+// the table alone), the planner's row estimate and its number of indexes.
+// The estimate is as fresh as the last VACUUM or ANALYZE.
+async fn table_size_comment(client: &Client, table_name: &str) -> Result<String, Box<dyn Error>> {
+    let row = client.query_one(
+        "SELECT pg_catalog.pg_total_relation_size(c.oid),
+                pg_catalog.pg_relation_size(c.oid),
+                c.reltuples::float8,
+                (SELECT count(*) FROM pg_catalog.pg_index i WHERE i.indrelid = c.oid)
+         FROM pg_catalog.pg_class c
+         WHERE c.relname = $1
+         AND c.relnamespace = 'public'::regnamespace",
+        &[&table_name],
+    ).await?;
+    
+    let total: i64 = row.get(0);
+    let data: i64 = row.get(1);
+    let tuples: f64 = row.get(2);
+    let indexes: i64 = row.get(3);
+    
+    // reltuples is -1 until the table is first vacuumed or analyzed
+    let rows = if tuples < 0.0 {
+        "row count unknown".to_string()
+    } else {
+        format!("~{} rows", human_count(tuples))
+    };
+    Ok(format!(
+        "-- table {}: {} ({} without indexes and TOAST), {}, {} index{}",
+        table_name, human_size(total), human_size(data), rows, indexes, if indexes == 1 { "" } else { "es" }
+    ))
+}
+
+fn human_size(bytes: i64) -> String {
+    const UNITS: &[&str] = &["kB", "MB", "GB", "TB", "PB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn human_count(count: f64) -> String {
+    if count < 1000.0 {
+        return format!("{}", count.round());
+    }
+    let (value, suffix) = if count < 1e6 {
+        (count / 1e3, "K")
+    } else if count < 1e9 {
+        (count / 1e6, "M")
+    } else {
+        (count / 1e9, "B")
+    };
+    format!("{:.1}{}", value, suffix)
+}
+
+// Where the identity and serial sequences of a --data-only-for table resume.
+// Its CREATE TABLE, which the identity's setval normally follows, isn't in the
+// dump, and the rows are loaded with explicit values that don't advance the
+// sequences.
+async fn dump_sequence_positions_to(client: &Client, target: &mut DumpTarget<'_>, table_name: &str, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    // setval is PostgreSQL's own
+    if opt.dialect != Dialect::Postgres {
+        return Ok(());
+    }
+    for column in fetch_columns(client, table_name).await? {
+        if let Some(setval) = column.identity_setval(table_name) {
+            target.write_line(&setval)?;
+        }
+    }
+    
+    let owned = client.query(
+        "SELECT s.relname, pg_catalog.pg_sequence_last_value(s.oid)
+         FROM pg_catalog.pg_depend d
+         JOIN pg_catalog.pg_class s ON s.oid = d.objid
+         JOIN pg_catalog.pg_class t ON t.oid = d.refobjid
+         WHERE d.classid = 'pg_catalog.pg_class'::regclass
+         AND d.refclassid = 'pg_catalog.pg_class'::regclass
+         AND d.deptype = 'a'
+         AND s.relkind = 'S'
+         AND s.relnamespace = 'public'::regnamespace
+         AND t.relnamespace = 'public'::regnamespace
+         AND t.relname = $1
+         ORDER BY s.relname",
+        &[&table_name],
+    ).await?;
+    for row in owned {
+        let sequence: String = row.get(0);
+        // A sequence that never handed out a value has nothing to carry over
+        let Some(last_value) = row.get::<_, Option<i64>>(1) else {
+            continue;
+        };
+        if opt.keeps_sequence(&sequence) {
+            target.write_line(&format!("SELECT pg_catalog.setval({}, {}, true);", quote_literal(&sequence), last_value))?;
+        }
+    }
+    target.write_line("")?;
+    Ok(())
+}
+
+// OWNED BY links between sequences and the columns that own them (serial
+// columns, or an explicit ALTER SEQUENCE ... OWNED BY), which make the
+// sequence go away with its table. Written once both exist; sequences or
+// columns left out of the dump are skipped.
+async fn dump_sequence_owners_to(client: &Client, target: &mut DumpTarget<'_>, table_names: &[String], column_filter: &ColumnFilter, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    let owners = client.query(
+        "SELECT s.relname, t.relname, a.attname
+         FROM pg_catalog.pg_depend d
+         JOIN pg_catalog.pg_class s ON s.oid = d.objid
+         JOIN pg_catalog.pg_class t ON t.oid = d.refobjid
+         JOIN pg_catalog.pg_attribute a ON a.attrelid = d.refobjid AND a.attnum = d.refobjsubid
+         WHERE d.classid = 'pg_catalog.pg_class'::regclass
+         AND d.refclassid = 'pg_catalog.pg_class'::regclass
+         AND d.deptype = 'a'
+         AND s.relkind = 'S'
+         AND s.relnamespace = 'public'::regnamespace
+         AND t.relnamespace = 'public'::regnamespace
+         AND t.relname = ANY($1)
+         ORDER BY s.relname",
+        &[&table_names],
+    ).await?;
+    
+    target.write_line("-- Sequence ownership")?;
+    for row in owners {
+        let sequence: String = row.get(0);
+        let table_name: String = row.get(1);
+        let column: String = row.get(2);
+        if !opt.keeps_sequence(&sequence) || !column_filter.keeps(&table_name, &column) {
+            continue;
+        }
+        target.write_line(&format!("ALTER SEQUENCE {} OWNED BY {}.{};", sequence, table_name, column))?;
+    }
+    target.write_line("")?;
+    Ok(())
+}
+
+// ALTER ... OWNER TO for every object the schema section creates, with the
+// owner passed through --map-role, so a reload by another role still leaves
+// each object with its original owner. Sequences owned by a column follow
+// their table and are left out.
+async fn dump_object_owners_to(client: &Client, target: &mut DumpTarget<'_>, table_names: &[String], opt: &Opt) -> Result<(), Box<dyn Error>> {
+    let objects = client.query(
+        &format!(
+            "SELECT 'TYPE', t.typname::text, pg_catalog.pg_get_userbyid(t.typowner)::text
+             FROM pg_catalog.pg_type t
+             WHERE t.typnamespace = 'public'::regnamespace AND t.typtype = 'e'
+             {}
+             UNION ALL
+             SELECT 'COLLATION', c.collname::text, pg_catalog.pg_get_userbyid(c.collowner)::text
+             FROM pg_catalog.pg_collation c
+             WHERE c.collnamespace = 'public'::regnamespace
+             {}
+             UNION ALL
+             SELECT 'TEXT SEARCH DICTIONARY', d.dictname::text, pg_catalog.pg_get_userbyid(d.dictowner)::text
+             FROM pg_catalog.pg_ts_dict d
+             WHERE d.dictnamespace = 'public'::regnamespace
+             {}
+             UNION ALL
+             SELECT 'TEXT SEARCH CONFIGURATION', c.cfgname::text, pg_catalog.pg_get_userbyid(c.cfgowner)::text
+             FROM pg_catalog.pg_ts_config c
+             WHERE c.cfgnamespace = 'public'::regnamespace
+             {}
+             UNION ALL
+             SELECT CASE c.relkind WHEN 'r' THEN 'TABLE' WHEN 'S' THEN 'SEQUENCE' ELSE 'FOREIGN TABLE' END,
+                    c.relname::text, pg_catalog.pg_get_userbyid(c.relowner)::text
+             FROM pg_catalog.pg_class c
+             WHERE c.relnamespace = 'public'::regnamespace
+             AND c.relkind IN ('r', 'S', 'f')
+             AND NOT EXISTS (
+                 SELECT 1 FROM pg_catalog.pg_depend d
+                 WHERE d.classid = 'pg_catalog.pg_class'::regclass
+                 AND d.objid = c.oid
+                 AND d.deptype IN ('a', 'i')
+                 AND c.relkind = 'S'
+             )
+             {}
+             ORDER BY 1, 2",
+            not_extension_member(opt, "pg_type", "t.oid"),
+            not_extension_member(opt, "pg_collation", "c.oid"),
+            not_extension_member(opt, "pg_ts_dict", "d.oid"),
+            not_extension_member(opt, "pg_ts_config", "c.oid"),
+            not_extension_member(opt, "pg_class", "c.oid"),
+        ),
+        &[],
+    ).await?;
+    
+    target.write_line("-- Object owners")?;
+    for row in objects {
+        let kind: &str = row.get(0);
+        let name: &str = row.get(1);
+        let owner: &str = row.get(2);
+        let dumped = match kind {
+            "TABLE" => table_names.iter().any(|table| table == name),
+            "SEQUENCE" => opt.keeps_sequence(name),
+            "FOREIGN TABLE" => opt.selects_all_tables() && opt.object_filter.keeps_foreign_table(name),
+            _ => true,
+        };
+        if dumped {
+            target.write_line(&format!("ALTER {} {} OWNER TO {};", kind, name, map_role(&opt.map_role, owner)))?;
+        }
+    }
+    target.write_line("")?;
+    Ok(())
+}
+
+// Foreign tables in the public schema, with their server and the table and
+// column options of the wrapper. Their rows live on the foreign server, so
+// no data is dumped unless --filter includes the server's foreign_data; the
+// server itself must already exist in the target.
+async fn dump_foreign_tables_to(client: &Client, target: &mut DumpTarget<'_>, column_filter: &ColumnFilter, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    let tables: Vec<Row> = client.query(
+        "SELECT c.relname,
+                pg_catalog.quote_ident(s.srvname),
+                (SELECT string_agg(pg_catalog.quote_ident(o.option_name) || ' ' || pg_catalog.quote_literal(o.option_value), ', ')
+                 FROM pg_catalog.pg_options_to_table(ft.ftoptions) o),
+                s.srvname::text
+         FROM pg_catalog.pg_foreign_table ft
+         JOIN pg_catalog.pg_class c ON c.oid = ft.ftrelid
+         JOIN pg_catalog.pg_foreign_server s ON s.oid = ft.ftserver
+         WHERE c.relnamespace = 'public'::regnamespace
+         ORDER BY c.relname",
+        &[],
+    ).await?.into_iter()
+        .filter(|table| opt.object_filter.keeps_foreign_table(table.get(0)))
+        .collect();
+    if tables.is_empty() {
+        return Ok(());
+    }
+    
+    target.write_line("-- Foreign tables")?;
+    for table in tables {
+        let table_name: String = table.get(0);
+        let server: String = table.get(1);
+        let options: Option<String> = table.get(2);
+        if opt.object_filter.includes_foreign_data(table.get(3)) {
+            target.write_line(&format!("-- Foreign table: {} (server {} must exist)", table_name, server))?;
+        } else {
+            target.write_line(&format!("-- Foreign table: {} (server {} must exist; data is not dumped)", table_name, server))?;
+        }
+        
+        let column_options = client.query(
+            "SELECT a.attname,
+                    (SELECT string_agg(pg_catalog.quote_ident(o.option_name) || ' ' || pg_catalog.quote_literal(o.option_value), ', ')
+                     FROM pg_catalog.pg_options_to_table(a.attfdwoptions) o)
+             FROM pg_catalog.pg_attribute a
+             WHERE a.attrelid = ('public.' || pg_catalog.quote_ident($1))::regclass
+             AND a.attnum > 0
+             AND NOT a.attisdropped
+             AND a.attfdwoptions IS NOT NULL",
+            &[&table_name],
+        ).await?;
+        
+        let mut columns = fetch_columns(client, &table_name).await?;
+        columns.retain(|column| column_filter.keeps(&table_name, &column.name));
+        let name_width = if target.style == FormatStyle::Pretty {
+            columns.iter().map(|c| c.name.chars().count()).max().unwrap_or(0)
+        } else {
+            0
+        };
+        let column_defs: Vec<String> = columns.iter()
+            .map(|column| {
+                let mut definition = column.definition(name_width);
+                if let Some(row) = column_options.iter().find(|row| row.get::<_, &str>(0) == column.name) {
+                    definition.push_str(&format!(" OPTIONS ({})", row.get::<_, String>(1)));
+                }
+                definition
+            })
+            .collect();
+        
+        let footer = match options {
+            Some(options) => format!(") SERVER {} OPTIONS ({});", server, options),
+            None => format!(") SERVER {};", server),
+        };
+        target.write_block(&format!("CREATE FOREIGN TABLE {} (", table_name), &column_defs, &footer)?;
+        target.write_line("")?;
+    }
+    Ok(())
+}
+
+// Relations in the public schema that the dump doesn't reproduce, listed in a
+// comment and on stderr so nothing is left out silently. Partitions of a
+// partitioned table are dumped as standalone tables.
+async fn dump_skipped_relations_to(client: &Client, target: &mut DumpTarget<'_>) -> Result<(), Box<dyn Error>> {
+    let skipped = client.query(
+        "SELECT c.relname,
+                CASE c.relkind
+                    WHEN 'p' THEN 'partitioned table'
+                    WHEN 'v' THEN 'view'
+                    WHEN 'm' THEN 'materialized view'
+                END
+         FROM pg_catalog.pg_class c
+         WHERE c.relnamespace = 'public'::regnamespace
+         AND c.relkind IN ('p', 'v', 'm')
+         ORDER BY c.relkind, c.relname",
+        &[],
+    ).await?;
+    if skipped.is_empty() {
+        return Ok(());
+    }
+    
+    target.write_line("-- Not dumped:")?;
+    for row in &skipped {
+        target.write_line(&format!("--   {} {}", row.get::<_, &str>(1), row.get::<_, &str>(0)))?;
+    }
+    target.write_line("")?;
+    eprintln!(
+        "Warning: {} partitioned tables or views in the public schema are not dumped; they are listed in the schema section",
+        skipped.len()
+    );
+    Ok(())
+}
+
+// User-defined collations in the public schema. The ICU locale lives in
+// colliculocale or colllocale depending on the server version, and in
+// collcollate before 15, so it is read through to_jsonb.
+async fn dump_collations_to(client: &Client, target: &mut DumpTarget<'_>, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    let collations = client.query(
+        &format!(
+            "SELECT pg_catalog.quote_ident(c.collname),
+                    c.collprovider::text,
+                    c.collcollate,
+                    c.collctype,
+                    COALESCE(to_jsonb(c) ->> 'colllocale', to_jsonb(c) ->> 'colliculocale', c.collcollate),
+                    c.collisdeterministic
+             FROM pg_catalog.pg_collation c
+             JOIN pg_catalog.pg_namespace n ON n.oid = c.collnamespace
+             WHERE n.nspname = 'public'
+             {}
+             ORDER BY c.collname",
+            not_extension_member(opt, "pg_collation", "c.oid")
+        ),
+        &[],
+    ).await?;
+    
+    target.write_line("-- Collations")?;
+    for collation in collations {
+        let name: String = collation.get(0);
+        let provider: String = collation.get(1);
+        let mut options = match provider.as_str() {
+            "i" => vec![
+                "provider = icu".to_string(),
+                format!("locale = {}", quote_literal(collation.get::<_, Option<&str>>(4).unwrap_or_default())),
+            ],
+            _ => vec![
+                "provider = libc".to_string(),
+                format!("lc_collate = {}", quote_literal(collation.get::<_, Option<&str>>(2).unwrap_or_default())),
+                format!("lc_ctype = {}", quote_literal(collation.get::<_, Option<&str>>(3).unwrap_or_default())),
+            ],
+        };
+        if !collation.get::<_, bool>(5) {
+            options.push("deterministic = false".to_string());
+        }
+        target.write_line(&format!("CREATE COLLATION {} ({});", name, options.join(", ")))?;
+    }
+    target.write_line("")?;
+    Ok(())
+}
+
+// Text search dictionaries and configurations in the public schema.
+// Dictionaries come first since configuration mappings refer to them; a
+// configuration is created empty from its parser and then given its mappings.
+async fn dump_text_search_to(client: &Client, target: &mut DumpTarget<'_>, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    let dictionaries = client.query(
+        &format!(
+            "SELECT pg_catalog.quote_ident(d.dictname),
+                    pg_catalog.quote_ident(tn.nspname) || '.' || pg_catalog.quote_ident(t.tmplname),
+                    d.dictinitoption
+             FROM pg_catalog.pg_ts_dict d
+             JOIN pg_catalog.pg_namespace n ON n.oid = d.dictnamespace
+             JOIN pg_catalog.pg_ts_template t ON t.oid = d.dicttemplate
+             JOIN pg_catalog.pg_namespace tn ON tn.oid = t.tmplnamespace
+             WHERE n.nspname = 'public'
+             {}
+             ORDER BY d.dictname",
+            not_extension_member(opt, "pg_ts_dict", "d.oid")
+        ),
+        &[],
+    ).await?;
+    
+    target.write_line("-- Text Search")?;
+    for dictionary in dictionaries {
+        let name: String = dictionary.get(0);
+        let template: String = dictionary.get(1);
+        let mut options = format!("TEMPLATE = {}", template);
+        if let Some(init) = dictionary.get::<_, Option<&str>>(2) {
+            options.push_str(&format!(", {}", init));
+        }
+        target.write_line(&format!("CREATE TEXT SEARCH DICTIONARY {} ({});", name, options))?;
+    }
+    
+    let configurations = client.query(
+        &format!(
+            "SELECT c.oid,
+                    pg_catalog.quote_ident(c.cfgname),
+                    pg_catalog.quote_ident(pn.nspname) || '.' || pg_catalog.quote_ident(p.prsname)
+             FROM pg_catalog.pg_ts_config c
+             JOIN pg_catalog.pg_namespace n ON n.oid = c.cfgnamespace
+             JOIN pg_catalog.pg_ts_parser p ON p.oid = c.cfgparser
+             JOIN pg_catalog.pg_namespace pn ON pn.oid = p.prsnamespace
+             WHERE n.nspname = 'public'
+             {}
+             ORDER BY c.cfgname",
+            not_extension_member(opt, "pg_ts_config", "c.oid")
+        ),
+        &[],
+    ).await?;
+    
+    for configuration in configurations {
+        let oid: u32 = configuration.get(0);
+        let name: String = configuration.get(1);
+        let parser: String = configuration.get(2);
+        target.write_line(&format!("CREATE TEXT SEARCH CONFIGURATION {} (PARSER = {});", name, parser))?;
+        
+        // One mapping per token type, with its dictionaries in order
+        let mappings = client.query(
+            "SELECT t.alias, string_agg(m.mapdict::pg_catalog.regdictionary::text, ', ' ORDER BY m.mapseqno)
+             FROM pg_catalog.pg_ts_config_map m
+             JOIN pg_catalog.pg_ts_config c ON c.oid = m.mapcfg
+             JOIN pg_catalog.ts_token_type(c.cfgparser) t ON t.tokid = m.maptokentype
+             WHERE m.mapcfg = $1
+             GROUP BY m.maptokentype, t.alias
+             ORDER BY m.maptokentype",
+            &[&oid],
+        ).await?;
+        for mapping in mappings {
+            let token: String = mapping.get(0);
+            let dictionaries: String = mapping.get(1);
+            target.write_line(&format!("ALTER TEXT SEARCH CONFIGURATION {} ADD MAPPING FOR {} WITH {};", name, token, dictionaries))?;
+        }
+    }
+    target.write_line("")?;
+    Ok(())
+}
+
+// Table privileges as the difference between each table's ACL and the default
+// its owner gets (what a NULL relacl means), as pg_dump does: REVOKEs for
+// default privileges that were taken away, GRANTs for the ones added. Tables
+// whose ACL was never changed produce nothing. Grants to PUBLIC are only
+// written with --include-acl-for-public.
+async fn dump_table_privileges_to(client: &Client, target: &mut DumpTarget<'_>, table_names: &[String], roles: &[RoleMapping], include_public: bool) -> Result<(), Box<dyn Error>> {
+    let changes = client.query(
+        "SELECT p.relname::text,
+                p.action,
+                CASE WHEN p.grantee <> 0 THEN pg_catalog.pg_get_userbyid(p.grantee) END,
+                CASE WHEN p.grantee <> 0 THEN pg_catalog.quote_ident(pg_catalog.pg_get_userbyid(p.grantee)) END,
+                p.is_grantable,
+                string_agg(p.privilege_type, ', ' ORDER BY p.privilege_type)
+         FROM (
+             SELECT c.relname, 'GRANT' AS action, a.grantee, a.is_grantable, a.privilege_type
+             FROM pg_catalog.pg_class c
+             JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+             CROSS JOIN LATERAL pg_catalog.aclexplode(c.relacl) a
+             WHERE n.nspname = 'public'
+             AND c.relname::text = ANY($1)
+             AND NOT EXISTS (
+                 SELECT 1
+                 FROM pg_catalog.aclexplode(pg_catalog.acldefault('r', c.relowner)) d
+                 WHERE d.grantee = a.grantee
+                 AND d.privilege_type = a.privilege_type
+                 AND d.is_grantable = a.is_grantable
+             )
+             UNION ALL
+             SELECT c.relname, 'REVOKE', d.grantee, false, d.privilege_type
+             FROM pg_catalog.pg_class c
+             JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+             CROSS JOIN LATERAL pg_catalog.aclexplode(pg_catalog.acldefault('r', c.relowner)) d
+             WHERE n.nspname = 'public'
+             AND c.relname::text = ANY($1)
+             AND c.relacl IS NOT NULL
+             AND NOT EXISTS (
+                 SELECT 1
+                 FROM pg_catalog.aclexplode(c.relacl) a
+                 WHERE a.grantee = d.grantee
+                 AND a.privilege_type = d.privilege_type
+             )
+         ) p
+         WHERE $2 OR p.grantee <> 0
+         GROUP BY p.relname, p.action, p.grantee, p.is_grantable
+         ORDER BY 1, 2 DESC, 3, 5",
+        &[&table_names, &include_public],
+    ).await?;
+    
+    target.write_line("-- Table privileges")?;
+    for row in changes {
+        let table_name: String = row.get(0);
+        let action: String = row.get(1);
+        let grantee_name: Option<String> = row.get(2);
+        let grantee_ident: Option<String> = row.get(3);
+        let grantable: bool = row.get(4);
+        let privileges: String = row.get(5);
+        // Mapped names are written as given; unmapped ones keep their quoting
+        let grantee = match (&grantee_name, &grantee_ident) {
+            (Some(name), Some(ident)) if map_role(roles, name) == name => ident.as_str(),
+            (Some(name), _) => map_role(roles, name),
+            _ => "PUBLIC",
+        };
+        if action == "REVOKE" {
+            target.write_line(&format!("REVOKE {} ON TABLE {} FROM {};", privileges, table_name, grantee))?;
+        } else {
+            target.write_line(&format!(
+                "GRANT {} ON TABLE {} TO {}{};",
+                privileges, table_name, grantee, if grantable { " WITH GRANT OPTION" } else { "" }
+            ))?;
+        }
+    }
+    target.write_line("")?;
+    
+    Ok(())
+}
+
+// Column-level grants. They reference the tables, so they follow the table
+// definitions rather than the roles. Read from pg_attribute.attacl rather than
+// information_schema, which only shows grants involving the current role;
+// privileges also granted on the whole table are left out, and so are grants
+// to PUBLIC unless asked for.
+async fn dump_column_privileges_to(client: &Client, target: &mut DumpTarget<'_>, table_names: &[String], roles: &[RoleMapping], column_filter: &ColumnFilter, include_public: bool) -> Result<(), Box<dyn Error>> {
+    let grants = client.query(
+        "SELECT c.relname::text,
+                CASE WHEN acl.grantee <> 0 THEN pg_catalog.pg_get_userbyid(acl.grantee)::text END,
+                acl.privilege_type,
+                acl.is_grantable,
+                array_agg(a.attname::text ORDER BY a.attnum)
+         FROM pg_catalog.pg_attribute a
+         JOIN pg_catalog.pg_class c ON c.oid = a.attrelid
+         JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+         CROSS JOIN LATERAL pg_catalog.aclexplode(a.attacl) acl
+         WHERE n.nspname = 'public'
+         AND c.relname::text = ANY($1)
+         AND a.attnum > 0
+         AND NOT a.attisdropped
+         AND ($2 OR acl.grantee <> 0)
+         AND NOT EXISTS (
+             SELECT 1
+             FROM pg_catalog.aclexplode(COALESCE(c.relacl, pg_catalog.acldefault('r', c.relowner))) tp
+             WHERE tp.grantee = acl.grantee
+             AND tp.privilege_type = acl.privilege_type
+         )
+         GROUP BY 1, 2, 3, 4
+         ORDER BY 1, 2, 3, 4",
+        &[&table_names, &include_public],
+    ).await?;
+    
+    target.write_line("-- Column privileges")?;
+    for row in grants {
+        let table_name: String = row.get(0);
+        let grantee: Option<String> = row.get(1);
+        let privilege: String = row.get(2);
+        let grantable: bool = row.get(3);
+        let mut columns: Vec<String> = row.get(4);
+        columns.retain(|column| column_filter.keeps(&table_name, column));
+        if columns.is_empty() {
+            continue;
+        }
+        
+        target.write_line(&format!(
+            "GRANT {} ({}) ON TABLE {} TO {}{};",
+            privilege,
+            columns.join(", "),
+            table_name,
+            grantee.as_deref().map_or("PUBLIC", |grantee| map_role(roles, grantee)),
+            if grantable { " WITH GRANT OPTION" } else { "" }
+        ))?;
+    }
+    target.write_line("")?;
+    
+    Ok(())
+}
+
+// Reproduces the source's comment and privileges on the public schema, the only
+// schema the dump covers. The default privileges changed in PostgreSQL 15
+// (PUBLIC lost CREATE), so the target's own default can't be relied on: start
+// from nothing for PUBLIC and grant back exactly what the source has.
+async fn dump_public_schema_to(client: &Client, target: &mut DumpTarget<'_>, roles: &[RoleMapping]) -> Result<(), Box<dyn Error>> {
+    let grants = client.query(
+        "SELECT CASE WHEN a.grantee <> 0 THEN pg_catalog.pg_get_userbyid(a.grantee) END,
+                CASE WHEN a.grantee <> 0 THEN quote_ident(pg_catalog.pg_get_userbyid(a.grantee)) END,
+                string_agg(a.privilege_type, ', ' ORDER BY a.privilege_type),
+                a.is_grantable
+         FROM pg_catalog.pg_namespace n,
+              aclexplode(COALESCE(n.nspacl, acldefault('n', n.nspowner))) a
+         WHERE n.nspname = 'public'
+         AND a.grantee <> n.nspowner
+         GROUP BY 1, 2, a.is_grantable
+         ORDER BY 1, a.is_grantable",
+        &[],
+    ).await?;
+    
+    target.write_line("-- Schema comment and privileges")?;
+    if let Some(comment) = public_schema_comment(client).await? {
+        target.write_line(&comment)?;
+    }
+    target.write_line("REVOKE ALL ON SCHEMA public FROM PUBLIC;")?;
+    for row in grants {
+        let grantee_name: Option<String> = row.get(0);
+        let grantee_ident: Option<String> = row.get(1);
+        let privileges: String = row.get(2);
+        let grantable: bool = row.get(3);
+        // Mapped names are written as given; unmapped ones keep their quoting
+        let grantee = match (&grantee_name, &grantee_ident) {
+            (Some(name), Some(ident)) if map_role(roles, name) == name => ident.as_str(),
+            (Some(name), _) => map_role(roles, name),
+            _ => "PUBLIC",
+        };
+        target.write_line(&format!(
+            "GRANT {} ON SCHEMA public TO {}{};",
+            privileges, grantee, if grantable { " WITH GRANT OPTION" } else { "" }
+        ))?;
+    }
+    target.write_line("")?;
+    
+    Ok(())
+}
+
+// Comment of the public schema in a new database, from template1
+const DEFAULT_PUBLIC_COMMENT: &str = "standard public schema";
+
+// The COMMENT for the public schema, if the source changed its comment from
+// the one every database starts with. Only the schema's owner may comment on
+// it, so the default is left alone for a restore as any other role.
+async fn public_schema_comment(client: &Client) -> Result<Option<String>, Box<dyn Error>> {
+    let comment: Option<String> = client.query_one(
+        "SELECT pg_catalog.obj_description('public'::regnamespace, 'pg_namespace')",
+        &[],
+    ).await?.get(0);
+    
+    Ok(comment
+        .filter(|comment| comment != DEFAULT_PUBLIC_COMMENT)
+        .map(|comment| format!("COMMENT ON SCHEMA public IS {};", quote_literal(&comment))))
+}
+
+// Tables among `table_names` with user-defined triggers
+async fn tables_with_triggers(client: &Client, table_names: &[String], opt: &Opt) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut tables: Vec<String> = trigger_names(client, table_names, opt).await?.into_iter()
+        .map(|(table_name, _)| table_name)
+        .collect();
+    tables.dedup();
+    Ok(tables)
+}
+
+// The user triggers of the tables that --filter keeps, as (table, trigger) pairs
+async fn trigger_names(client: &Client, table_names: &[String], opt: &Opt) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let triggers = client.query(
+        "SELECT c.relname::text, t.tgname::text
+         FROM pg_catalog.pg_trigger t
+         JOIN pg_catalog.pg_class c ON c.oid = t.tgrelid
+         WHERE c.relnamespace = 'public'::regnamespace
+         AND c.relname = ANY($1)
+         AND NOT t.tgisinternal
+         ORDER BY c.relname, t.tgname",
+        &[&table_names],
+    ).await?;
+    
+    Ok(triggers.iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .filter(|(_, trigger): &(String, String)| opt.object_filter.keeps_trigger(trigger))
+        .collect())
+}
+
+// SECURITY LABEL statements for the given tables and their columns, the
+// trigger functions dump_triggers_to writes for them, and the roles the roles
+// section covers. Extensions such as anon keep their rules in these labels.
+async fn dump_security_labels_to(client: &Client, target: &mut DumpTarget<'_>, table_names: &[String], column_filter: &ColumnFilter, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    target.write_line("-- Security labels")?;
+    
+    let relation_labels = client.query(
+        "SELECT c.relname::text, a.attname::text, l.provider, l.label
+         FROM pg_catalog.pg_seclabel l
+         JOIN pg_catalog.pg_class c ON c.oid = l.objoid
+         LEFT JOIN pg_catalog.pg_attribute a ON a.attrelid = c.oid AND a.attnum = l.objsubid AND l.objsubid > 0
+         WHERE l.classoid = 'pg_catalog.pg_class'::regclass
+         AND c.relnamespace = 'public'::regnamespace
+         AND c.relname = ANY($1)
+         ORDER BY c.relname, l.objsubid, l.provider",
+        &[&table_names],
+    ).await?;
+    
+    for row in relation_labels {
+        let table_name: String = row.get(0);
+        let column_name: Option<String> = row.get(1);
+        let provider: String = row.get(2);
+        let label: String = row.get(3);
+        let object = match column_name {
+            Some(column_name) if !column_filter.keeps(&table_name, &column_name) => continue,
+            Some(column_name) => format!("COLUMN {}.{}", table_name, column_name),
+            None => format!("TABLE {}", table_name),
+        };
+        target.write_line(&format!("SECURITY LABEL FOR {} ON {} IS {};", provider, object, quote_literal(&label)))?;
+    }
+    
+    // The functions of the triggers --filter keeps, as dump_triggers_to writes them
+    let triggers: Vec<String> = trigger_names(client, table_names, opt).await?.into_iter()
+        .map(|(_, trigger)| trigger)
+        .collect();
+    let function_labels = client.query(
+        &format!(
+            "SELECT DISTINCT p.oid::pg_catalog.regprocedure::text, l.provider, l.label
+             FROM pg_catalog.pg_seclabel l
+             JOIN pg_catalog.pg_proc p ON p.oid = l.objoid
+             JOIN pg_catalog.pg_trigger t ON t.tgfoid = p.oid
+             JOIN pg_catalog.pg_class c ON c.oid = t.tgrelid
+             WHERE l.classoid = 'pg_catalog.pg_proc'::regclass
+             AND c.relnamespace = 'public'::regnamespace
+             AND p.pronamespace = 'public'::regnamespace
+             AND c.relname = ANY($1)
+             AND t.tgname = ANY($2)
+             AND NOT t.tgisinternal
+             {}
+             ORDER BY 1, 2",
+            not_extension_member(opt, "pg_proc", "p.oid")
+        ),
+        &[&table_names, &triggers],
+    ).await?;
+    
+    for row in function_labels {
+        let signature: String = row.get(0);
+        let provider: String = row.get(1);
+        let label: String = row.get(2);
+        target.write_line(&format!("SECURITY LABEL FOR {} ON FUNCTION {} IS {};", provider, signature, quote_literal(&label)))?;
+    }
+    
+    // Roles associated with the database: its owner, the current user and object owners
+    let role_labels = client.query(
+        "SELECT r.rolname::text, l.provider, l.label
+         FROM pg_catalog.pg_shseclabel l
+         JOIN pg_catalog.pg_roles r ON r.oid = l.objoid
+         WHERE l.classoid = 'pg_catalog.pg_authid'::regclass
+         AND (
+             r.oid = (SELECT d.datdba FROM pg_catalog.pg_database d WHERE d.datname = pg_catalog.current_database())
+             OR r.rolname = current_user
+             OR r.oid IN (
+                 SELECT c.relowner
+                 FROM pg_catalog.pg_class c
+                 JOIN pg_catalog.pg_namespace n ON c.relnamespace = n.oid
+                 WHERE c.relkind IN ('r', 'S', 'v')
+                 AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+                 AND n.nspname NOT LIKE 'pg_%'
+             )
+         )
+         ORDER BY r.rolname, l.provider",
+        &[],
+    ).await?;
+    
+    for row in role_labels {
+        let role_name: String = row.get(0);
+        let provider: String = row.get(1);
+        let label: String = row.get(2);
+        target.write_line(&format!("SECURITY LABEL FOR {} ON ROLE {} IS {};", provider, map_role(&opt.map_role, &role_name), quote_literal(&label)))?;
+    }
+    
+    target.write_line("")?;
+    Ok(())
+}
+
+// Triggers of the given tables, preceded by the public trigger functions they
+// execute. A trigger that isn't in the default (fire on origin) state gets the
+// ALTER TABLE that restores its tgenabled setting.
+async fn dump_triggers_to(client: &Client, target: &mut DumpTarget<'_>, table_names: &[String], opt: &Opt) -> Result<(), Box<dyn Error>> {
+    // Only the functions of the triggers --filter keeps are written
+    let kept: Vec<String> = trigger_names(client, table_names, opt).await?.into_iter()
+        .map(|(_, trigger)| trigger)
+        .collect();
+    let functions = client.query(
+        &format!(
+            "SELECT DISTINCT p.proname, pg_catalog.pg_get_functiondef(p.oid),
+                    p.oid::pg_catalog.regprocedure::text, pg_catalog.pg_get_userbyid(p.proowner)::text
+             FROM pg_catalog.pg_trigger t
+             JOIN pg_catalog.pg_class c ON c.oid = t.tgrelid
+             JOIN pg_catalog.pg_proc p ON p.oid = t.tgfoid
+             WHERE c.relnamespace = 'public'::regnamespace
+             AND p.pronamespace = 'public'::regnamespace
+             AND c.relname = ANY($1)
+             AND t.tgname = ANY($2)
+             AND NOT t.tgisinternal
+             {}
+             ORDER BY p.proname",
+            not_extension_member(opt, "pg_proc", "p.oid")
+        ),
+        &[&table_names, &kept],
+    ).await?;
+    
+    target.write_line("-- Trigger functions")?;
+    for function in functions {
+        // The body is written untouched, blank lines included
+        let definition: &str = function.get(1);
+        let statement = format!("{}{}", definition.trim_end(), target.terminator);
+        target.write_raw_line(&statement)?;
+        if opt.writes_owners() {
+            let signature: &str = function.get(2);
+            let owner: &str = function.get(3);
+            target.write_line(&format!("ALTER FUNCTION {} OWNER TO {};", signature, map_role(&opt.map_role, owner)))?;
+        }
+        target.write_line("")?;
+    }
+    
+    let triggers = client.query(
+        "SELECT c.relname, pg_catalog.quote_ident(t.tgname), pg_catalog.pg_get_triggerdef(t.oid), t.tgenabled::text
+         FROM pg_catalog.pg_trigger t
+         JOIN pg_catalog.pg_class c ON c.oid = t.tgrelid
+         WHERE c.relnamespace = 'public'::regnamespace
+         AND c.relname = ANY($1)
+         AND t.tgname = ANY($2)
+         AND NOT t.tgisinternal
+         ORDER BY c.relname, t.tgname",
+        &[&table_names, &kept],
+    ).await?;
+    
+    target.write_line("-- Triggers")?;
+    for trigger in triggers {
+        let table_name: String = trigger.get(0);
+        let trigger_name: String = trigger.get(1);
+        let definition: String = trigger.get(2);
+        target.write_line(&format!("{};", definition))?;
+        
+        let state = match trigger.get::<_, &str>(3) {
+            "D" => Some("DISABLE"),
+            "R" => Some("ENABLE REPLICA"),
+            "A" => Some("ENABLE ALWAYS"),
+            _ => None,
+        };
+        if let Some(state) = state {
+            target.write_line(&format!("ALTER TABLE public.{} {} TRIGGER {};", table_name, state, trigger_name))?;
+        }
+    }
+    
+    target.write_line("")?;
+    Ok(())
+}
+
+// All tables are emptied before any rows are loaded: truncating them one by one
+// between loads would let CASCADE wipe child tables that were already filled
+fn dump_truncates_to(target: &mut DumpTarget<'_>, table_names: &[String]) -> Result<(), Box<dyn Error>> {
+    target.write_line("-- Empty the tables before loading their data")?;
+    for table_name in table_names {
+        target.write_line(&format!("TRUNCATE TABLE {} CASCADE;", table_name))?;
+    }
+    target.write_line("")?;
+    Ok(())
+}
+
+async fn dump_table_data_to(client: &Client, target: &mut DumpTarget<'_>, table_name: &str, row_filter: &RowFilter, column_filter: &ColumnFilter, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    let format = opt.data_format;
+
+    // A predicate naming columns the table lacks fails with undefined_column;
+    // such tables are dumped whole or left out rather than failing the dump
+    let mut predicate = row_filter.predicate.as_deref();
+    let mut notes = Vec::new();
+    if let Some(expr) = predicate {
+        let check = format!("SELECT 1 FROM {} WHERE {}", table_name, expr);
+        match client.prepare(&check).await {
+            Ok(_) => notes.push(format!("where: {}", expr)),
+            Err(e) if e.code() == Some(&SqlState::UNDEFINED_COLUMN) => {
+                if row_filter.skip_missing {
+                    target.write_line(&format!("-- Data for table: {} skipped (--where-all does not apply)", table_name))?;
+                    target.write_line("")?;
+                    return Ok(());
+                }
+                eprintln!("Note: --where-all does not apply to table {}, dumping all of its rows", table_name);
+                notes.push("--where-all not applied".to_string());
+                predicate = None;
+            }
+            Err(e) => {
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Invalid --where-all predicate on table {}: {}", table_name, e),
+                )));
+            }
+        }
+    }
+    if row_filter.extension_condition(table_name).is_some() {
+        notes.push("rows the extension didn't add".to_string());
+    }
+    if let Some(limit) = row_filter.rows {
+        notes.push(format!(
+            "sampled: at most {} {} rows",
+            limit,
+            if row_filter.random { "random" } else { "leading" }
+        ));
+    }
+    if notes.is_empty() {
+        target.write_line(&format!("-- Data for table: {}", table_name))?;
+    } else {
+        target.write_line(&format!("-- Data for table: {} ({})", table_name, notes.join("; ")))?;
+    }
+    
+    // Get column information
+    let mut columns = client.query(
+        "SELECT 
+            a.attname, 
+            pg_catalog.format_type(a.atttypid, a.atttypmod),
+            a.atttypid,
+            a.attidentity::text,
+            a.atttypid::pg_catalog.regtype::text
+         FROM pg_catalog.pg_attribute a
+         JOIN pg_catalog.pg_class c ON c.oid = a.attrelid
+         WHERE c.relname = $1
+         AND c.relnamespace = 'public'::regnamespace
+         AND a.attnum > 0
+         AND NOT a.attisdropped
+         ORDER BY a.attnum",
+        &[&table_name],
+    ).await?;
+    columns.retain(|col| column_filter.keeps(table_name, col.get(0)));
+    if opt.sort_columns == ColumnOrder::Alphabetical {
+        columns.sort_by(|a, b| a.get::<_, &str>(0).cmp(b.get::<_, &str>(0)));
+    }
+    
+    // A table whose columns were all dropped (or left out) can still hold
+    // rows, but there's no INSERT or COPY to write them with
+    if columns.is_empty() {
+        target.write_line("-- No columns to dump; the table's rows are left out")?;
+        target.write_line("")?;
+        return Ok(());
+    }
+    
+    // Get column names
+    let column_names: Vec<String> = columns.iter()
+        .map(|col| col.get::<_, String>(0))
+        .collect();
+        
+    let column_names_str = column_names.join(", ");
+    
+    // GENERATED ALWAYS identity columns only take the dumped values when the
+    // INSERT overrides them; COPY writes them as is
+    let overriding = if columns.iter().any(|col| col.get::<_, &str>(3) == "a") {
+        " OVERRIDING SYSTEM VALUE"
+    } else {
+        ""
+    };
+    
+    // Read types the driver can't decode through their text representation;
+    // COPY rows are made of text values only
+    let select_list: Vec<String> = columns.iter()
+        .map(|col| {
+            let name: String = col.get(0);
+            let type_oid: u32 = col.get(2);
+            if format == DataFormat::Insert && has_native_format(type_oid) {
+                name
+            } else if format == DataFormat::Insert && type_oid == Type::MONEY.oid() {
+                format!("{}::numeric::text", name)
+            } else {
+                format!("{}::text", name)
+            }
+        })
+        .collect();
+    
+    // Get table data, optionally filtered and limited to a sample
+    let mut select_query = format!("SELECT {} FROM {}{}", select_list.join(", "), table_name, row_filter.where_clause(table_name, predicate));
+    if let Some(limit) = row_filter.rows {
+        if row_filter.random {
+            select_query.push_str(" ORDER BY random()");
+        }
+        select_query.push_str(&format!(" LIMIT {}", limit));
+    }
+    let statement = client.prepare(&select_query).await?;
+    
+    let selected: Vec<&str> = statement.columns().iter().map(|column| column.name()).collect();
+    check_column_list(table_name, &column_names, &selected)?;
+    
+    // Rows are normally fetched in one go; a table estimated to need more than
+    // --max-memory is read through a cursor, --fetch-size rows at a time
+    let mut rows = match opt.max_memory {
+        Some(limit) if estimated_table_bytes(client, table_name).await? > limit => {
+            eprintln!("Note: streaming table {}, its rows are estimated to need more than --max-memory", table_name);
+            RowSource::Streamed(CursorRows::open(client, &select_query, opt).await?)
+        }
+        _ => RowSource::Buffered(client.query(&statement, &[]).await?.into_iter()),
+    };
+    
+    // However writing the rows ends, a cursor left open is closed, and its
+    // transaction ended, so the session is ready for whatever comes next
+    let written: Result<(), Box<dyn Error>> = async {
+        // Oversized values are checked on their text form, which every column
+        // except the natively read numbers and booleans is fetched as. Values of
+        // types that can't be cut are kept whole rather than nulled, which a NOT
+        // NULL column wouldn't take.
+        let mut oversized = 0;
+        let mut kept_whole = 0;
+        let mut limit_field = |row: &Row, i: usize| -> Result<FieldLimit, Box<dyn Error>> {
+            let Some(limit) = opt.max_field_size else {
+                return Ok(FieldLimit::Fits);
+            };
+            let Ok(Some(value)) = row.try_get::<_, Option<&str>>(i) else {
+                return Ok(FieldLimit::Fits);
+            };
+            if value.len() <= limit {
+                return Ok(FieldLimit::Fits);
+            }
+            let column: String = columns[i].get(0);
+            match opt.field_action() {
+                FieldAction::Abort => Err(format!(
+                    "Value of {}.{} is {} bytes, over the --max-field-size of {}",
+                    table_name, column, value.len(), limit
+                ).into()),
+                FieldAction::Null => {
+                    oversized += 1;
+                    Ok(FieldLimit::Replaced(None))
+                }
+                FieldAction::Truncate => match truncate_field(value, columns[i].get(2), limit) {
+                    Some(cut) => {
+                        oversized += 1;
+                        Ok(FieldLimit::Replaced(Some(cut.to_string())))
+                    }
+                    None => {
+                        kept_whole += 1;
+                        Ok(FieldLimit::KeptWhole)
+                    }
+                },
+            }
+        };
+        
+        if format == DataFormat::Copy {
+            // Text COPY can't tell an empty string from an empty NULL marker, so that one takes CSV
+            let csv = opt.null_as.as_deref() == Some("");
+            let mut options = Vec::new();
+            if csv {
+                options.push("FORMAT csv".to_string());
+            }
+            if let Some(marker) = &opt.null_as {
+                options.push(format!("NULL {}", quote_literal(marker)));
+            }
+            if opt.copy_freeze {
+                options.push("FREEZE".to_string());
+            }
+            let with = if options.is_empty() { String::new() } else { format!(" WITH ({})", options.join(", ")) };
+            target.write_line(&format!("COPY {} ({}) FROM stdin{};", table_name, column_names_str, with))?;
+            
+            let null_marker = opt.null_as.as_deref().unwrap_or("\\N");
+            let encode = |value: Option<&str>, i: usize| -> Result<String, Box<dyn Error>> {
+                if !csv {
+                    return Ok(copy_field(value, null_marker));
+                }
+                csv_field(value).ok_or_else(|| format!(
+                    "Value of {}.{} has a line that is just \\., which ends CSV data; use a non-empty --null-as",
+                    table_name, columns[i].get::<_, String>(0)
+                ).into())
+            };
+            while let Some(row) = rows.next().await? {
+                let mut fields = Vec::with_capacity(columns.len());
+                for i in 0..columns.len() {
+                    fields.push(match limit_field(&row, i)? {
+                        FieldLimit::Replaced(value) => encode(value.as_deref(), i)?,
+                        FieldLimit::Fits | FieldLimit::KeptWhole => encode(row.get::<_, Option<&str>>(i), i)?,
+                    });
+                }
+                target.write_raw_line(&fields.join(if csv { "," } else { "\t" }))?;
+                target.stats.rows += 1;
+            }
+            target.write_raw_line("\\.")?;
+            if oversized > 0 {
+                target.write_line(&format!("-- {} values over {} bytes were {}", oversized, opt.max_field_size.unwrap_or_default(), oversized_outcome(opt.field_action())))?;
+            }
+            if kept_whole > 0 {
+                target.write_line(&format!("-- {} values over {} bytes can't be cut and were kept whole", kept_whole, opt.max_field_size.unwrap_or_default()))?;
+            }
+            warn_oversized(table_name, oversized, kept_whole, opt);
+            target.write_line("")?;
+            return Ok(());
+        }
+        
+        // Values of --retype columns that the new type doesn't take as they are
+        // get a cast, from the old type since quoted values would otherwise be
+        // read by the new type's input function; numbers and booleans are typed
+        // already
+        let mut retypes: Vec<Option<(Option<String>, &str)>> = Vec::with_capacity(columns.len());
+        for column in &columns {
+            let Some(new_type) = retyped(&opt.retype, table_name, column.get(0)) else {
+                retypes.push(None);
+                continue;
+            };
+            let new_base: Option<String> = client.query_one("SELECT pg_catalog.to_regtype($1)::text", &[&new_type]).await?.get(0);
+            if keeps_values(column.get(4), column.get(1), new_base.as_deref().unwrap_or_default(), new_type) {
+                retypes.push(None);
+                continue;
+            }
+            let typed = matches!(Type::from_oid(column.get(2)), Some(Type::BOOL | Type::INT2 | Type::INT4 | Type::INT8 | Type::FLOAT4 | Type::FLOAT8));
+            retypes.push(Some(((!typed).then(|| column.get(1)), new_type)));
+        }
+        
+        // Statements written since the last BEGIN of --commit-every
+        let mut batch = 0;
+        while let Some(row) = rows.next().await? {
+            let mut values = Vec::new();
+            let mut limited = Vec::new();
+            let mut whole = Vec::new();
+            
+            for (i, column) in columns.iter().enumerate() {
+                match limit_field(&row, i)? {
+                    FieldLimit::Replaced(value) => {
+                        limited.push(column.get::<_, String>(0));
+                        values.push(match value {
+                            Some(text) => quote_string(&text, opt.string_quoting),
+                            None => "NULL".to_string(),
+                        });
+                    }
+                    FieldLimit::KeptWhole => {
+                        whole.push(column.get::<_, String>(0));
+                        values.push(typed_literal(column.get(2), format_value(&row, i, opt.string_quoting), opt.dialect));
+                    }
+                    FieldLimit::Fits => values.push(typed_literal(column.get(2), format_value(&row, i, opt.string_quoting), opt.dialect)),
+                }
+                if let Some((old_type, new_type)) = &retypes[i] {
+                    let value = values.pop().unwrap_or_default();
+                    let value = match old_type {
+                        Some(old_type) => cast_literal(value, old_type, opt.dialect),
+                        None => value,
+                    };
+                    values.push(cast_literal(value, new_type, opt.dialect));
+                }
+            }
+            
+            let mut statement = format!(
+                "INSERT INTO {} ({}){} VALUES ({});",
+                table_name, column_names_str, overriding, values.join(", ")
+            );
+            let mut notes = Vec::new();
+            if !limited.is_empty() {
+                notes.push(format!("{}: {}", oversized_outcome(opt.field_action()), limited.join(", ")));
+            }
+            if !whole.is_empty() {
+                notes.push(format!("over the limit but kept whole: {}", whole.join(", ")));
+            }
+            if !notes.is_empty() {
+                statement.push_str(&format!(" -- {}", notes.join("; ")));
+            }
+            if opt.commit_every.is_some() && batch == 0 {
+                target.write_line("BEGIN;")?;
+            }
+            target.write_line(&statement)?;
+            target.stats.rows += 1;
+            if let Some(every) = opt.commit_every {
+                batch += 1;
+                if batch == every {
+                    target.write_line("COMMIT;")?;
+                    batch = 0;
+                }
+            }
+        }
+        if batch > 0 {
+            target.write_line("COMMIT;")?;
+        }
+        
+        warn_oversized(table_name, oversized, kept_whole, opt);
+        target.write_line("")?;
+        Ok(())
+    }.await;
+    let closed = rows.close().await;
+    written?;
+    closed?;
+    Ok(())
+}
+
+// The column list written to the dump and the values taken from each row
+// must line up one to one, or values would load into the wrong columns
+fn check_column_list(table_name: &str, listed: &[String], selected: &[&str]) -> Result<(), Box<dyn Error>> {
+    if selected != listed {
+        return Err(format!(
+            "Column mismatch in table {}: dumping ({}) but the query returned ({})",
+            table_name, listed.join(", "), selected.join(", ")
+        ).into());
+    }
+    Ok(())
+}
+
+// The rows of a table, either all fetched before writing or read in batches
+enum RowSource<'a> {
+    Buffered(std::vec::IntoIter<Row>),
+    Streamed(CursorRows<'a>),
+}
+
+impl RowSource<'_> {
+    async fn next(&mut self) -> Result<Option<Row>, tokio_postgres::Error> {
+        match self {
+            // Fetched rows are written without waiting on anything; using up
+            // the task's budget lets the --deadline timer cut in on a long table
+            RowSource::Buffered(rows) => {
+                tokio::task::consume_budget().await;
+                Ok(rows.next())
+            }
+            RowSource::Streamed(cursor) => cursor.next().await,
+        }
+    }
+    
+    async fn close(&mut self) -> Result<(), tokio_postgres::Error> {
+        match self {
+            RowSource::Buffered(_) => Ok(()),
+            RowSource::Streamed(cursor) => cursor.close().await,
+        }
+    }
+}
+
+// A query read through a server-side cursor, --fetch-size rows per round
+// trip. Cursors only live in a transaction: --snapshot already has one open,
+// otherwise the cursor gets its own, ended once the last row is read or by
+// close() when the rows are given up on.
+struct CursorRows<'a> {
+    client: &'a Client,
+    fetch: String,
+    fetch_size: u64,
+    batch: std::vec::IntoIter<Row>,
+    own_transaction: bool,
+    done: bool,
+    // Until the cursor is closed and its own transaction ended
+    open: bool,
+}
+
+impl<'a> CursorRows<'a> {
+    async fn open(client: &'a Client, query: &str, opt: &Opt) -> Result<CursorRows<'a>, tokio_postgres::Error> {
+        let own_transaction = opt.snapshot.is_none();
+        if own_transaction {
+            client.batch_execute("BEGIN READ ONLY").await?;
+        }
+        client.batch_execute(&format!("DECLARE dump_rows NO SCROLL CURSOR FOR {}", query)).await?;
+        Ok(CursorRows {
+            client,
+            fetch: format!("FETCH FORWARD {} FROM dump_rows", opt.fetch_size),
+            fetch_size: opt.fetch_size,
+            batch: Vec::new().into_iter(),
+            own_transaction,
+            done: false,
+            open: true,
+        })
+    }
+    
+    async fn next(&mut self) -> Result<Option<Row>, tokio_postgres::Error> {
+        loop {
+            if let Some(row) = self.batch.next() {
+                return Ok(Some(row));
+            }
+            if self.done {
+                return Ok(None);
+            }
+            let rows = self.client.query(&self.fetch, &[]).await?;
+            // A short batch is the last one
+            if (rows.len() as u64) < self.fetch_size {
+                self.done = true;
+                self.client.batch_execute(if self.own_transaction { "CLOSE dump_rows; COMMIT" } else { "CLOSE dump_rows" }).await?;
+                self.open = false;
+            }
+            self.batch = rows.into_iter();
+        }
+    }
+    
+    // Gives up on the rows not read yet. The cursor's own transaction is
+    // rolled back, which also works once an error has aborted it; inside
+    // --snapshot's only the cursor goes.
+    async fn close(&mut self) -> Result<(), tokio_postgres::Error> {
+        if !self.open {
+            return Ok(());
+        }
+        self.done = true;
+        self.open = false;
+        self.client.batch_execute(if self.own_transaction { "ROLLBACK" } else { "CLOSE dump_rows" }).await
+    }
+}
+
+// Rough size of a table's rows once fetched: the row estimate times the
+// average width of its columns from pg_stats. Tables without statistics
+// fall back to their size on disk, TOAST included.
+async fn estimated_table_bytes(client: &Client, table_name: &str) -> Result<u64, Box<dyn Error>> {
+    let estimate: f64 = client.query_one(
+        "SELECT CASE
+                    WHEN c.reltuples >= 0 AND w.width IS NOT NULL THEN c.reltuples::float8 * w.width
+                    ELSE pg_catalog.pg_table_size(c.oid)::float8
+                END
+         FROM pg_catalog.pg_class c
+         LEFT JOIN LATERAL (
+             SELECT sum(s.avg_width)::float8 AS width
+             FROM pg_catalog.pg_stats s
+             WHERE s.schemaname = 'public' AND s.tablename = c.relname
+         ) w ON true
+         WHERE c.relname = $1
+         AND c.relnamespace = 'public'::regnamespace",
+        &[&table_name],
+    ).await?.get(0);
+    Ok(estimate as u64)
+}
+
+fn oversized_outcome(action: FieldAction) -> &'static str {
+    match action {
+        FieldAction::Null => "replaced with NULL",
+        _ => "truncated",
+    }
+}
+
+fn warn_oversized(table_name: &str, count: usize, kept_whole: usize, opt: &Opt) {
+    if count > 0 {
+        eprintln!(
+            "Warning: {} values in table {} were over --max-field-size {} and {}",
+            count, table_name, opt.max_field_size.unwrap_or_default(), oversized_outcome(opt.field_action())
+        );
+    }
+    if kept_whole > 0 {
+        eprintln!(
+            "Warning: {} values in table {} were over --max-field-size {} but of types that can't be cut, so they were kept whole",
+            kept_whole, table_name, opt.max_field_size.unwrap_or_default()
+        );
+    }
+}
+
+async fn dump_users_and_roles_to(client: &Client, target: &mut DumpTarget<'_>, db_name: &str, roles: &[RoleMapping]) -> Result<(), Box<dyn Error>> {
+    target.write_line("-- Users, roles and permissions")?;
+    target.write_line("")?;
+    
+    // Check if we have access to role-related information
+    let has_role_access = client.query_one(
+        "SELECT COUNT(*) FROM pg_catalog.pg_roles LIMIT 1",
+        &[],
+    ).await.is_ok();
+    
+    if !has_role_access {
+        target.write_line("-- Warning: No access to role information. Skipping user and role dump.")?;
+        target.write_line("-- You may need superuser privileges to dump roles.")?;
+        return Ok(());
+    }
+    
+    // First get the owner of the database
+    let db_owner_query = client.query_one(
+        "SELECT r.rolname 
+         FROM pg_catalog.pg_database d 
+         JOIN pg_catalog.pg_roles r ON d.datdba = r.oid 
+         WHERE d.datname = $1",
+        &[&db_name],
+    ).await;
+    
+    let mut db_owner = String::new();
+    if let Ok(owner_row) = db_owner_query {
+        db_owner = owner_row.get(0);
+        target.write_line(&format!("-- Database owner: {}", map_role(roles, &db_owner)))?;
+    }
+    
+    // Get the active user too
+    let current_user_query = client.query_one("SELECT current_user", &[]).await;
+    let mut current_user = String::new();
+    if let Ok(user_row) = current_user_query {
+        current_user = user_row.get(0);
+        target.write_line(&format!("-- Current connection user: {}", map_role(roles, &current_user)))?;
+    }
+    
+    // Get tables in the database to find owners
+    let table_owners = client.query(
+        "SELECT DISTINCT r.rolname
+         FROM pg_catalog.pg_class c
+         JOIN pg_catalog.pg_roles r ON c.relowner = r.oid
+         JOIN pg_catalog.pg_namespace n ON c.relnamespace = n.oid
+         WHERE c.relkind IN ('r', 'S', 'v')
+         AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+         AND n.nspname NOT LIKE 'pg_%'",
+        &[],
+    ).await?;
+    
+    let mut role_names = Vec::new();
+    
+    // Always include the database owner and current user
+    if !db_owner.is_empty() {
+        role_names.push(db_owner.clone());
+    }
+    
+    if !current_user.is_empty() && !role_names.contains(&current_user) {
+        role_names.push(current_user.clone());
+    }
+    
+    // Add owners of tables, views, and sequences
+    for row in table_owners {
+        let role: String = row.get(0);
+        if !role_names.contains(&role) {
+            role_names.push(role);
+        }
+    }
+    
+    // If we have any roles, dump them
+    if !role_names.is_empty() {
+        target.write_line(&format!("-- Found {} roles associated with this database", role_names.len()))?;
+        
+        // Get role details
+        for role_name in &role_names {
+            let role_info = client.query_one(
+                "SELECT r.rolname, r.rolsuper, r.rolinherit, r.rolcreaterole, 
+                      r.rolcreatedb, r.rolcanlogin, r.rolreplication
+                 FROM pg_catalog.pg_roles r
+                 WHERE r.rolname = $1",
+                &[&role_name],
+            ).await?;
+            
+            let rolname: String = role_info.get(0);
+            let output_name = map_role(roles, &rolname);
+            let is_superuser: bool = role_info.get(1);
+            let inherit: bool = role_info.get(2);
+            let create_role: bool = role_info.get(3);
+            let create_db: bool = role_info.get(4);
+            let can_login: bool = role_info.get(5);
+            let replication: bool = role_info.get(6);
+            
+            target.write_line(&format!("-- Role: {} ({})", 
+                output_name, 
+                if rolname == db_owner {
+                    "database owner"
+                } else if rolname == current_user {
+                    "current user"
+                } else {
+                    "object owner"
+                }
+            ))?;
+            
+            let mut create_role_stmt = format!("CREATE ROLE {}", output_name);
+            
+            if is_superuser {
+                create_role_stmt.push_str(" SUPERUSER");
+            } else {
+                create_role_stmt.push_str(" NOSUPERUSER");
+            }
+            
+            if inherit {
+                create_role_stmt.push_str(" INHERIT");
+            } else {
+                create_role_stmt.push_str(" NOINHERIT");
+            }
+            
+            if create_role {
+                create_role_stmt.push_str(" CREATEROLE");
+            } else {
+                create_role_stmt.push_str(" NOCREATEROLE");
+            }
+            
+            if create_db {
+                create_role_stmt.push_str(" CREATEDB");
+            } else {
+                create_role_stmt.push_str(" NOCREATEDB");
+            }
+            
+            if can_login {
+                create_role_stmt.push_str(" LOGIN");
+            } else {
+                create_role_stmt.push_str(" NOLOGIN");
+            }
+            
+            if replication {
+                create_role_stmt.push_str(" REPLICATION");
+            } else {
+                create_role_stmt.push_str(" NOREPLICATION");
+            }
+            
+            create_role_stmt.push(';');
+            target.write_line(&create_role_stmt)?;
+            
+            // Try to get password (requires superuser). Both md5 hashes and SCRAM
+            // verifiers are accepted as-is by ALTER ROLE, so they are copied verbatim.
+            let pwd_result = client.query_opt(
+                "SELECT rolpassword FROM pg_catalog.pg_authid WHERE rolname = $1",
+                &[&rolname],
+            ).await;
+            
+            match pwd_result {
+                Ok(Some(pwd_row)) => {
+                    let pwd: Option<String> = pwd_row.get(0);
+                    if let Some(password) = pwd
+                        && (password.starts_with("md5") || password.starts_with("SCRAM-SHA-256$")) {
+                        target.write_line(&format!("ALTER ROLE {} WITH ENCRYPTED PASSWORD {};", output_name, quote_literal(&password)))?;
+                    }
+                }
+                Ok(None) => {}
+                Err(_) => {
+                    target.write_line(&format!("-- Password for role {} not dumped: reading pg_authid requires superuser", output_name))?;
+                }
+            }
+            
+            // Get role memberships involving these roles
+            let parent_roles = client.query(
+                "SELECT r.rolname
+                 FROM pg_catalog.pg_roles r
+                 JOIN pg_catalog.pg_auth_members m ON r.oid = m.roleid
+                 JOIN pg_catalog.pg_roles ur ON ur.oid = m.member
+                 WHERE ur.rolname = $1
+                 AND r.rolname = ANY($2)",
+                &[&rolname, &role_names],
+            ).await?;
+            
+            for parent in parent_roles {
+                let parent_name: String = parent.get(0);
+                target.write_line(&format!("GRANT {} TO {};", map_role(roles, &parent_name), output_name))?;
+            }
+            
+            target.write_line("")?;
+        }
+    } else {
+        target.write_line("-- No roles found that own objects in this database")?;
+    }
+    
+    target.write_line("")?;
+    Ok(())
+}
+
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace("'", "''"))
+}
+
+async fn dump_comments_to(client: &Client, target: &mut DumpTarget<'_>, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    let roles = &opt.map_role;
+    target.write_line("-- Comments")?;
+    if opt.writes_set() {
+        target.write_line(&format!("SET search_path = {};", DUMP_SEARCH_PATH))?;
+    }
+    target.write_line("")?;
+    
+    if let Some(comment) = public_schema_comment(client).await? {
+        target.write_line(&comment)?;
+    }
+    
+    // Tables and their columns
+    let table_comments = client.query(
+        &format!(
+            "SELECT c.relname, pg_catalog.obj_description(c.oid, 'pg_class')
+             FROM pg_catalog.pg_class c
+             JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+             WHERE c.relkind = 'r'
+             AND n.nspname = 'public'
+             AND pg_catalog.obj_description(c.oid, 'pg_class') IS NOT NULL
+             {}
+             ORDER BY c.relname",
+            not_extension_member(opt, "pg_class", "c.oid")
+        ),
+        &[],
+    ).await?;
+    
+    for row in table_comments {
+        let table_name: String = row.get(0);
+        let comment: String = row.get(1);
+        target.write_line(&format!("COMMENT ON TABLE {} IS {};", table_name, quote_literal(&comment)))?;
+    }
+    
+    let column_comments = client.query(
+        &format!(
+            "SELECT c.relname, a.attname, pg_catalog.col_description(c.oid, a.attnum)
+             FROM pg_catalog.pg_attribute a
+             JOIN pg_catalog.pg_class c ON c.oid = a.attrelid
+             JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+             WHERE c.relkind = 'r'
+             AND n.nspname = 'public'
+             AND a.attnum > 0
+             AND NOT a.attisdropped
+             AND pg_catalog.col_description(c.oid, a.attnum) IS NOT NULL
+             {}
+             ORDER BY c.relname, a.attnum",
+            not_extension_member(opt, "pg_class", "c.oid")
+        ),
+        &[],
+    ).await?;
+    
+    for row in column_comments {
+        let table_name: String = row.get(0);
+        let column_name: String = row.get(1);
+        let comment: String = row.get(2);
+        target.write_line(&format!("COMMENT ON COLUMN {}.{} IS {};", table_name, column_name, quote_literal(&comment)))?;
+    }
+    
+    // Constraints and indexes of the tables
+    let constraint_comments = client.query(
+        &format!(
+            "SELECT c.relname, con.conname, pg_catalog.obj_description(con.oid, 'pg_constraint')
+             FROM pg_catalog.pg_constraint con
+             JOIN pg_catalog.pg_class c ON c.oid = con.conrelid
+             JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+             WHERE c.relkind = 'r'
+             AND n.nspname = 'public'
+             AND pg_catalog.obj_description(con.oid, 'pg_constraint') IS NOT NULL
+             {}
+             ORDER BY c.relname, con.conname",
+            not_extension_member(opt, "pg_class", "c.oid")
+        ),
+        &[],
+    ).await?;
+    
+    for row in constraint_comments {
+        let table_name: String = row.get(0);
+        let constraint_name: String = row.get(1);
+        let comment: String = row.get(2);
+        target.write_line(&format!("COMMENT ON CONSTRAINT {} ON public.{} IS {};", constraint_name, table_name, quote_literal(&comment)))?;
+    }
+    
+    let index_comments = client.query(
+        &format!(
+            "SELECT ic.relname, pg_catalog.obj_description(ic.oid, 'pg_class')
+             FROM pg_catalog.pg_index i
+             JOIN pg_catalog.pg_class ic ON ic.oid = i.indexrelid
+             JOIN pg_catalog.pg_class c ON c.oid = i.indrelid
+             JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+             WHERE c.relkind = 'r'
+             AND n.nspname = 'public'
+             AND pg_catalog.obj_description(ic.oid, 'pg_class') IS NOT NULL
+             {}
+             ORDER BY ic.relname",
+            not_extension_member(opt, "pg_class", "c.oid")
+        ),
+        &[],
+    ).await?;
+    
+    for row in index_comments {
+        let index_name: String = row.get(0);
+        let comment: String = row.get(1);
+        target.write_line(&format!("COMMENT ON INDEX {} IS {};", index_name, quote_literal(&comment)))?;
+    }
+    
+    // Types, skipping the implicit row types of tables
+    let type_comments = client.query(
+        &format!(
+            "SELECT t.typname, pg_catalog.obj_description(t.oid, 'pg_type')
+             FROM pg_catalog.pg_type t
+             JOIN pg_catalog.pg_namespace n ON n.oid = t.typnamespace
+             LEFT JOIN pg_catalog.pg_class c ON c.oid = t.typrelid
+             WHERE n.nspname = 'public'
+             AND (t.typrelid = 0 OR c.relkind = 'c')
+             AND pg_catalog.obj_description(t.oid, 'pg_type') IS NOT NULL
+             {}
+             ORDER BY t.typname",
+            not_extension_member(opt, "pg_type", "t.oid")
+        ),
+        &[],
+    ).await?;
+    
+    for row in type_comments {
+        let type_name: String = row.get(0);
+        let comment: String = row.get(1);
+        target.write_line(&format!("COMMENT ON TYPE {} IS {};", type_name, quote_literal(&comment)))?;
+    }
+    
+    // Functions, procedures and aggregates, identified by their argument types
+    let function_comments = client.query(
+        &format!(
+            "SELECT p.proname,
+                    pg_catalog.pg_get_function_identity_arguments(p.oid),
+                    p.prokind::text,
+                    pg_catalog.obj_description(p.oid, 'pg_proc')
+             FROM pg_catalog.pg_proc p
+             JOIN pg_catalog.pg_namespace n ON n.oid = p.pronamespace
+             WHERE n.nspname = 'public'
+             AND pg_catalog.obj_description(p.oid, 'pg_proc') IS NOT NULL
+             {}
+             ORDER BY p.proname, 2",
+            not_extension_member(opt, "pg_proc", "p.oid")
+        ),
+        &[],
+    ).await?;
+    
+    for row in function_comments {
+        let function_name: String = row.get(0);
+        let arguments: String = row.get(1);
+        let kind: String = row.get(2);
+        let comment: String = row.get(3);
+        let object_type = match kind.as_str() {
+            "p" => "PROCEDURE",
+            "a" => "AGGREGATE",
+            _ => "FUNCTION",
+        };
+        target.write_line(&format!("COMMENT ON {} {}({}) IS {};", object_type, function_name, arguments, quote_literal(&comment)))?;
+    }
+    
+    // Roles associated with the database: its owner, the current user and object owners
+    let role_comments = client.query(
+        "SELECT r.rolname, pg_catalog.shobj_description(r.oid, 'pg_authid')
+         FROM pg_catalog.pg_roles r
+         WHERE pg_catalog.shobj_description(r.oid, 'pg_authid') IS NOT NULL
+         AND (
+             r.oid = (SELECT d.datdba FROM pg_catalog.pg_database d WHERE d.datname = pg_catalog.current_database())
+             OR r.rolname = current_user
+             OR r.oid IN (
+                 SELECT c.relowner
+                 FROM pg_catalog.pg_class c
+                 JOIN pg_catalog.pg_namespace n ON c.relnamespace = n.oid
+                 WHERE c.relkind IN ('r', 'S', 'v')
+                 AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+                 AND n.nspname NOT LIKE 'pg_%'
+             )
+         )
+         ORDER BY r.rolname",
+        &[],
+    ).await?;
+    
+    for row in role_comments {
+        let role_name: String = row.get(0);
+        let comment: String = row.get(1);
+        target.write_line(&format!("COMMENT ON ROLE {} IS {};", map_role(roles, &role_name), quote_literal(&comment)))?;
+    }
+    
+    target.write_line("")?;
+    Ok(())
+}
+
+async fn dump_create_database_to(client: &Client, target: &mut DumpTarget<'_>, db_name: &str, drop_first: bool, roles: &[RoleMapping]) -> Result<(), Box<dyn Error>> {
+    target.write_line("-- Database creation")?;
+    target.write_line("")?;
+    
+    let db_info = client.query_one(
+        "SELECT r.rolname,
+                pg_catalog.pg_encoding_to_char(d.encoding),
+                d.datcollate,
+                d.datctype
+         FROM pg_catalog.pg_database d
+         JOIN pg_catalog.pg_roles r ON d.datdba = r.oid
+         WHERE d.datname = $1",
+        &[&db_name],
+    ).await?;
+    
+    let owner: String = db_info.get(0);
+    let encoding: String = db_info.get(1);
+    let collate: String = db_info.get(2);
+    let ctype: String = db_info.get(3);
+    
+    if drop_first {
+        target.write_line(&format!("DROP DATABASE IF EXISTS {};", db_name))?;
+    }
+    
+    target.write_line(&format!(
+        "CREATE DATABASE {} WITH OWNER = {} ENCODING = '{}' LC_COLLATE = '{}' LC_CTYPE = '{}' TEMPLATE template0;",
+        db_name,
+        map_role(roles, &owner),
+        encoding,
+        collate.replace("'", "''"),
+        ctype.replace("'", "''")
+    ))?;
+    target.write_line("")?;
+    target.write_line(&format!("\\connect {}", db_name))?;
+    target.write_line("")?;
+    
+    Ok(())
+}
+
+// Expands a leading `~` to the user's home directory
+fn expand_tilde(path: &str) -> PathBuf {
+    if path == "~" || path.starts_with("~/") {
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+        if let Some(home) = home {
+            return PathBuf::from(home).join(path.trim_start_matches('~').trim_start_matches('/'));
+        }
+    }
+    PathBuf::from(path)
+}
+
+// The plain dump's writer, and the --compress-program process behind it
+type Output = (Box<dyn Write>, Option<Compressor>);
+
+// Plain SQL goes to the output file, or to standard output for `--output -`.
+// Stdout is locked once for the whole dump, so nothing else can interleave
+// with it, and is buffered by the DumpTarget like a file.
+// With --compress-program the primary output is the program's input; call
+// Compressor::finish once the writer is dropped.
+fn open_output(opt: &Opt, path: &Path) -> Result<Output, Box<dyn Error>> {
+    // Tee files are checked and created first, so a refused one leaves no output behind
+    let mut sinks: Vec<Box<dyn Write>> = Vec::new();
+    for tee_path in &opt.tee {
+        if tee_path == "-" {
+            sinks.push(Box::new(std::io::stdout().lock()));
+            continue;
+        }
+        let tee_path = expand_tilde(tee_path);
+        if tee_path.exists() && !opt.force {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("Tee file {} already exists (use --force to overwrite)", tee_path.display()),
+            )));
+        }
+        sinks.push(Box::new(File::create(&tee_path)?));
+    }
+    
+    let (primary, compressor): (Box<dyn Write>, Option<Compressor>) = match &opt.compress_program {
+        Some(program) => {
+            let output = if opt.writes_to_stdout() { Stdio::inherit() } else { Stdio::from(File::create(path)?) };
+            let (input, compressor) = Compressor::spawn(program, output)?;
+            (Box::new(input), Some(compressor))
+        }
+        None if opt.writes_to_stdout() => (Box::new(std::io::stdout().lock()), None),
+        None => (Box::new(File::create(path)?), None),
+    };
+    if sinks.is_empty() {
+        return Ok((primary, compressor));
+    }
+    sinks.insert(0, primary);
+    Ok((Box::new(Tee { sinks }), compressor))
+}
+
+// The process started for --compress-program. Its output goes straight to
+// the output file; the dump writes to its input through CompressorInput.
+struct Compressor {
+    child: Child,
+    program: String,
+}
+
+impl Compressor {
+    fn spawn(program: &str, output: Stdio) -> Result<(CompressorInput, Compressor), Box<dyn Error>> {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+        let mut child = command.arg(program)
+            .stdin(Stdio::piped())
+            .stdout(output)
+            .spawn()
+            .map_err(|e| format!("Could not start --compress-program {}: {}", program, e))?;
+        let stdin = child.stdin.take().ok_or("--compress-program has no input pipe")?;
+        Ok((
+            CompressorInput { stdin, program: program.to_string() },
+            Compressor { child, program: program.to_string() },
+        ))
+    }
+    
+    // Waits for the program to write out the rest, once its input is closed
+    fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(format!("--compress-program {} failed ({})", self.program, status).into());
+        }
+        Ok(())
+    }
+}
+
+struct CompressorInput {
+    stdin: ChildStdin,
+    program: String,
+}
+
+impl Write for CompressorInput {
+    // A program that exits early shows up as a broken pipe; name it instead
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stdin.write(buf).map_err(|e| self.describe(e))
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stdin.flush().map_err(|e| self.describe(e))
+    }
+}
+
+impl CompressorInput {
+    fn describe(&self, e: std::io::Error) -> std::io::Error {
+        std::io::Error::new(e.kind(), format!("Could not write to --compress-program {}: {}", self.program, e))
+    }
+}
+
+// Copies everything written to each of several sinks, for --tee
+struct Tee {
+    sinks: Vec<Box<dyn Write>>,
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for sink in &mut self.sinks {
+            sink.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        for sink in &mut self.sinks {
+            sink.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// UTC time as YYYYMMDDTHHMMSSZ, for the {timestamp} placeholder
+fn utc_timestamp() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, time) = (secs / 86400, secs % 86400);
+    
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+// Expands the placeholders of an --output-template. {table} is only
+// available when naming the table data entries of a directory archive.
+fn expand_output_template(template: &str, dbname: &str, timestamp: &str, table: Option<&str>) -> Result<String, Box<dyn Error>> {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..].find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| format!("Unclosed placeholder in --output-template {}", template))?;
+        let value = match &rest[start + 1..end] {
+            "dbname" => dbname,
+            "schema" => "public",
+            "timestamp" => timestamp,
+            "table" => table.ok_or("{table} is only allowed in the file name part of --output-template, with --format directory")?,
+            other => return Err(format!("Unknown placeholder {{{}}} in --output-template", other).into()),
+        };
+        expanded.push_str(value);
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+// Splits an --output-template into the part naming the dump and, when the file
+// name uses {table}, the part naming each table's data entry
+fn split_output_template(template: &str) -> (&str, Option<&str>) {
+    match template.rsplit_once('/') {
+        Some((dir, file)) if file.contains("{table}") => (dir, Some(file)),
+        _ => (template, None),
+    }
+}
+
+// Works out where the dump goes, creating missing directories and refusing
+// to clobber an existing file unless --force is given
+fn prepare_output_path(opt: &Opt, timestamp: &str) -> Result<PathBuf, Box<dyn Error>> {
+    if opt.writes_to_stdout() {
+        return Ok(PathBuf::from("-"));
+    }
+    
+    let dump_dir = Path::new("dump-output");
+    
+    // Default output filename or use provided one
+    let output_filename = match (&opt.output, &opt.output_template) {
+        (Some(filename), _) => filename.clone(),
+        (None, Some(template)) => {
+            let (dump_template, entry_template) = split_output_template(template);
+            if entry_template.is_some() && opt.format != ArchiveFormat::Directory {
+                return Err("{table} in --output-template needs --format directory, which writes a file per table".into());
+            }
+            expand_output_template(dump_template, opt.dbname(), timestamp, None)?
+        }
+        (None, None) if opt.diff.is_some() => format!("{}-migration.sql", opt.dbname()),
+        (None, None) if opt.data_diff.is_some() => format!("{}-data-migration.sql", opt.dbname()),
+        (None, None) => match (opt.format, opt.compress) {
+            (ArchiveFormat::Plain, _) => format!("{}-dump.sql", opt.dbname()),
+            (ArchiveFormat::Directory, _) => format!("{}-dump", opt.dbname()),
+            (ArchiveFormat::Tar, false) => format!("{}-dump.tar", opt.dbname()),
+            (ArchiveFormat::Tar, true) => format!("{}-dump.tar.gz", opt.dbname()),
+            (ArchiveFormat::Custom, _) => format!("{}-dump.backup", opt.dbname()),
+        }
+    };
+    
+    // Relative names land in dump-output; absolute and ~ paths are used as given
+    let full_path = dump_dir.join(expand_tilde(&output_filename));
+    
+    // Only the directory format may write into an existing directory
+    if full_path.is_dir() && opt.format != ArchiveFormat::Directory {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Output path {} is a directory", full_path.display()),
+        )));
+    }
+    
+    let occupied = if full_path.is_dir() {
+        fs::read_dir(&full_path)?.next().is_some()
+    } else {
+        full_path.exists()
+    };
+    
+    if occupied && !opt.force {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("Output file {} already exists (use --force to overwrite)", full_path.display()),
+        )));
+    }
+    
+    if let Some(parent) = full_path.parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.exists() {
+        fs::create_dir_all(parent)?;
+        if opt.stdout_taken() {
+            eprintln!("Created {} directory", parent.display());
+        } else {
+            println!("Created {} directory", parent.display());
+        }
+    }
+    
+    Ok(full_path)
+}
+
+// A self-contained part of the dump; in archive formats each one is a separate entry
+enum Section {
+    Roles,
+    ErrorStop,
+    Database,
+    Schema(Vec<String>),
+    LoadSetup,
+    Truncate(Vec<String>),
+    Data(String),
+    LoadFinish,
+    CyclicKeys(Vec<String>),
+    Triggers(Vec<String>),
+    SecurityLabels(Vec<String>),
+    Constraints(Vec<String>),
+    Validate(Vec<String>),
+    Maintenance(Vec<String>),
+    Metadata(DumpMetadata),
+    Comments,
+}
+
+// What --record-dump-metadata records, gathered when the dump is planned
+struct DumpMetadata {
+    dumped_at: String,
+    server_version: String,
+    tables: usize,
+    data_tables: usize,
+    triggers: usize,
+}
+
+impl Section {
+    fn kind(&self) -> &'static str {
+        match self {
+            Section::Roles => "ROLES",
+            Section::ErrorStop => "ERROR-STOP",
+            Section::Database => "DATABASE",
+            Section::Schema(_) => "SCHEMA",
+            Section::LoadSetup => "LOAD-SETUP",
+            Section::Truncate(_) => "TRUNCATE",
+            Section::Data(_) => "DATA",
+            Section::LoadFinish => "LOAD-FINISH",
+            Section::CyclicKeys(_) => "CYCLIC-KEYS",
+            Section::Triggers(_) => "TRIGGERS",
+            Section::SecurityLabels(_) => "SECURITY-LABELS",
+            Section::Constraints(_) => "CONSTRAINTS",
+            Section::Validate(_) => "VALIDATE",
+            Section::Maintenance(_) => "MAINTENANCE",
+            Section::Metadata(_) => "METADATA",
+            Section::Comments => "COMMENTS",
+        }
+    }
+    
+    // The text after SECTION: in --section-markers, e.g. `DATA table=orders`
+    fn marker(&self) -> String {
+        match self {
+            Section::Data(table_name) => format!("{} table={}", self.kind(), table_name),
+            _ => self.kind().to_string(),
+        }
+    }
+    
+    fn name(&self) -> String {
+        match self {
+            Section::Data(table_name) => table_name.clone(),
+            _ => self.kind().to_lowercase(),
+        }
+    }
+}
+
+// Rejects unknown tables and columns, and warns about NOT NULL columns without
+// a default that are left out: their rows can't be loaded into a table that has them
+async fn check_column_filter(client: &Client, column_filter: &ColumnFilter) -> Result<(), Box<dyn Error>> {
+    for selection in column_filter.selections() {
+        let columns = fetch_columns(client, &selection.table).await?;
+        if columns.is_empty() {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Table {} not found in the public schema", selection.table),
+            )));
+        }
+        if let Some(missing) = selection.columns.iter().find(|name| !columns.iter().any(|c| &c.name == *name)) {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Column {} not found in table {}", missing, selection.table),
+            )));
+        }
+    }
+    
+    let mut warned: Vec<String> = Vec::new();
+    for selection in column_filter.selections() {
+        if warned.contains(&selection.table) {
+            continue;
+        }
+        warned.push(selection.table.clone());
+        for column in fetch_columns(client, &selection.table).await? {
+            if column.not_null && column.default.is_none() && !column_filter.keeps(&selection.table, &column.name) {
+                eprintln!(
+                    "Warning: column {}.{} is NOT NULL without a default; its rows will not load into a table that has it",
+                    selection.table, column.name
+                );
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+// Checks that each --retype column and type exists, and warns about those whose
+// values may not survive the cast
+async fn check_retypes(client: &Client, retypes: &[Retype]) -> Result<(), Box<dyn Error>> {
+    for retype in retypes {
+        let column = client.query_opt(
+            "SELECT a.atttypid::pg_catalog.regtype::text, pg_catalog.format_type(a.atttypid, a.atttypmod), a.attidentity <> ''
+             FROM pg_catalog.pg_attribute a
+             JOIN pg_catalog.pg_class c ON c.oid = a.attrelid
+             WHERE c.relname = $1
+             AND c.relnamespace = 'public'::regnamespace
+             AND c.relkind = 'r'
+             AND a.attname = $2
+             AND a.attnum > 0
+             AND NOT a.attisdropped",
+            &[&retype.table, &retype.column],
+        ).await?;
+        let Some(column) = column else {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Column {}.{} of --retype not found in the public schema", retype.table, retype.column),
+            )));
+        };
+        
+        // to_regtype reads the name the way a column definition does, minus
+        // any length or precision
+        let new_type: Option<String> = match client.query_one("SELECT pg_catalog.to_regtype($1)::text", &[&retype.new_type]).await {
+            Ok(row) => row.get(0),
+            Err(_) => None,
+        };
+        let Some(new_type) = new_type else {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Unknown type {} in --retype for {}.{}", retype.new_type, retype.table, retype.column),
+            )));
+        };
+        
+        if !keeps_values(column.get(0), column.get(1), &new_type, &retype.new_type) {
+            eprintln!(
+                "Warning: --retype casts {}.{} from {} to {}, which may change or lose values, or fail to load some of them",
+                retype.table, retype.column, column.get::<_, &str>(1), retype.new_type
+            );
+        }
+        if column.get(2) {
+            eprintln!(
+                "Warning: {}.{} is an identity column; its sequence keeps the range of {}",
+                retype.table, retype.column, column.get::<_, &str>(1)
+            );
+        }
+    }
+    Ok(())
+}
+
+// The tables --table, --tables-from-file and --filter pick
+struct TableSelection {
+    // Asked for by name; empty when the whole database is dumped
+    requested: Vec<String>,
+    // Named by exclude table rules, which win over everything else
+    excluded: Vec<String>,
+    // Tables whose rows the table_data rules leave out
+    without_data: Vec<String>,
+}
+
+async fn select_tables(client: &Client, opt: &Opt, table_names: &[String]) -> Result<TableSelection, Box<dyn Error>> {
+    if let Some(missing) = opt.tables.iter().find(|t| !table_names.contains(t)) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Table {} not found in the public schema", missing),
+        )));
+    }
+    
+    let inheritance = filter::list_inheritance(client).await?;
+    let mut requested = opt.tables.clone();
+    requested.extend(opt.object_filter.tables(true, table_names, &inheritance)?);
+    requested.sort();
+    requested.dedup();
+    Ok(TableSelection {
+        requested,
+        excluded: opt.object_filter.tables(false, table_names, &inheritance)?,
+        without_data: opt.object_filter.tables_without_data(table_names, &inheritance),
+    })
+}
+
+// Foreign tables on the servers that include foreign_data rules of --filter name
+async fn foreign_data_tables(client: &Client, opt: &Opt) -> Result<Vec<String>, Box<dyn Error>> {
+    let tables = client.query(
+        "SELECT c.relname::text, s.srvname::text
+         FROM pg_catalog.pg_foreign_table ft
+         JOIN pg_catalog.pg_class c ON c.oid = ft.ftrelid
+         JOIN pg_catalog.pg_foreign_server s ON s.oid = ft.ftserver
+         WHERE c.relnamespace = 'public'::regnamespace
+         ORDER BY c.relname",
+        &[],
+    ).await?;
+    Ok(tables.iter()
+        .filter(|row| opt.object_filter.includes_foreign_data(row.get(1)) && opt.object_filter.keeps_foreign_table(row.get(0)))
+        .map(|row| row.get(0))
+        .collect())
+}
+
+// Works out which sections the dump consists of, in restore order
+async fn plan_sections(client: &Session, opt: &Opt) -> Result<Vec<Section>, Box<dyn Error>> {
+    // Documentation export skips all DDL and data
+    if opt.comments_only {
+        let mut sections = Vec::new();
+        if opt.on_error_stop {
+            sections.push(Section::ErrorStop);
+        }
+        sections.push(Section::Comments);
+        return Ok(sections);
+    }
+    
+    let mut sections = vec![Section::Roles];
+    
+    // Roles usually exist already on the target server, so their CREATE ROLE
+    // failures are let through and the policy starts right after them
+    if opt.on_error_stop {
+        sections.push(Section::ErrorStop);
+    }
+    
+    // Roles must exist before the database can be created with its owner
+    if opt.create {
+        sections.push(Section::Database);
+    }
+    
+    let mut table_names = list_tables(client).await?;
+    let mut data_tables = table_names.clone();
+    let edges = list_foreign_key_edges(client).await?;
+    
+    // Narrow down to the requested tables, plus the tables their foreign keys need
+    let selection = select_tables(client, opt, &table_names).await?;
+    if !selection.requested.is_empty() {
+        let referenced = if opt.include_dependents {
+            referenced_tables(&selection.requested, &edges)
+        } else {
+            Vec::new()
+        };
+        
+        table_names.retain(|t| selection.requested.contains(t) || referenced.contains(t));
+        data_tables.retain(|t| selection.requested.contains(t) || (opt.include_dependent_data && referenced.contains(t)));
+    }
+    table_names.retain(|t| !selection.excluded.contains(t));
+    data_tables.retain(|t| !selection.excluded.contains(t) && !selection.without_data.contains(t));
+    
+    // Tables the target already has, such as those kept by migrations, only
+    // get their rows; the reverse for tables whose rows aren't wanted
+    let data_only = |table: &String| opt.data_only_for(table);
+    let schema_only = |table: &String| opt.schema_only_for.iter().any(|pattern| glob_matches(pattern, table));
+    if let Some(table) = table_names.iter().find(|t| data_only(t) && schema_only(t)) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Table {} matches both --data-only-for and --schema-only-for", table),
+        )));
+    }
+    table_names.retain(|t| !data_only(t));
+    data_tables.retain(|t| !schema_only(t));
+    
+    // CREATE EXTENSION makes the tables of extensions; of their rows only those
+    // the extension's configuration tables hold for the user are dumped
+    let mut extension_tables = Vec::new();
+    if opt.exclude_extension_owned_objects {
+        extension_tables = list_extension_tables(client).await?;
+        let member = |table: &String| extension_tables.iter().find(|extension| extension.name == *table);
+        table_names.retain(|t| member(t).is_none());
+        data_tables.retain(|t| member(t).is_none_or(|extension| opt.extension_data && extension.condition.is_some()));
+    }
+    
+    sections.push(Section::Schema(table_names.clone()));
+    let schema_tables = table_names.clone();
+    
+    // Settings and the transaction for the data load wrap all data sections;
+    // for MySQL and SQLite they hold foreign key checks and the transaction
+    let wrap_load = opt.copy_freeze || opt.disable_triggers || matches!(opt.dialect, Dialect::Mysql | Dialect::Sqlite);
+    if wrap_load {
+        sections.push(Section::LoadSetup);
+    }
+    
+    // FREEZE needs the tables truncated in the same transaction as the COPY
+    // The rows an extension adds to its tables stay, so those aren't emptied
+    let truncated: Vec<String> = data_tables.iter()
+        .filter(|t| !extension_tables.iter().any(|extension| extension.name == **t))
+        .cloned()
+        .collect();
+    if (opt.truncate_before_load || opt.copy_freeze) && !truncated.is_empty() {
+        sections.push(Section::Truncate(truncated));
+    }
+    let maintained_tables = data_tables.clone();
+    
+    // Referenced rows are loaded before the rows that reference them
+    sections.extend(load_levels(&data_tables, &edges).concat().into_iter().map(Section::Data));
+    
+    // Foreign tables have no keys, so their rows can come last. MySQL and
+    // SQLite get no foreign tables to load them into.
+    if opt.selects_all_tables() && !matches!(opt.dialect, Dialect::Mysql | Dialect::Sqlite) {
+        sections.extend(foreign_data_tables(client, opt).await?.into_iter().map(Section::Data));
+    }
+    
+    if wrap_load {
+        sections.push(Section::LoadFinish);
+    }
+    
+    // Foreign keys on a cycle are held back from the schema until all data is in
+    let cyclic = cyclic_edges(&edges);
+    let cyclic_tables: Vec<String> = table_names.iter()
+        .filter(|table| cyclic.iter().any(|(child, _)| child == *table))
+        .cloned()
+        .collect();
+    if !opt.constraints_not_valid && !cyclic_tables.is_empty() {
+        sections.push(Section::CyclicKeys(cyclic_tables));
+    }
+    
+    // Triggers are created once the data is in, so they don't fire for loaded rows
+    let trigger_tables = tables_with_triggers(client, &table_names, opt).await?;
+    if !trigger_tables.is_empty() {
+        sections.push(Section::Triggers(trigger_tables));
+    }
+    
+    // Labels go on objects of every phase, trigger functions included
+    if opt.security_labels {
+        sections.push(Section::SecurityLabels(table_names.clone()));
+    }
+    
+    if opt.constraints_not_valid {
+        sections.push(Section::Constraints(table_names.clone()));
+        if !opt.no_validate {
+            sections.push(Section::Validate(table_names));
+        }
+    }
+    
+    // VACUUM can't run in a transaction, so this comes after the load's COMMIT
+    if opt.post_maintenance != PostMaintenance::None && !maintained_tables.is_empty() {
+        sections.push(Section::Maintenance(maintained_tables));
+    }
+    
+    // The record is inserted last, once everything else has loaded
+    if opt.record_dump_metadata {
+        let dumped_at = client.query_one("SELECT pg_catalog.now()::text", &[]).await?;
+        sections.push(Section::Metadata(DumpMetadata {
+            dumped_at: dumped_at.get(0),
+            server_version: version_name(client.server_version),
+            tables: schema_tables.len(),
+            data_tables: sections.iter().filter(|section| matches!(section, Section::Data(_))).count(),
+            triggers: trigger_names(client, &schema_tables, opt).await?.len(),
+        }));
+    }
+    
+    Ok(sections)
+}
+
+async fn dump_section_to(client: &Client, target: &mut DumpTarget<'_>, opt: &Opt, section: &Section, row_filter: &RowFilter, column_filter: &ColumnFilter) -> Result<(), Box<dyn Error>> {
+    match opt.dialect {
+        Dialect::Mysql => return mysql::dump_section_to(client, target, opt, section, row_filter, column_filter).await,
+        Dialect::Sqlite => return sqlite::dump_section_to(client, target, opt, section, row_filter, column_filter).await,
+        _ => {}
+    }
+    let started = Instant::now();
+    match section {
+        Section::Roles => {
+            dump_users_and_roles_to(client, target, opt.dbname(), &opt.map_role).await?;
+            target.stats.record("roles", started);
+        }
+        Section::ErrorStop => {
+            target.write_line("-- Abort the reload at the first error (psql only)")?;
+            target.write_line("\\set ON_ERROR_STOP on")?;
+            target.write_line("")?;
+        }
+        Section::Database => {
+            dump_create_database_to(client, target, opt.dbname(), opt.drop_database, &opt.map_role).await?;
+            target.stats.record("database", started);
+        }
+        // The schema records its own phases (types, sequences, tables, ...)
+        Section::Schema(table_names) => dump_schema_to(client, target, table_names, column_filter, opt).await?,
+        Section::LoadSetup => {
+            target.write_line("-- Data load settings")?;
+            if opt.copy_freeze {
+                target.write_line("BEGIN;")?;
+            }
+            if opt.disable_triggers {
+                target.write_line("SET session_replication_role = replica;")?;
+            }
+            target.write_line("")?;
+        }
+        Section::LoadFinish => {
+            target.write_line("-- End of data load")?;
+            if opt.copy_freeze {
+                target.write_line("COMMIT;")?;
+            }
+            if opt.disable_triggers {
+                target.write_line("RESET session_replication_role;")?;
+            }
+            target.write_line("")?;
+        }
+        Section::CyclicKeys(table_names) => {
+            dump_cyclic_foreign_keys_to(client, target, table_names, column_filter).await?;
+            target.stats.record("cyclic foreign keys", started);
+        }
+        Section::Triggers(table_names) => {
+            dump_triggers_to(client, target, table_names, opt).await?;
+            target.stats.record("triggers", started);
+        }
+        Section::SecurityLabels(table_names) => {
+            dump_security_labels_to(client, target, table_names, column_filter, opt).await?;
+            target.stats.record("security labels", started);
+        }
+        Section::Constraints(table_names) => {
+            dump_deferred_constraints_to(client, target, table_names, column_filter, false).await?;
+            target.stats.record("constraints", started);
+        }
+        Section::Validate(table_names) => {
+            dump_deferred_constraints_to(client, target, table_names, column_filter, true).await?;
+            target.stats.record("validate", started);
+        }
+        Section::Maintenance(table_names) => {
+            let command = match opt.post_maintenance {
+                PostMaintenance::VacuumAnalyze => "VACUUM ANALYZE",
+                _ => "ANALYZE",
+            };
+            target.write_line("-- Post-load maintenance")?;
+            for table_name in table_names {
+                target.write_line(&format!("{} {};", command, table_name))?;
+            }
+            target.write_line("")?;
+        }
+        Section::Truncate(table_names) => {
+            dump_truncates_to(target, table_names)?;
+            target.stats.record("truncate", started);
+        }
+        Section::Data(table_name) => {
+            dump_table_data_to(client, target, table_name, row_filter, column_filter, opt).await?;
+            if opt.data_only_for(table_name) {
+                dump_sequence_positions_to(client, target, table_name, opt).await?;
+            }
+            target.stats.tables += 1;
+            target.stats.record("data", started);
+        }
+        Section::Metadata(metadata) => dump_metadata_to(target, metadata, opt)?,
+        Section::Comments => {
+            dump_comments_to(client, target, opt).await?;
+            target.stats.record("comments", started);
+        }
+    }
+    Ok(())
+}
+
+// The --record-dump-metadata table, created unless an earlier reload made it,
+// and this dump's row. Reloading several dumps into a database leaves a row
+// for each.
+fn dump_metadata_to(target: &mut DumpTarget<'_>, metadata: &DumpMetadata, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    let table = &opt.dump_metadata_table;
+    target.write_line("-- Dump metadata")?;
+    let columns = [
+        ("dumped_at", "timestamp with time zone"),
+        ("source_host", "text"),
+        ("source_port", "integer"),
+        ("source_database", "text"),
+        ("server_version", "text"),
+        ("tool_version", "text"),
+        ("tables", "integer"),
+        ("data_tables", "integer"),
+        ("triggers", "integer"),
+    ];
+    let name_width = if target.style == FormatStyle::Pretty { "source_database".len() } else { 0 };
+    let column_defs: Vec<String> = columns.iter()
+        .map(|(name, data_type)| format!("{:width$} {} NOT NULL", name, data_type, width = name_width))
+        .collect();
+    target.write_block(&format!("CREATE TABLE IF NOT EXISTS {} (", table), &column_defs, ");")?;
+    let values = [
+        quote_literal(&metadata.dumped_at),
+        quote_literal(opt.host()),
+        opt.port.to_string(),
+        quote_literal(opt.dbname()),
+        quote_literal(&metadata.server_version),
+        quote_literal(&format!("database-dump {}", env!("CARGO_PKG_VERSION"))),
+        metadata.tables.to_string(),
+        metadata.data_tables.to_string(),
+        metadata.triggers.to_string(),
+    ];
+    let names: Vec<&str> = columns.iter().map(|(name, _)| *name).collect();
+    target.write_line(&format!("INSERT INTO {} ({}) VALUES ({});", table, names.join(", "), values.join(", ")))?;
+    target.write_line("")?;
+    Ok(())
+}
+
+// Adds restore.sh, which replays the archive with psql. Entries before and after
+// the data run one after another; the data entries run a load level at a time,
+// up to `jobs` at once. Each data load is its own session, so it first reads
+// session.sql, the schema entry's SET statements.
+async fn write_restore_script(client: &Client, archive: &mut ArchiveWriter, opt: &Opt, sections: &[Section], jobs: usize) -> Result<(), Box<dyn Error>> {
+    let session_file = if !opt.writes_set() {
+        None
+    } else {
+        let mut buffer: Vec<u8> = Vec::new();
+        {
+            let mut target = DumpTarget::new(&mut buffer, opt);
+            dump_settings_to(client, &mut target).await?;
+            target.flush()?;
+        }
+        archive.add_file(archive::SESSION_FILE, &buffer, false)?;
+        Some(archive::SESSION_FILE)
+    };
+    
+    let data_tables: Vec<String> = sections.iter()
+        .filter_map(|section| match section {
+            Section::Data(table_name) => Some(table_name.clone()),
+            _ => None,
+        })
+        .collect();
+    let levels = load_levels(&data_tables, &list_foreign_key_edges(client).await?);
+    
+    let script = archive::restore_script(opt.dbname(), archive.entries(), &levels, jobs, session_file, opt.on_error_stop);
+    archive.add_file(archive::RESTORE_SCRIPT, script.as_bytes(), true)
+}
+
+// --counts-only: prints the row count of each table (or of the --table
+// selection) to stdout. Exact counts run in the dump's session, so they share
+// its --snapshot; estimates are -1 for tables never vacuumed or analyzed.
+async fn print_row_counts(client: &Client, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    let mut table_names = list_tables(client).await?;
+    let selection = select_tables(client, opt, &table_names).await?;
+    if !selection.requested.is_empty() {
+        table_names.retain(|t| selection.requested.contains(t));
+    }
+    table_names.retain(|t| !selection.excluded.contains(t));
+    
+    let mut counts = Vec::with_capacity(table_names.len());
+    for table_name in &table_names {
+        let count: i64 = if opt.estimate {
+            client.query_one(
+                "SELECT c.reltuples::int8 FROM pg_catalog.pg_class c
+                 WHERE c.relname = $1 AND c.relnamespace = 'public'::regnamespace",
+                &[table_name],
+            ).await?.get(0)
+        } else {
+            client.query_one(&format!("SELECT count(*) FROM {}", table_name), &[]).await?.get(0)
+        };
+        counts.push((table_name, count));
+    }
+    
+    let mut out = std::io::stdout().lock();
+    match opt.counts_format {
+        CountsFormat::Csv => {
+            writeln!(out, "table,rows")?;
+            for (table_name, count) in counts {
+                let needs_quotes = table_name.contains([',', '"', '\n', '\r']);
+                if needs_quotes {
+                    writeln!(out, "\"{}\",{}", table_name.replace('"', "\"\""), count)?;
+                } else {
+                    writeln!(out, "{},{}", table_name, count)?;
+                }
+            }
+        }
+        CountsFormat::Json => {
+            let items: Vec<String> = counts.iter()
+                .map(|(table_name, count)| format!("  {{\"table\": {}, \"rows\": {}}}", json_string(table_name), count))
+                .collect();
+            writeln!(out, "[\n{}\n]", items.join(",\n"))?;
+        }
+    }
+    Ok(())
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Options whose value can come from the environment, which outranks the
+// config file and the connection service
+const ENV_OPTIONS: &[(&str, &str)] = &[("password", "PGPASSWORD"), ("passfile", "PGPASSFILE"), ("service", "PGSERVICE")];
+
+// Parses the command line, then fills in options it leaves unset from the
+// --config file, if any, and then from the --service entry of the service
+// file. Their values are appended as extra arguments so they go through the
+// same validation as the command line.
+fn parse_options() -> Result<Opt, Box<dyn Error>> {
+    let mut args: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let mut matches = Opt::clap().get_matches_from(&args);
+    let mut opt = Opt::from_clap(&matches);
+    
+    if let Some(path) = opt.config.clone() {
+        args = append_unset_options(&args, &matches, config::load(&path)?);
+        (matches, opt) = reparse(&args, &format!("config file {}", path))?;
+    }
+    
+    if let Some(name) = opt.service.clone() {
+        args = append_unset_options(&args, &matches, service::load(&name)?);
+        opt = reparse(&args, &format!("service {}", name))?.1;
+    }
+    
+    // Connection options may come from the file or service, so clap can't enforce them
+    if opt.list.is_none() && !opt.help_formats {
+        for (name, value) in [("host", &opt.host), ("dbname", &opt.dbname), ("user", &opt.user)] {
+            if value.is_none() {
+                return Err(format!("--{} is required, on the command line, in the config file or in the service", name).into());
+            }
+        }
+    }
+    
+    if let Some(path) = &opt.tables_from_file {
+        let tables = read_table_list(path)?;
+        opt.tables.extend(tables);
+    }
+    
+    if let Some(path) = &opt.filter {
+        opt.object_filter = filter::ObjectFilter::load(path)?;
+    }
+    
+    // The selection can come from a file, so clap can't check this one either
+    if opt.include_dependents && opt.selects_all_tables() {
+        return Err("--include-dependents needs tables selected with --table, --tables-from-file or --filter".into());
+    }
+    Ok(opt)
+}
+
+// Table names listed one per line. Names may carry the public schema, the
+// only one dumped; blank lines and lines starting with # are skipped.
+fn read_table_list(path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read table list {}: {}", path, e))?;
+    
+    let mut tables = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('.') {
+            Some(("public", table)) => tables.push(table.to_string()),
+            Some(_) => {
+                return Err(format!("Table {} in {} is outside the public schema, the only one dumped", line, path).into());
+            }
+            None => tables.push(line.to_string()),
+        }
+    }
+    Ok(tables)
+}
+
+// Appends `options` to the command line, skipping every option the command
+// line or the environment already sets
+fn append_unset_options(args: &[std::ffi::OsString], matches: &structopt::clap::ArgMatches, options: Vec<config::ConfigOption>) -> Vec<std::ffi::OsString> {
+    let mut merged = args.to_vec();
+    for (name, values) in options {
+        let from_env = ENV_OPTIONS.iter()
+            .any(|(option, var)| *option == name && std::env::var_os(var).is_some());
+        if matches.occurrences_of(&name) > 0 || from_env {
+            continue;
+        }
+        if values.is_empty() {
+            merged.push(format!("--{}", name).into());
+        }
+        for value in values {
+            merged.push(format!("--{}={}", name, value).into());
+        }
+    }
+    merged
+}
+
+// Parses the merged arguments, blaming `source` for an invalid option
+fn reparse(args: &[std::ffi::OsString], source: &str) -> Result<(structopt::clap::ArgMatches<'static>, Opt), Box<dyn Error>> {
+    let matches = Opt::clap().get_matches_from_safe(args)
+        .map_err(|e| {
+            let message = e.message.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string();
+            format!("Invalid option in {}: {}", source, message)
+        })?;
+    let opt = Opt::from_clap(&matches);
+    Ok((matches, opt))
+}
+
+// Connects, with retries, and switches to --set-role before any catalog query
+// so everything runs with the role's privileges
+async fn open_session(opt: &Opt) -> Result<Session, Box<dyn Error>> {
+    let client = match connect_with_retry(opt, 3).await {
+        Ok(c) => c,
+        Err(e) if unsupported_server(e.as_ref()) => return Err(e),
+        Err(e) => {
+            eprintln!("Connection error: {}", e);
+            eprintln!("Please check your connection parameters and credentials.");
+            return Err(e);
+        }
+    };
+    
+    set_role(&client, opt).await?;
+    Ok(client)
+}
+
+// Switches a session to --set-role, if given
+async fn set_role(client: &Client, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    if let Some(role) = &opt.set_role
+        && let Err(e) = client.batch_execute(&format!("SET ROLE \"{}\"", role.replace('"', "\"\""))).await
+    {
+        eprintln!("Could not switch to role {}: {}", role, e);
+        return Err(Box::new(e));
+    }
+    Ok(())
+}
+
+// The session a section is read from: with --reconnect-per-table each table's
+// data gets its own, otherwise everything shares the main one
+async fn section_session(opt: &Opt, section: &Section) -> Result<Option<Session>, Box<dyn Error>> {
+    if opt.reconnect_per_table && matches!(section, Section::Data(_)) {
+        Ok(Some(open_session(opt).await?))
+    } else {
+        Ok(None)
+    }
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    let opt = parse_options()?;
+    
+    // Listing an archive needs no database connection
+    if let Some(path) = &opt.list {
+        return list_archive(path);
+    }
+    if opt.help_formats {
+        return formats::print_reference();
+    }
+    
+    if opt.diff.is_some() && opt.format != ArchiveFormat::Plain {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--diff only writes plain SQL output",
+        )));
+    }
+    
+    if opt.data_diff.is_some() && opt.format != ArchiveFormat::Plain {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--data-diff only writes plain SQL output",
+        )));
+    }
+    
+    if opt.verify_reload && opt.format != ArchiveFormat::Plain {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--verify-reload only supports plain SQL output",
+        )));
+    }
+    
+    if opt.writes_to_stdout() && opt.format != ArchiveFormat::Plain {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Only plain SQL output can be written to standard output",
+        )));
+    }
+    
+    if !opt.tee.is_empty() && opt.format != ArchiveFormat::Plain {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--tee only supports plain SQL output",
+        )));
+    }
+    
+    let stdout_sinks = opt.tee.iter().filter(|path| *path == "-").count() + opt.writes_to_stdout() as usize;
+    if stdout_sinks > 1 {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Standard output can only be named once across --output and --tee",
+        )));
+    }
+    
+    if opt.compress_program.is_some() && opt.format != ArchiveFormat::Plain {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--compress-program only applies to plain output; use --compress for the tar and custom formats",
+        )));
+    }
+    
+    if opt.compress_program.is_some() && opt.verify_reload {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--verify-reload reads the dump back from its file, which --compress-program compresses",
+        )));
+    }
+    
+    if opt.writes_to_stdout() && opt.verify_reload {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--verify-reload reads the dump back from its file, so it needs an output file",
+        )));
+    }
+    
+    if opt.copy_freeze && opt.data_format != DataFormat::Copy {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--copy-freeze requires --data-format copy",
+        )));
+    }
+    
+    if opt.null_as.is_some() && opt.data_format != DataFormat::Copy {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--null-as requires --data-format copy",
+        )));
+    }
+    
+    if opt.commit_every.is_some() && opt.data_format != DataFormat::Insert {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--commit-every requires --data-format insert",
+        )));
+    }
+    
+    // The other dialects are plain SQL for other engines: no psql meta-commands,
+    // COPY blocks, E'' strings or session settings
+    if opt.dialect != Dialect::Postgres {
+        if opt.format != ArchiveFormat::Plain {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--dialect generic, mysql and sqlite only write plain SQL output",
+            )));
+        }
+        if opt.data_format != DataFormat::Insert || opt.string_quoting != StringQuoting::Standard {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--dialect generic, mysql and sqlite need --data-format insert and --string-quoting standard",
+            )));
+        }
+        if opt.create || opt.on_error_stop || opt.disable_triggers {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--create, --on-error-stop and --disable-triggers need psql or SET statements, which only --dialect postgres writes",
+            )));
+        }
+    }
+    
+    // The MySQL and SQLite output is built from the tables and rows alone
+    if matches!(opt.dialect, Dialect::Mysql | Dialect::Sqlite) {
+        if opt.diff.is_some() || opt.data_diff.is_some() || opt.comments_only {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--diff, --data-diff and --comments-only write PostgreSQL statements, not --dialect mysql or sqlite",
+            )));
+        }
+        if opt.security_labels || opt.constraints_not_valid || opt.post_maintenance != PostMaintenance::None || opt.max_field_size.is_some() {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--security-labels, --constraints-not-valid, --post-maintenance and --max-field-size don't apply to --dialect mysql or sqlite",
+            )));
+        }
+        if opt.exclude_extension_owned_objects {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--exclude-extension-owned-objects writes CREATE EXTENSION, which only PostgreSQL has",
+            )));
+        }
+        if !opt.retype.is_empty() {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--retype takes PostgreSQL types, which --dialect mysql and sqlite map on their own",
+            )));
+        }
+        if opt.record_dump_metadata {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--record-dump-metadata is only available for --dialect postgres and generic",
+            )));
+        }
+    }
+    
+    if opt.statement_terminator.is_empty() {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--statement-terminator can't be empty",
+        )));
+    }
+    
+    // pg_restore, psql's restore.sh and the reload check all expect ;
+    if opt.statement_terminator != ";" && (opt.format != ArchiveFormat::Plain || opt.verify_reload) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--statement-terminator only applies to plain output without --verify-reload",
+        )));
+    }
+    
+    if opt.commit_every == Some(0) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--commit-every needs at least one statement per transaction",
+        )));
+    }
+    
+    if opt.fetch_size == 0 {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--fetch-size needs at least one row per fetch",
+        )));
+    }
+    
+    if opt.max_field_action.is_some() && opt.max_field_size.is_none() {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--max-field-action only applies with --max-field-size",
+        )));
+    }
+    
+    if opt.section_markers && opt.format != ArchiveFormat::Plain {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--section-markers only applies to plain SQL output; archives already keep each section in its own entry",
+        )));
+    }
+    
+    if opt.parallel_copy.is_some() && matches!(opt.format, ArchiveFormat::Plain | ArchiveFormat::Custom) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--parallel-copy writes a restore script for the directory and tar formats; custom archives load in parallel with pg_restore -j",
+        )));
+    }
+    
+    if opt.parallel_copy == Some(0) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--parallel-copy needs at least one job",
+        )));
+    }
+    
+    if opt.compress && !matches!(opt.format, ArchiveFormat::Tar | ArchiveFormat::Custom) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--compress is only supported with --format tar or custom",
+        )));
+    }
+    
+    // pg_restore runs each entry on its own, possibly on different connections
+    // with -j, so settings that span the data load don't carry over
+    if opt.format == ArchiveFormat::Custom && (opt.copy_freeze || opt.disable_triggers) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--copy-freeze and --disable-triggers don't apply to custom archives; use pg_restore --single-transaction or --disable-triggers instead",
+        )));
+    }
+    
+    // pg_restore splits INSERT data at semicolons and doesn't know dollar quotes
+    if opt.format == ArchiveFormat::Custom
+        && opt.data_format == DataFormat::Insert
+        && matches!(opt.string_quoting, StringQuoting::Dollar | StringQuoting::Auto) {
+        return Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Custom archives need --string-quoting standard or escape for INSERT data",
+        )));
+    }
+    
+    // Check the output location before spending time on the connection
+    let timestamp = utc_timestamp();
+    let full_path = if opt.counts_only {
+        PathBuf::from("-")
+    } else {
+        prepare_output_path(&opt, &timestamp)?
+    };
+    
+    // --deadline races the dump against a timer. Dropping the dump closes its
+    // sessions, which cancels whatever query was running.
+    // Only output this run opened is marked: the deadline can come before the
+    // file is created, and --force may have left a previous dump at the path
+    let opened = Cell::new(false);
+    let Some(deadline) = opt.deadline else {
+        return dump_database(&opt, &full_path, &timestamp, started, &opened).await;
+    };
+    tokio::select! {
+        result = dump_database(&opt, &full_path, &timestamp, started, &opened) => result,
+        _ = tokio::time::sleep(Duration::from_secs(deadline)) => {
+            if opened.get()
+                && let Err(e) = mark_aborted(&opt, &full_path) {
+                eprintln!("Warning: could not mark the output as aborted: {}", e);
+            }
+            Err(Box::new(DeadlineExceeded(deadline)))
+        }
+    }
+}
+
+// Everything the dump does once the options are checked and the output
+// location is settled
+async fn dump_database(opt: &Opt, full_path: &Path, timestamp: &str, started: Instant, opened: &Cell<bool>) -> Result<(), Box<dyn Error>> {
+    // Test connection before proceeding with retries
+    let connect_started = Instant::now();
+    let client = open_session(opt).await?;
+    let connect_time = connect_started.elapsed();
+    if opt.verbose {
+        eprintln!("Connected to PostgreSQL {}", version_name(client.server_version));
+    }
+    client.run(dump_with(&client, opt, full_path, timestamp, started, connect_time, opened)).await
+}
+
+async fn dump_with(client: &Session, opt: &Opt, full_path: &Path, timestamp: &str, started: Instant, connect_time: Duration, opened: &Cell<bool>) -> Result<(), Box<dyn Error>> {
+    // Test if we can query basic schema information. Catalog access can be
+    // restricted per table, so a failure here is only a warning; the queries
+    // that need what is missing will report it. This runs before --snapshot
+    // opens its transaction, which a failed query would abort.
+    if let Err(e) = client.query_one("SELECT COUNT(*) FROM pg_catalog.pg_class LIMIT 1", &[]).await {
+        eprintln!("Warning: cannot query the database schema ({}); the dump may fail or be incomplete", e);
+    }
+    
+    if opt.warmup {
+        warm_up(client, opt, 3).await?;
+    }
     
-    // Always include the database owner and current user
-    if !db_owner.is_empty() {
-        role_names.push(db_owner.clone());
+    // The snapshot has to be imported before the transaction runs any query
+    if let Some(snapshot) = &opt.snapshot
+        && let Err(e) = client.batch_execute(&format!(
+            "BEGIN ISOLATION LEVEL REPEATABLE READ, READ ONLY; SET TRANSACTION SNAPSHOT {};",
+            quote_literal(snapshot)
+        )).await
+    {
+        eprintln!("Could not use snapshot {}: {}", snapshot, e);
+        return Err(Box::new(e));
     }
     
-    if !current_user.is_empty() && !role_names.contains(&current_user) {
-        role_names.push(current_user.clone());
+    if opt.counts_only {
+        print_row_counts(client, opt).await?;
+        if opt.snapshot.is_some() {
+            client.batch_execute("COMMIT").await?;
+        }
+        return Ok(());
     }
     
-    // Add owners of tables, views, and sequences
-    for row in table_owners {
-        let role: String = row.get(0);
-        if !role_names.contains(&role) {
-            role_names.push(role);
+    if let Some(url) = &opt.diff {
+        let stats = run_diff(client, opt, url, full_path, opened).await?;
+        if opt.timing {
+            stats.print_summary(started.elapsed(), connect_time);
         }
+        return Ok(());
     }
     
-    // If we have any roles, dump them
-    if !role_names.is_empty() {
-        target.write_line(&format!("-- Found {} roles associated with this database", role_names.len()))?;
-        
-        // Get role details
-        for role_name in &role_names {
-            let role_info = client.query_one(
-                "SELECT r.rolname, r.rolsuper, r.rolinherit, r.rolcreaterole, 
-                      r.rolcreatedb, r.rolcanlogin, r.rolreplication
-                 FROM pg_catalog.pg_roles r
-                 WHERE r.rolname = $1",
-                &[&role_name],
-            ).await?;
-            
-            let rolname: String = role_info.get(0);
-            let is_superuser: bool = role_info.get(1);
-            let inherit: bool = role_info.get(2);
-            let create_role: bool = role_info.get(3);
-            let create_db: bool = role_info.get(4);
-            let can_login: bool = role_info.get(5);
-            let replication: bool = role_info.get(6);
-            
-            target.write_line(&format!("-- Role: {} ({})", 
-                rolname, 
-                if rolname == db_owner {
-                    "database owner"
-                } else if rolname == current_user {
-                    "current user"
-                } else {
-                    "object owner"
+    if let Some(url) = &opt.data_diff {
+        let stats = run_data_diff(client, opt, url, full_path, opened).await?;
+        if opt.timing {
+            stats.print_summary(started.elapsed(), connect_time);
+        }
+        return Ok(());
+    }
+    
+    let sections = plan_sections(client, opt).await?;
+    
+    // The graph is written before any data is read, so it is there to look
+    // at even when the dump fails
+    if let Some(path) = &opt.emit_depgraph {
+        // Tables whose rows alone are dumped, as with --data-only-for, are
+        // part of the graph as much as those that are created
+        let mut tables: Vec<String> = Vec::new();
+        for section in &sections {
+            let names = match section {
+                Section::Schema(tables) => tables.as_slice(),
+                Section::Data(table_name) => std::slice::from_ref(table_name),
+                _ => continue,
+            };
+            for name in names {
+                if !tables.contains(name) {
+                    tables.push(name.clone());
                 }
-            ))?;
-            
-            let mut create_role_stmt = format!("CREATE ROLE {}", rolname);
-            
-            if is_superuser {
-                create_role_stmt.push_str(" SUPERUSER");
-            } else {
-                create_role_stmt.push_str(" NOSUPERUSER");
             }
-            
-            if inherit {
-                create_role_stmt.push_str(" INHERIT");
-            } else {
-                create_role_stmt.push_str(" NOINHERIT");
+        }
+        tables.sort();
+        depgraph::write(path, opt.depgraph_format, &tables, &list_foreign_key_edges(client).await?)?;
+    }
+    
+    // Extension configuration tables take the condition of pg_extension_config_dump,
+    // written there as a WHERE clause
+    let mut extension_conditions = Vec::new();
+    if opt.extension_data {
+        for table in list_extension_tables(client).await? {
+            let Some(condition) = table.condition else { continue };
+            let condition = condition.trim();
+            let condition = match condition.get(..6) {
+                Some(keyword) if keyword.eq_ignore_ascii_case("where ") => condition[6..].trim(),
+                _ => condition,
+            };
+            if !condition.is_empty() {
+                extension_conditions.push((table.name, condition.to_string()));
             }
-            
-            if create_role {
-                create_role_stmt.push_str(" CREATEROLE");
-            } else {
-                create_role_stmt.push_str(" NOCREATEROLE");
+        }
+    }
+    
+    let row_filter = RowFilter {
+        rows: opt.sample_rows,
+        random: opt.sample_random,
+        predicate: opt.where_all.clone(),
+        skip_missing: opt.where_all_skip_missing,
+        extension_conditions,
+    };
+    
+    let column_filter = ColumnFilter {
+        include: opt.columns.clone(),
+        exclude: opt.exclude_columns.clone(),
+    };
+    check_column_filter(client, &column_filter).await?;
+    check_retypes(client, &opt.retype).await?;
+    
+    let mut stats = DumpStats::default();
+    
+    // Byte ranges of the plain dump that --verify-reload replays
+    let mut replayed_ranges: Vec<(usize, usize)> = Vec::new();
+    
+    if opt.format == ArchiveFormat::Plain {
+        // Create the output file
+        let (mut file, compressor) = open_output(opt, full_path)?;
+        opened.set(true);
+        
+        // Write headers to file
+        let header = if opt.no_comments {
+            String::new()
+        } else {
+            format!("-- Database Dump for: {}\n-- Host: {}:{}\n\n", opt.dbname(), opt.host(), opt.port)
+        };
+        file.write_all(header.as_bytes())?;
+        stats.bytes += header.len() as u64;
+        
+        // Create dump target with file
+        let mut target = DumpTarget::new(&mut file, opt);
+        let mut progress = if opt.verbose { Some(Progress::start(client, &sections).await?) } else { None };
+        
+        for section in &sections {
+            let start = header.len() + target.stats.bytes as usize;
+            let session = section_session(opt, section).await?;
+            if opt.section_markers {
+                target.write_raw_line(&format!("-- >>> SECTION: {}", section.marker()))?;
             }
-            
-            if create_db {
-                create_role_stmt.push_str(" CREATEDB");
-            } else {
-                create_role_stmt.push_str(" NOCREATEDB");
+            let reader = session.as_ref().unwrap_or(client);
+            reader.run(dump_section_to(reader, &mut target, opt, section, &row_filter, &column_filter)).await?;
+            if let (Some(progress), Section::Data(table_name)) = (&mut progress, section) {
+                progress.table_done(table_name);
             }
-            
-            if can_login {
-                create_role_stmt.push_str(" LOGIN");
-            } else {
-                create_role_stmt.push_str(" NOLOGIN");
+            if opt.section_markers {
+                target.write_raw_line(&format!("-- <<< END SECTION: {}", section.marker()))?;
             }
-            
-            if replication {
-                create_role_stmt.push_str(" REPLICATION");
-            } else {
-                create_role_stmt.push_str(" NOREPLICATION");
+            // Roles are cluster-wide, so replaying them would alter the source server's roles
+            if !matches!(section, Section::Roles) {
+                replayed_ranges.push((start, header.len() + target.stats.bytes as usize));
             }
-            
-            create_role_stmt.push(';');
-            target.write_line(&create_role_stmt)?;
-            
-            // Try to get password (requires superuser)
-            let pwd_result = client.query_opt(
-                "SELECT rolpassword FROM pg_catalog.pg_authid WHERE rolname = $1",
-                &[&rolname],
-            ).await;
-            
-            if let Ok(Some(pwd_row)) = pwd_result {
-                let pwd: Option<String> = pwd_row.get(0);
-                if let Some(password) = pwd {
-                    if password.starts_with("md5") {
-                        target.write_line(&format!("ALTER ROLE {} WITH ENCRYPTED PASSWORD '{}';", rolname, password))?;
-                    }
-                }
+        }
+        target.flush()?;
+        stats.merge(std::mem::take(&mut target.stats));
+        drop(target);
+        drop(file);
+        if let Some(compressor) = compressor {
+            compressor.finish()?;
+        }
+    } else {
+        let mut header = vec![
+            format!("Database Dump for: {}", opt.dbname()),
+            format!("Host: {}:{}", opt.host(), opt.port),
+        ];
+        // Restores that feed the entries to psql read the policy from the TOC
+        if opt.on_error_stop {
+            header.push("On error stop: on".to_string());
+        }
+        let mut archive = if opt.format == ArchiveFormat::Custom {
+            ArchiveWriter::custom(full_path, opt.compress, ArchiveHeader {
+                dbname: opt.dbname().to_string(),
+                server_version: version_name(client.server_version),
+                created: timestamp.to_string(),
+            })?
+        } else {
+            ArchiveWriter::create(full_path, opt.format, opt.compress, header)?
+        };
+        opened.set(true);
+        let entry_template = opt.output_template.as_deref().and_then(|template| split_output_template(template).1);
+        
+        let mut progress = if opt.verbose { Some(Progress::start(client, &sections).await?) } else { None };
+        
+        // Each section becomes its own entry so it can be restored selectively
+        for section in &sections {
+            let mut buffer: Vec<u8> = Vec::new();
+            {
+                let session = section_session(opt, section).await?;
+                let mut target = DumpTarget::new(&mut buffer, opt);
+                let reader = session.as_ref().unwrap_or(client);
+                reader.run(dump_section_to(reader, &mut target, opt, section, &row_filter, &column_filter)).await?;
+                target.flush()?;
+                stats.merge(target.stats);
             }
-            
-            // Get role memberships involving these roles
-            let parent_roles = client.query(
-                "SELECT r.rolname
-                 FROM pg_catalog.pg_roles r
-                 JOIN pg_catalog.pg_auth_members m ON r.oid = m.roleid
-                 JOIN pg_catalog.pg_roles ur ON ur.oid = m.member
-                 WHERE ur.rolname = $1
-                 AND r.rolname = ANY($2)",
-                &[&rolname, &role_names],
-            ).await?;
-            
-            for parent in parent_roles {
-                let parent_name: String = parent.get(0);
-                target.write_line(&format!("GRANT {} TO {};", parent_name, rolname))?;
+            if let (Some(progress), Section::Data(table_name)) = (&mut progress, section) {
+                progress.table_done(table_name);
             }
-            
-            target.write_line("")?;
+            let file_name = match (section, entry_template) {
+                (Section::Data(table_name), Some(template)) => Some(expand_output_template(template, opt.dbname(), timestamp, Some(table_name))?),
+                _ => None,
+            };
+            archive.add_entry(section.kind(), &section.name(), file_name.as_deref(), &buffer)?;
+        }
+        
+        if let Some(jobs) = opt.parallel_copy {
+            write_restore_script(client, &mut archive, opt, &sections, jobs).await?;
         }
+        
+        archive.finish()?;
+    }
+    
+    if opt.snapshot.is_some() {
+        client.batch_execute("COMMIT").await?;
+    }
+    
+    if opt.writes_to_stdout() {
+        eprintln!("Dump completed");
+    } else if opt.stdout_taken() {
+        eprintln!("Dump completed and saved to: {}", full_path.display());
     } else {
-        target.write_line("-- No roles found that own objects in this database")?;
+        println!("Dump completed and saved to: {}", full_path.display());
+    }
+    
+    if opt.verify_reload {
+        let verify_started = Instant::now();
+        let data_tables: Vec<String> = sections.iter()
+            .filter_map(|section| match section {
+                Section::Data(table_name) => Some(table_name.clone()),
+                _ => None,
+            })
+            .collect();
+        verify_reload(client, opt, full_path, &replayed_ranges, &data_tables).await?;
+        stats.record("verify reload", verify_started);
+    }
+    
+    if opt.timing {
+        stats.print_summary(started.elapsed(), connect_time);
     }
     
-    target.write_line("")?;
     Ok(())
 }
 
-async fn run() -> Result<(), Box<dyn Error>> {
-    let opt = Opt::from_args();
+// Loads the dump into a scratch database on the same server and compares the
+// row count of every dumped table with the source
+async fn verify_reload(client: &Session, opt: &Opt, dump_path: &Path, ranges: &[(usize, usize)], table_names: &[String]) -> Result<(), Box<dyn Error>> {
+    let contents = fs::read(dump_path)?;
+    let mut sql = String::new();
+    for (start, end) in ranges {
+        sql.push_str(std::str::from_utf8(&contents[*start..*end])?);
+    }
     
-    // Test connection before proceeding with retries
-    let client = match connect_with_retry(&opt, 3).await {
-        Ok(c) => c,
+    let (scratch_name, temporary) = match &opt.verify_dbname {
+        Some(name) => (name.clone(), false),
+        None => (format!("database_dump_verify_{}", process::id()), true),
+    };
+    
+    if temporary {
+        let db_info = client.query_one(
+            "SELECT pg_catalog.pg_encoding_to_char(encoding), datcollate, datctype
+             FROM pg_catalog.pg_database
+             WHERE datname = pg_catalog.current_database()",
+            &[],
+        ).await?;
+        let encoding: String = db_info.get(0);
+        let collate: String = db_info.get(1);
+        let ctype: String = db_info.get(2);
+        client.batch_execute(&format!(
+            "CREATE DATABASE {} TEMPLATE template0 ENCODING {} LC_COLLATE {} LC_CTYPE {}",
+            scratch_name, quote_literal(&encoding), quote_literal(&collate), quote_literal(&ctype)
+        )).await?;
+    }
+    
+    let result = async {
+        let hosts: Vec<&str> = opt.host().split(',').map(str::trim).filter(|h| !h.is_empty()).collect();
+        let scratch = connect_hosts(opt, &hosts, |config| {
+            config.dbname(&scratch_name).target_session_attrs(TargetSessionAttrs::ReadWrite);
+        }).await?;
+        set_role(&scratch, opt).await?;
+        
+        scratch.run(async {
+            verify::apply_sql(&scratch, &sql).await?;
+            verify::compare_row_counts(client, &scratch, table_names).await
+        }).await
+    }.await;
+    
+    // A failed drop is reported but doesn't hide the outcome of the load
+    if temporary {
+        // WITH (FORCE) arrived in PostgreSQL 13
+        let force = if client.server_version >= 130000 { " WITH (FORCE)" } else { "" };
+        if let Err(e) = client.batch_execute(&format!("DROP DATABASE IF EXISTS {}{}", scratch_name, force)).await {
+            eprintln!("Warning: could not drop the scratch database {}: {}", scratch_name, e);
+        }
+    }
+    
+    let mismatches = match result {
+        Ok(mismatches) => mismatches,
         Err(e) => {
-            eprintln!("Connection error: {}", e);
-            eprintln!("Please check your connection parameters and credentials.");
+            eprintln!("Reload verification failed while loading into {}: {}", scratch_name, e);
             return Err(e);
         }
     };
     
-    // Test if we can query basic schema information
-    let can_query_schema = client.query_one(
-        "SELECT COUNT(*) FROM pg_catalog.pg_class LIMIT 1",
-        &[],
-    ).await.is_ok();
-    
-    if !can_query_schema {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::PermissionDenied,
-            "Cannot query database schema. Check your permissions."
-        )));
+    for (table_name, expected, actual) in &mismatches {
+        eprintln!("Row count mismatch for {}: {} in the source, {} after reload", table_name, expected, actual);
     }
-    
-    // Create dump-output directory if it doesn't exist
-    let dump_dir = Path::new("dump-output");
-    if !dump_dir.exists() {
-        fs::create_dir(dump_dir)?;
-        println!("Created dump-output directory");
+    if !mismatches.is_empty() {
+        return Err(Box::new(std::io::Error::other(format!(
+            "Reload verification failed: {} of {} tables differ",
+            mismatches.len(), table_names.len()
+        ))));
     }
     
-    // Default output filename or use provided one
-    let output_filename = match &opt.output {
-        Some(filename) => filename.clone(),
-        None => format!("{}-dump.sql", opt.dbname)
+    if opt.stdout_taken() {
+        eprintln!("Reload verified: {} tables match the source", table_names.len());
+    } else {
+        println!("Reload verified: {} tables match the source", table_names.len());
+    }
+    Ok(())
+}
+
+// Connects to the database at `url` that --diff or --data-diff compares against,
+// returning the client and a description of it for the migration header
+async fn connect_url(opt: &Opt, url: &str) -> Result<(Session, String), Box<dyn Error>> {
+    let mut config: Config = url.parse()?;
+    if config.get_connect_timeout().is_none() {
+        config.connect_timeout(Duration::from_secs(opt.connect_timeout));
+    }
+    let other = connect_config(&config).await?;
+    set_role(&other, opt).await?;
+    // Describe the target without echoing the URL, which may carry a password
+    let other_host = match config.get_hosts().first() {
+        Some(tokio_postgres::config::Host::Tcp(host)) => host.clone(),
+        _ => "local socket".to_string(),
     };
+    Ok((other, format!("{} on {}", config.get_dbname().unwrap_or_default(), other_host)))
+}
+
+// Writes a migration that brings the database at `url` in line with the source schema
+async fn run_diff(client: &Client, opt: &Opt, url: &str, full_path: &Path, opened: &Cell<bool>) -> Result<DumpStats, Box<dyn Error>> {
+    let (other, other_name) = connect_url(opt, url).await?;
     
-    // Combine the directory path with the output filename
-    let full_path = dump_dir.join(&output_filename);
+    let snapshot_started = Instant::now();
+    let source = diff::snapshot(client).await?;
+    let target_schema = other.run(diff::snapshot(&other)).await?;
     
-    // Create the output file
-    let mut file = File::create(&full_path)?;
+    let (mut file, compressor) = open_output(opt, full_path)?;
+    opened.set(true);
+    let mut target = DumpTarget::new(&mut file, opt);
+    target.write_line(&format!(
+        "-- Schema migration to make {} match {} on {}",
+        other_name, opt.dbname(), opt.host()
+    ))?;
+    if opt.writes_set() {
+        target.write_line(&format!("SET search_path = {};", DUMP_SEARCH_PATH))?;
+    }
+    target.write_line("")?;
+    target.stats.record("snapshot", snapshot_started);
+    let changes = diff::write_migration(&source, &target_schema, &opt.diff_ignore, &mut target)?;
+    target.stats.tables = source.len();
+    target.flush()?;
+    let stats = std::mem::take(&mut target.stats);
+    drop(target);
+    drop(file);
+    if let Some(compressor) = compressor {
+        compressor.finish()?;
+    }
     
-    // Write headers to file
-    writeln!(file, "Database Dump for: {}", opt.dbname)?;
-    writeln!(file, "Host: {}:{}\n", opt.host, opt.port)?;
+    if opt.writes_to_stdout() {
+        eprintln!("Found {} schema changes", changes);
+    } else if opt.stdout_taken() {
+        eprintln!("Found {} schema changes, saved to: {}", changes, full_path.display());
+    } else {
+        println!("Found {} schema changes, saved to: {}", changes, full_path.display());
+    }
+    Ok(stats)
+}
+
+// Writes the statements that bring the rows of the database at `url` in line
+// with the source, for tables with the same primary key on both sides
+async fn run_data_diff(client: &Client, opt: &Opt, url: &str, full_path: &Path, opened: &Cell<bool>) -> Result<DumpStats, Box<dyn Error>> {
+    let (other, other_name) = connect_url(opt, url).await?;
     
-    // Create dump target with file
-    {
-        let mut target = DumpTarget::new(Some(&mut file));
-        // First dump users and roles
-        dump_users_and_roles_to(&client, &mut target, &opt.dbname).await?;
+    let (mut file, compressor) = open_output(opt, full_path)?;
+    opened.set(true);
+    let mut target = DumpTarget::new(&mut file, opt);
+    target.write_line(&format!(
+        "-- Data migration to make {} match {} on {}",
+        other_name, opt.dbname(), opt.host()
+    ))?;
+    if opt.writes_set() {
+        target.write_line(&format!("SET search_path = {};", DUMP_SEARCH_PATH))?;
+    }
+    target.write_line("")?;
+    let compare_started = Instant::now();
+    let (changes, tables) = other.run(datadiff::write_data_migration(client, &other, &opt.tables, opt.string_quoting, &mut target)).await?;
+    target.stats.record("compare", compare_started);
+    target.stats.tables = tables;
+    target.flush()?;
+    let stats = std::mem::take(&mut target.stats);
+    drop(target);
+    drop(file);
+    if let Some(compressor) = compressor {
+        compressor.finish()?;
     }
     
-    {
-        let mut target = DumpTarget::new(Some(&mut file));
-        // Then dump schema (tables, sequences, etc)
-        dump_schema_to(&client, &mut target).await?;
+    if opt.writes_to_stdout() {
+        eprintln!("Found {} data changes", changes);
+    } else if opt.stdout_taken() {
+        eprintln!("Found {} data changes, saved to: {}", changes, full_path.display());
+    } else {
+        println!("Found {} data changes, saved to: {}", changes, full_path.display());
     }
+    Ok(stats)
+}
+
+fn list_archive(path: &str) -> Result<(), Box<dyn Error>> {
+    let (header, entries) = archive::read_toc(&expand_tilde(path))?;
+    
+    for line in &header {
+        println!("; {}", line);
+    }
+    println!(";");
     
-    println!("Dump completed and saved to: {}", full_path.display());
+    for entry in &entries {
+        println!("{:>4}  {:<15} {:<40} {}", entry.id, entry.kind, entry.name, entry.file);
+    }
     
     Ok(())
 }
 
+// The dump ran past --deadline. It exits with its own status, so a
+// scheduler can tell an overrun from a failure.
+#[derive(Debug)]
+struct DeadlineExceeded(u64);
+
+impl std::fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The dump did not finish within the --deadline of {} seconds", self.0)
+    }
+}
+
+impl Error for DeadlineExceeded {}
+
+const DEADLINE_EXIT_CODE: i32 = 3;
+
+// Closes a plain dump cut short by --deadline with a marker, so the partial
+// file can't pass for a complete one. Compressed output and archives can't
+// take a line at the end; they are reported as incomplete instead.
+fn mark_aborted(opt: &Opt, path: &Path) -> Result<(), Box<dyn Error>> {
+    const MARKER: &str = "\n-- ABORTED: deadline exceeded\n";
+    if opt.counts_only {
+        return Ok(());
+    }
+    if opt.format != ArchiveFormat::Plain || opt.compress_program.is_some() {
+        eprintln!("Warning: {} is incomplete", if opt.writes_to_stdout() { "the output".to_string() } else { path.display().to_string() });
+        return Ok(());
+    }
+    let mut outputs: Vec<PathBuf> = opt.tee.iter().map(|tee_path| expand_tilde(tee_path)).collect();
+    outputs.insert(0, path.to_path_buf());
+    for output in outputs {
+        if output.as_os_str() == "-" {
+            std::io::stdout().write_all(MARKER.as_bytes())?;
+        } else {
+            fs::OpenOptions::new().append(true).open(&output)?.write_all(MARKER.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
 fn main() {
     let rt = Runtime::new().unwrap();
     if let Err(e) = rt.block_on(run()) {
         eprintln!("Error: {}", e);
-        process::exit(1);
+        process::exit(if e.is::<DeadlineExceeded>() { DEADLINE_EXIT_CODE } else { 1 });
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn column_lists_must_match_the_query() {
+        let listed = names(&["id", "name", "total"]);
+        assert!(check_column_list("orders", &listed, &["id", "name", "total"]).is_ok());
+
+        let error = check_column_list("orders", &listed, &["id", "total", "name"]).unwrap_err();
+        assert_eq!(error.to_string(), "Column mismatch in table orders: dumping (id, name, total) but the query returned (id, total, name)");
+        assert!(check_column_list("orders", &listed, &["id", "name"]).is_err());
+        assert!(check_column_list("orders", &listed, &["id", "name", "total", "extra"]).is_err());
+    }
+
+    #[test]
+    fn money_is_cast_through_numeric() {
+        assert_eq!(typed_literal(Type::MONEY.oid(), "'1234.50'".to_string(), Dialect::Postgres), "'1234.50'::numeric::money");
+        assert_eq!(typed_literal(Type::MONEY.oid(), "'-0.01'".to_string(), Dialect::Generic), "CAST(CAST('-0.01' AS numeric) AS money)");
+        assert_eq!(typed_literal(Type::MONEY.oid(), "NULL".to_string(), Dialect::Postgres), "NULL");
+    }
+
+    #[test]
+    fn bit_strings_become_b_literals() {
+        assert_eq!(typed_literal(Type::BIT.oid(), "'1010'".to_string(), Dialect::Postgres), "B'1010'");
+        assert_eq!(typed_literal(Type::VARBIT.oid(), "'0'".to_string(), Dialect::Postgres), "B'0'");
+        assert_eq!(typed_literal(Type::VARBIT.oid(), "''".to_string(), Dialect::Generic), "B''");
+        assert_eq!(typed_literal(Type::BIT.oid(), "NULL".to_string(), Dialect::Postgres), "NULL");
+    }
+
+    #[test]
+    fn other_literals_are_left_alone() {
+        assert_eq!(typed_literal(Type::TEXT.oid(), "'1010'".to_string(), Dialect::Postgres), "'1010'");
+        assert_eq!(typed_literal(Type::NUMERIC.oid(), "'12.5'".to_string(), Dialect::Generic), "'12.5'");
+    }
+
+    fn edges(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(child, parent)| (child.to_string(), parent.to_string())).collect()
+    }
+
+    #[test]
+    fn self_references_are_cyclic() {
+        let graph = edges(&[("employee", "employee")]);
+        assert_eq!(cyclic_edges(&graph), graph);
+        assert_eq!(load_levels(&names(&["employee"]), &graph), [names(&["employee"])]);
+    }
+
+    #[test]
+    fn mutual_references_are_cyclic() {
+        let graph = edges(&[("dept", "emp"), ("emp", "dept"), ("badge", "emp")]);
+        assert_eq!(cyclic_edges(&graph), edges(&[("dept", "emp"), ("emp", "dept")]));
+        assert_eq!(
+            load_levels(&names(&["badge", "dept", "emp"]), &graph),
+            [names(&["dept", "emp"]), names(&["badge"])]
+        );
+    }
+
+    #[test]
+    fn longer_cycles_are_found() {
+        let graph = edges(&[("a", "b"), ("b", "c"), ("c", "a"), ("d", "a"), ("e", "d")]);
+        assert_eq!(cyclic_edges(&graph), edges(&[("a", "b"), ("b", "c"), ("c", "a")]));
+        assert_eq!(
+            load_levels(&names(&["a", "b", "c", "d", "e"]), &graph),
+            [names(&["a", "b", "c"]), names(&["d"]), names(&["e"])]
+        );
+    }
+
+    #[test]
+    fn acyclic_graphs_load_parents_first() {
+        let graph = edges(&[("line_items", "orders"), ("orders", "customers"), ("line_items", "products")]);
+        assert!(cyclic_edges(&graph).is_empty());
+        assert_eq!(
+            load_levels(&names(&["customers", "line_items", "orders", "products"]), &graph),
+            [names(&["customers", "products"]), names(&["orders"]), names(&["line_items"])]
+        );
+    }
+
+    #[test]
+    fn references_to_tables_outside_the_dump_are_ignored() {
+        let graph = edges(&[("orders", "customers")]);
+        assert_eq!(load_levels(&names(&["orders"]), &graph), [names(&["orders"])]);
+    }
+
+    #[test]
+    fn non_finite_floats_are_quoted_words() {
+        assert_eq!(float_literal(f64::NAN, "NaN".to_string()), "'NaN'");
+        assert_eq!(float_literal(f64::INFINITY, "inf".to_string()), "'Infinity'");
+        assert_eq!(float_literal(f64::NEG_INFINITY, "-inf".to_string()), "'-Infinity'");
+        assert_eq!(float_literal(f32::INFINITY as f64, "inf".to_string()), "'Infinity'");
+    }
+
+    #[test]
+    fn finite_floats_keep_their_rendering() {
+        assert_eq!(float_literal(1234.56, format!("{:?}", 1234.56)), "1234.56");
+        assert_eq!(float_literal(-0.0, format!("{:?}", -0.0)), "-0.0");
+        assert_eq!(float_literal(1e300, format!("{:?}", 1e300)), "1e300");
+    }
+
+    #[test]
+    fn version_names_follow_the_release_numbering() {
+        assert_eq!(version_name(150018), "15.18");
+        assert_eq!(version_name(120000), "12.0");
+        assert_eq!(version_name(170002), "17.2");
+        assert_eq!(version_name(90624), "9.6.24");
+        assert_eq!(version_name(100001), "10.1");
+    }
+
+    #[test]
+    fn copy_fields_keep_null_apart_from_empty() {
+        assert_eq!(copy_field(None, "\\N"), "\\N");
+        assert_eq!(copy_field(Some(""), "\\N"), "");
+        assert_eq!(copy_field(None, "NULL"), "NULL");
+        assert_eq!(copy_field(Some("NULL"), "NULL"), "\\116ULL");
+        assert_eq!(copy_field(Some("a\tb\\c"), "\\N"), "a\\tb\\\\c");
+    }
+
+    #[test]
+    fn csv_fields_quote_everything_but_null() {
+        assert_eq!(csv_field(None).as_deref(), Some(""));
+        assert_eq!(csv_field(Some("")).as_deref(), Some("\"\""));
+        assert_eq!(csv_field(Some("say \"hi\"")).as_deref(), Some("\"say \"\"hi\"\"\""));
+        assert_eq!(csv_field(Some("a\n\\.\nb")), None);
+    }
+
+    #[test]
+    fn intervals_are_cast() {
+        assert_eq!(typed_literal(Type::INTERVAL.oid(), "'1 day 02:03:04'".to_string(), Dialect::Postgres), "'1 day 02:03:04'::interval");
+        assert_eq!(typed_literal(Type::INTERVAL.oid(), "'-1 days +02:03:04'".to_string(), Dialect::Generic), "CAST('-1 days +02:03:04' AS interval)");
+        assert_eq!(typed_literal(Type::INTERVAL.oid(), "NULL".to_string(), Dialect::Postgres), "NULL");
+    }
+
+    #[test]
+    fn keywords_are_recased() {
+        assert_eq!(recase_keywords("CREATE TABLE orders (id integer NOT NULL);", KeywordCase::Lower), "create table orders (id integer not null);");
+        assert_eq!(recase_keywords("alter table Orders add primary key (id);", KeywordCase::Upper), "ALTER TABLE Orders ADD PRIMARY KEY (id);");
+        assert_eq!(recase_keywords("CREATE TABLE t;", KeywordCase::Preserve), "CREATE TABLE t;");
+    }
+
+    #[test]
+    fn quoted_names_and_literals_keep_their_case() {
+        assert_eq!(recase_keywords(r#"CREATE TABLE "TABLE" ("Select" text DEFAULT 'NOT NULL');"#, KeywordCase::Lower), r#"create table "TABLE" ("Select" text default 'NOT NULL');"#);
+        assert_eq!(recase_keywords("INSERT INTO `Order` VALUES ('it''s NULL');", KeywordCase::Lower), "insert into `Order` values ('it''s NULL');");
+    }
+
+    #[test]
+    fn dollar_quoted_bodies_keep_their_case() {
+        assert_eq!(
+            recase_keywords("AS $fn$ SELECT NULL $fn$ LANGUAGE sql;", KeywordCase::Lower),
+            "as $fn$ SELECT NULL $fn$ LANGUAGE sql;",
+        );
+        assert_eq!(recase_keywords("AS $$BEGIN RETURN; END$$;", KeywordCase::Lower), "as $$BEGIN RETURN; END$$;");
+        // $1 is a parameter, not a quote
+        assert_eq!(recase_keywords("SELECT $1 FROM t", KeywordCase::Lower), "select $1 from t");
+    }
+
+    #[test]
+    fn comments_keep_their_case() {
+        assert_eq!(recase_keywords("-- Name: orders; Type: TABLE", KeywordCase::Lower), "-- Name: orders; Type: TABLE");
+        assert_eq!(recase_keywords("DROP TABLE t; -- DROP TABLE", KeywordCase::Lower), "drop table t; -- DROP TABLE");
+        assert_eq!(recase_keywords("\\connect NULL", KeywordCase::Lower), "\\connect NULL");
+    }
+}
@@ -0,0 +1,648 @@
+use std::cell::Cell;
+use std::error::Error;
+use std::time::Instant;
+
+use tokio_postgres::Client;
+
+use crate::export::{self, SourceColumn, ValueKind};
+use crate::{ColumnFilter, DumpTarget, FormatStyle, Opt, RowFilter, Section, dump_skipped_relations_to, objects_on_left_out_columns, trigger_names};
+
+// A value of `column` as a MySQL literal. Values MySQL has no form for are
+// written as NULL.
+fn literal(column: &MysqlColumn, value: Option<&str>) -> String {
+    match value {
+        None => "NULL".to_string(),
+        Some(value) if no_mysql_value(column, value) => "NULL".to_string(),
+        Some(value) => match column.kind {
+            ValueKind::Number | ValueKind::Bool | ValueKind::Money => value.to_string(),
+            ValueKind::Bytes => format!("X'{}'", value),
+            ValueKind::Bits => format!("b'{}'", value),
+            _ => quote_string(value),
+        },
+    }
+}
+
+// NaN and the infinities of numbers, and infinite or BC dates and
+// timestamps, which DATE and DATETIME can't hold
+fn no_mysql_value(column: &MysqlColumn, value: &str) -> bool {
+    match column.kind {
+        ValueKind::Number | ValueKind::Money => value.parse::<f64>().is_ok_and(|number| !number.is_finite()),
+        _ if column.data_type.starts_with("DATE") => value.ends_with("infinity") || value.ends_with(" BC"),
+        _ => false,
+    }
+}
+
+// A column as MySQL gets it
+struct MysqlColumn {
+    name: String,
+    data_type: String,
+    kind: ValueKind,
+    not_null: bool,
+    default: Option<String>,
+    auto_increment: bool,
+    // What didn't carry over, kept in the column's COMMENT
+    notes: Vec<String>,
+}
+
+impl MysqlColumn {
+    fn definition(&self, width: usize) -> String {
+        let mut definition = format!("{:width$} {}", quote_ident(&self.name), self.data_type, width = width);
+        if self.not_null {
+            definition.push_str(" NOT NULL");
+        }
+        if let Some(default) = &self.default {
+            definition.push_str(&format!(" DEFAULT {}", default));
+        }
+        if self.auto_increment {
+            definition.push_str(" AUTO_INCREMENT");
+        }
+        if !self.notes.is_empty() {
+            definition.push_str(&format!(" COMMENT {}", quote_string(&self.notes.join("; "))));
+        }
+        definition
+    }
+
+    // The column as part of an index; MySQL indexes long text only by a
+    // prefix and JSON not at all
+    fn key_part(&self) -> Option<String> {
+        match self.data_type.as_str() {
+            "JSON" => None,
+            "LONGTEXT" | "LONGBLOB" => Some(format!("{}(255)", quote_ident(&self.name))),
+            _ => Some(quote_ident(&self.name)),
+        }
+    }
+}
+
+// Quotes a MySQL identifier with backticks
+fn quote_ident(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+// A MySQL string literal. Backslashes escape by default there, and line
+// breaks are escaped as mysqldump does so each statement stays on one line.
+// Quotes are doubled rather than escaped, which the keyword and terminator
+// scanning of DumpTarget reads correctly.
+fn quote_string(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('\'');
+    for c in value.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '\'' => literal.push_str("''"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\u{1a}' => literal.push_str("\\Z"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('\'');
+    literal
+}
+
+// Maps a type as format_type writes it to a MySQL type, with how its values
+// are written and a note when the type had no counterpart
+fn map_type(pg_type: &str, enum_labels: &[String]) -> (String, ValueKind, Option<String>) {
+    if !enum_labels.is_empty() {
+        let labels: Vec<String> = enum_labels.iter().map(|label| quote_string(label)).collect();
+        return (format!("ENUM({})", labels.join(", ")), ValueKind::Text, None);
+    }
+    if pg_type.ends_with("[]") {
+        return ("JSON".to_string(), ValueKind::Array, None);
+    }
+
+    // timestamp(3) without time zone has its modifier in the middle
+    let (name, modifier) = match (pg_type.find('('), pg_type.find(')')) {
+        (Some(open), Some(close)) if open < close => (
+            format!("{}{}", &pg_type[..open], &pg_type[close + 1..]),
+            Some(&pg_type[open + 1..close]),
+        ),
+        _ => (pg_type.to_string(), None),
+    };
+    let length = modifier.and_then(|modifier| modifier.parse::<u32>().ok());
+    let precision = modifier.unwrap_or("6");
+
+    let mapped = match name.as_str() {
+        "smallint" => Some(("SMALLINT".to_string(), ValueKind::Number)),
+        "integer" => Some(("INT".to_string(), ValueKind::Number)),
+        "bigint" => Some(("BIGINT".to_string(), ValueKind::Number)),
+        "real" => Some(("FLOAT".to_string(), ValueKind::Number)),
+        "double precision" => Some(("DOUBLE".to_string(), ValueKind::Number)),
+        "numeric" => Some((format!("DECIMAL({})", modifier.unwrap_or("65,30")), ValueKind::Number)),
+        "money" => Some(("DECIMAL(19,2)".to_string(), ValueKind::Money)),
+        "boolean" => Some(("TINYINT(1)".to_string(), ValueKind::Bool)),
+        // utf8mb4 rows hold at most 16383 characters of VARCHAR
+        "character varying" => Some(match length {
+            Some(length) if length <= 16383 => (format!("VARCHAR({})", length), ValueKind::Text),
+            _ => ("LONGTEXT".to_string(), ValueKind::Text),
+        }),
+        "character" => Some(match length {
+            Some(length) if length <= 255 => (format!("CHAR({})", length), ValueKind::Text),
+            _ => ("LONGTEXT".to_string(), ValueKind::Text),
+        }),
+        "text" | "name" | "citext" => Some(("LONGTEXT".to_string(), ValueKind::Text)),
+        "bytea" => Some(("LONGBLOB".to_string(), ValueKind::Bytes)),
+        "date" => Some(("DATE".to_string(), ValueKind::Text)),
+        "timestamp without time zone" => Some((format!("DATETIME({})", precision), ValueKind::Text)),
+        "timestamp with time zone" => {
+            return (format!("DATETIME({})", precision), ValueKind::Timestamptz, Some("timestamp with time zone, stored in UTC".to_string()));
+        }
+        "time without time zone" => Some((format!("TIME({})", precision), ValueKind::Text)),
+        "json" | "jsonb" => Some(("JSON".to_string(), ValueKind::Text)),
+        "uuid" => Some(("CHAR(36)".to_string(), ValueKind::Text)),
+        "inet" | "cidr" => Some(("VARCHAR(43)".to_string(), ValueKind::Text)),
+        "macaddr" => Some(("VARCHAR(17)".to_string(), ValueKind::Text)),
+        "bit" => match length {
+            Some(length) if length <= 64 => Some((format!("BIT({})", length), ValueKind::Bits)),
+            _ => None,
+        },
+        _ => None,
+    };
+    match mapped {
+        Some((data_type, kind)) => (data_type, kind, None),
+        None => ("LONGTEXT".to_string(), ValueKind::Text, Some(format!("{}, stored as text", pg_type))),
+    }
+}
+
+// Maps a column default to MySQL: literals, the current time and date, and
+// random UUIDs. Text, blob and JSON columns only take expression defaults.
+fn map_default(expr: &str, column: &MysqlColumn) -> Option<String> {
+    let literal = if let Some((value, rest)) = export::string_literal(expr) {
+        if !rest.is_empty() && !rest.starts_with("::") {
+            return None;
+        }
+        match column.kind {
+            ValueKind::Bool => if value == "true" { "1" } else { "0" }.to_string(),
+            ValueKind::Number | ValueKind::Money if value.parse::<f64>().is_ok() => value,
+            _ => quote_string(&value),
+        }
+    } else if expr.parse::<f64>().is_ok() {
+        expr.to_string()
+    } else {
+        match expr {
+            "true" => "1".to_string(),
+            "false" => "0".to_string(),
+            "now()" | "CURRENT_TIMESTAMP" | "LOCALTIMESTAMP" | "transaction_timestamp()" | "statement_timestamp()" => {
+                // CURRENT_TIMESTAMP must match the column's fractional digits
+                let digits = column.data_type.strip_prefix("DATETIME").unwrap_or_default();
+                return column.data_type.starts_with("DATETIME").then(|| format!("CURRENT_TIMESTAMP{}", digits));
+            }
+            "CURRENT_DATE" => return Some("(CURRENT_DATE)".to_string()),
+            "gen_random_uuid()" | "uuid_generate_v4()" => return Some("(UUID())".to_string()),
+            _ => return None,
+        }
+    };
+    match column.data_type.as_str() {
+        "LONGTEXT" | "LONGBLOB" | "JSON" => Some(format!("({})", literal)),
+        _ => Some(literal),
+    }
+}
+
+fn map_column(source: &SourceColumn) -> MysqlColumn {
+    let (data_type, kind, note) = map_type(&source.pg_type, &source.enum_labels);
+    let mut column = MysqlColumn {
+        name: source.name.clone(),
+        data_type,
+        kind,
+        not_null: source.not_null,
+        default: None,
+        auto_increment: source.sequence && kind == ValueKind::Number,
+        notes: note.into_iter().collect(),
+    };
+    match &source.default {
+        Some(expr) if source.generated => column.notes.push(format!("generated as {} in PostgreSQL, not converted", expr)),
+        Some(expr) if !column.auto_increment => match map_default(expr, &column) {
+            Some(default) => column.default = Some(default),
+            None => column.notes.push(format!("default {} not converted", expr)),
+        },
+        _ => {}
+    }
+    column
+}
+
+// The MySQL type a column of `pg_type` gets, with what doesn't carry over,
+// for --help-formats
+pub fn describe_type(pg_type: &str, enum_labels: &[String]) -> (String, Vec<String>) {
+    let column = map_column(&SourceColumn {
+        name: "value".to_string(),
+        pg_type: pg_type.to_string(),
+        not_null: false,
+        default: None,
+        sequence: false,
+        generated: false,
+        enum_labels: enum_labels.to_vec(),
+    });
+    (column.data_type, column.notes)
+}
+
+// InnoDB takes at most 1017 columns, fewer than PostgreSQL's 1600
+const MAX_COLUMNS: usize = 1017;
+
+// The bytes a row may take, not counting the text and blob values kept
+// outside of it
+const MAX_ROW_BYTES: usize = 65535;
+
+// The most bytes a column takes in the row: utf8mb4 characters are up to 4
+// bytes, and text, blob and JSON values leave a 12-byte pointer behind
+fn row_bytes(data_type: &str) -> usize {
+    let length = |prefix: &str| data_type.strip_prefix(prefix)
+        .and_then(|rest| rest.strip_suffix(')'))
+        .and_then(|length| length.parse::<usize>().ok());
+    if let Some(length) = length("VARCHAR(") {
+        length * 4 + 2
+    } else if let Some(length) = length("CHAR(") {
+        length * 4
+    } else if let Some(rest) = data_type.strip_prefix("DECIMAL(") {
+        // Nine digits take four bytes
+        let digits = rest.split([',', ')']).next().and_then(|digits| digits.parse::<usize>().ok()).unwrap_or(65);
+        digits / 2 + 1
+    } else {
+        match data_type {
+            "LONGTEXT" | "LONGBLOB" | "JSON" => 12,
+            _ => 8,
+        }
+    }
+}
+
+// Turns the widest VARCHAR and CHAR columns into LONGTEXT until the row fits
+// in MySQL's row size, which PostgreSQL has no counterpart of. Columns of a
+// key, which LONGTEXT could only be a prefix of, go last. Returns how many
+// columns were changed.
+fn fit_row_size(columns: &mut [MysqlColumn], keyed: &[&String]) -> usize {
+    let mut changed = 0;
+    while columns.iter().map(|column| row_bytes(&column.data_type)).sum::<usize>() > MAX_ROW_BYTES {
+        let Some(widest) = columns.iter_mut()
+            .filter(|column| column.data_type.starts_with("VARCHAR(") || column.data_type.starts_with("CHAR("))
+            .max_by_key(|column| (!keyed.contains(&&column.name), row_bytes(&column.data_type)))
+        else {
+            break;
+        };
+        widest.notes.push(format!("{} in PostgreSQL, stored as LONGTEXT to keep the row within MySQL's {} bytes", widest.data_type, MAX_ROW_BYTES));
+        widest.data_type = "LONGTEXT".to_string();
+        // Text columns only take expression defaults
+        if let Some(default) = &mut widest.default
+            && !default.starts_with('(') {
+            *default = format!("({})", default);
+        }
+        changed += 1;
+    }
+    changed
+}
+
+// Why MySQL can't take a table of these columns at all, if it can't
+fn unsupported_table(columns: &[MysqlColumn]) -> Option<String> {
+    if columns.is_empty() {
+        Some("a table without columns, which MySQL doesn't allow".to_string())
+    } else if columns.len() > MAX_COLUMNS {
+        Some(format!("a table of {} columns, over the {} InnoDB allows", columns.len(), MAX_COLUMNS))
+    } else {
+        None
+    }
+}
+
+async fn fetch_columns(client: &Client, table_name: &str, column_filter: &ColumnFilter, opt: &Opt) -> Result<Vec<MysqlColumn>, Box<dyn Error>> {
+    Ok(export::fetch_columns(client, table_name, column_filter, opt).await?.iter().map(map_column).collect())
+}
+
+// Writes CREATE TABLE statements for MySQL, with the primary key, unique
+// constraints, foreign keys and plain indexes inline. Foreign key checks are
+// off for the rest of the load, so tables and rows may come in any order.
+// What has no MySQL form is listed in -- Not converted: lines.
+async fn dump_tables_to(client: &Client, target: &mut DumpTarget<'_>, table_names: &[String], column_filter: &ColumnFilter, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    target.write_line("-- Database schema definition for MySQL (tables, keys, indexes)")?;
+    target.write_line("SET NAMES utf8mb4;")?;
+    target.write_line("SET FOREIGN_KEY_CHECKS = 0;")?;
+    // A 0 loaded into an AUTO_INCREMENT column would otherwise be replaced
+    // by the next value
+    target.write_line("SET SESSION sql_mode = CONCAT(@@sql_mode, ',NO_AUTO_VALUE_ON_ZERO');")?;
+    target.write_line("")?;
+
+    let on_left_out_columns = objects_on_left_out_columns(client, table_names, column_filter).await?;
+
+    for table_name in table_names {
+        target.write_line(&format!("-- Table: {}", table_name))?;
+        let mut columns = fetch_columns(client, table_name, column_filter, opt).await?;
+        if let Some(reason) = unsupported_table(&columns) {
+            target.write_line(&format!("-- Not converted: {}", reason))?;
+            target.write_line("")?;
+            eprintln!("Warning: table {} is left out, as {}", table_name, reason);
+            continue;
+        }
+        let constraints = export::fetch_constraints(client, table_name, &on_left_out_columns).await?;
+        let indexes = export::fetch_indexes(client, table_name, &on_left_out_columns, opt).await?;
+
+        let keyed: Vec<&String> = constraints.iter().flat_map(|constraint| &constraint.columns)
+            .chain(indexes.iter().flat_map(|index| &index.columns))
+            .collect();
+        let widened = fit_row_size(&mut columns, &keyed);
+        if widened > 0 {
+            eprintln!("Warning: rows of table {} are too wide for MySQL; {} columns are stored as LONGTEXT, noted in their comments", table_name, widened);
+        }
+
+        // MySQL takes one AUTO_INCREMENT column per table, and only one that
+        // leads a key; other serial and identity columns lose their default
+        let leading: Vec<&String> = constraints.iter()
+            .filter(|constraint| constraint.kind == "p" || constraint.kind == "u")
+            .filter_map(|constraint| constraint.columns.first())
+            .chain(indexes.iter().filter(|index| index.unique && index.simple).filter_map(|index| index.columns.first()))
+            .collect();
+        let mut auto_increment = false;
+        for column in columns.iter_mut().filter(|column| column.auto_increment) {
+            if !auto_increment && leading.contains(&&column.name) {
+                auto_increment = true;
+                continue;
+            }
+            column.auto_increment = false;
+            column.notes.push("filled from a sequence in PostgreSQL, not AUTO_INCREMENT: MySQL allows one such column per table, leading a key".to_string());
+        }
+
+        let mut items: Vec<String> = Vec::new();
+        let mut not_converted: Vec<String> = Vec::new();
+
+        let name_width = if target.style == FormatStyle::Pretty {
+            columns.iter().map(|column| quote_ident(&column.name).chars().count()).max().unwrap_or(0)
+        } else {
+            0
+        };
+        items.extend(columns.iter().map(|column| column.definition(name_width)));
+
+        let key_parts = |names: &[String]| -> Option<Vec<String>> {
+            names.iter()
+                .map(|name| columns.iter().find(|column| &column.name == name).and_then(MysqlColumn::key_part))
+                .collect()
+        };
+
+        for constraint in constraints {
+            let mut parts = key_parts(&constraint.columns);
+            // A foreign key can't use a prefix of its columns, and a key
+            // unique on a prefix would turn away rows that differ after it
+            if parts.as_ref().is_some_and(|parts| parts.iter().any(|part| part.ends_with("(255)"))) {
+                parts = None;
+            }
+            let item = match (constraint.kind.as_str(), parts) {
+                ("p", Some(parts)) => Some(format!("PRIMARY KEY ({})", parts.join(", "))),
+                ("u", Some(parts)) => Some(format!("UNIQUE KEY {} ({})", quote_ident(&constraint.name), parts.join(", "))),
+                ("f", Some(parts)) => {
+                    let actions: Option<Vec<&str>> = [("UPDATE", constraint.on_update.as_str()), ("DELETE", constraint.on_delete.as_str())].iter()
+                        .map(|&(event, action)| match action {
+                            "a" => Some(""),
+                            "r" => Some(if event == "UPDATE" { " ON UPDATE RESTRICT" } else { " ON DELETE RESTRICT" }),
+                            "c" => Some(if event == "UPDATE" { " ON UPDATE CASCADE" } else { " ON DELETE CASCADE" }),
+                            "n" => Some(if event == "UPDATE" { " ON UPDATE SET NULL" } else { " ON DELETE SET NULL" }),
+                            // InnoDB rejects SET DEFAULT
+                            _ => None,
+                        })
+                        .collect();
+                    let referenced: Vec<String> = constraint.referenced_columns.iter().map(|name| quote_ident(name)).collect();
+                    actions.map(|actions| format!(
+                        "CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} ({}){}",
+                        quote_ident(&constraint.name), parts.join(", "),
+                        quote_ident(constraint.references.as_deref().unwrap_or_default()), referenced.join(", "), actions.concat()
+                    ))
+                }
+                _ => None,
+            };
+            match item {
+                Some(item) => items.push(item),
+                None => not_converted.push(format!("constraint {} {}", constraint.name, constraint.definition)),
+            }
+        }
+
+        // Indexes behind constraints came with them above
+        for index in indexes {
+            let parts = key_parts(&index.columns)
+                .filter(|parts| index.simple && !(index.unique && parts.iter().any(|part| part.ends_with("(255)"))));
+            match parts {
+                Some(parts) => items.push(format!(
+                    "{}KEY {} ({})",
+                    if index.unique { "UNIQUE " } else { "" }, quote_ident(&index.name), parts.join(", ")
+                )),
+                None => not_converted.push(format!("index {}", index.definition)),
+            }
+        }
+
+        target.write_block(
+            &format!("CREATE TABLE {} (", quote_ident(table_name)),
+            &items,
+            ") ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;",
+        )?;
+        export::write_not_converted(target, &not_converted)?;
+        if !not_converted.is_empty() {
+            eprintln!("Warning: {} constraints or indexes of table {} have no MySQL form; they are listed after its CREATE TABLE", not_converted.len(), table_name);
+        }
+        target.write_line("")?;
+    }
+
+    // Views and partitioned tables are left out, as for PostgreSQL
+    dump_skipped_relations_to(client, target).await?;
+    Ok(())
+}
+
+// Writes a table's rows as MySQL INSERT statements, read as they arrive
+async fn dump_table_data_to(client: &Client, target: &mut DumpTarget<'_>, table_name: &str, row_filter: &RowFilter, column_filter: &ColumnFilter, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    let columns = fetch_columns(client, table_name, column_filter, opt).await?;
+    if unsupported_table(&columns).is_some() {
+        return Ok(());
+    }
+    target.write_line(&format!("-- Data for table: {}", table_name))?;
+
+    let select_list: Vec<String> = columns.iter().map(|column| column.kind.select(&column.name)).collect();
+    let names: Vec<String> = columns.iter().map(|column| quote_ident(&column.name)).collect();
+    let prefix = format!("INSERT INTO {} ({}) VALUES (", quote_ident(table_name), names.join(", "));
+    let nulled = Cell::new(0);
+    let left_out = Cell::new(0);
+    let statement = |values: &[Option<&str>]| {
+        let lost: Vec<(&MysqlColumn, &str)> = columns.iter().zip(values)
+            .filter_map(|(column, &value)| value.filter(|value| no_mysql_value(column, value)).map(|value| (column, value)))
+            .collect();
+        let described: Vec<String> = lost.iter().map(|(column, value)| format!("{} {}", column.name, value)).collect();
+        // A NOT NULL column can't take the NULL, so the row is only listed,
+        // with the values as PostgreSQL has them
+        if lost.iter().any(|(column, _)| column.not_null) {
+            left_out.set(left_out.get() + 1);
+            let literals: Vec<String> = columns.iter().zip(values)
+                .map(|(column, &value)| match value {
+                    Some(value) if no_mysql_value(column, value) => quote_string(value),
+                    _ => literal(column, value),
+                })
+                .collect();
+            return format!("-- Not converted, as NULL can't stand in for {} in NOT NULL columns: {}{});", described.join(", "), prefix, literals.join(", "));
+        }
+        let literals: Vec<String> = columns.iter().zip(values)
+            .map(|(column, &value)| literal(column, value))
+            .collect();
+        let mut statement = format!("{}{});", prefix, literals.join(", "));
+        if !lost.is_empty() {
+            nulled.set(nulled.get() + lost.len());
+            statement.push_str(&format!(" -- Not converted: {}", described.join(", ")));
+        }
+        statement
+    };
+    export::write_rows(client, target, table_name, &select_list, statement, row_filter, opt).await?;
+    if nulled.get() > 0 {
+        eprintln!("Warning: {} NaN, infinite or BC values in table {} have no MySQL form and were written as NULL", nulled.get(), table_name);
+    }
+    if left_out.get() > 0 {
+        eprintln!("Warning: {} rows of table {} have NaN, infinite or BC values in NOT NULL columns; they are listed as not converted", left_out.get(), table_name);
+    }
+    target.write_line("")?;
+    Ok(())
+}
+
+// Writes a section of the dump for --dialect mysql. Roles, triggers and the
+// other PostgreSQL-only sections become a note at most.
+pub async fn dump_section_to(client: &Client, target: &mut DumpTarget<'_>, opt: &Opt, section: &Section, row_filter: &RowFilter, column_filter: &ColumnFilter) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    match section {
+        Section::Roles => {
+            target.write_line("-- Not converted: roles and privileges")?;
+            target.write_line("")?;
+        }
+        Section::Schema(table_names) => {
+            dump_tables_to(client, target, table_names, column_filter, opt).await?;
+            target.stats.record("tables", started);
+        }
+        Section::Truncate(table_names) => {
+            target.write_line("-- Empty tables before loading")?;
+            for table_name in table_names {
+                target.write_line(&format!("TRUNCATE TABLE {};", quote_ident(table_name)))?;
+            }
+            target.write_line("")?;
+        }
+        Section::Data(table_name) => {
+            dump_table_data_to(client, target, table_name, row_filter, column_filter, opt).await?;
+            target.stats.tables += 1;
+            target.stats.record("data", started);
+        }
+        Section::LoadFinish => {
+            target.write_line("-- End of data load")?;
+            target.write_line("SET FOREIGN_KEY_CHECKS = 1;")?;
+            target.write_line("")?;
+        }
+        Section::Triggers(table_names) => {
+            let triggers = trigger_names(client, table_names, opt).await?;
+            target.write_line("-- Not converted: triggers and their functions")?;
+            for (table_name, trigger) in &triggers {
+                target.write_line(&format!("--   trigger {} on {}", trigger, table_name))?;
+            }
+            target.write_line("")?;
+        }
+        // Foreign keys are already inline, with checks off during the load
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, data_type: &str) -> MysqlColumn {
+        MysqlColumn {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            kind: ValueKind::Text,
+            not_null: false,
+            default: None,
+            auto_increment: false,
+            notes: Vec::new(),
+        }
+    }
+
+    fn varchars(count: usize, length: usize) -> Vec<MysqlColumn> {
+        (0..count).map(|i| column(&format!("c{}", i), &format!("VARCHAR({})", length))).collect()
+    }
+
+    #[test]
+    fn values_without_a_mysql_form_become_null() {
+        let double = MysqlColumn { kind: ValueKind::Number, ..column("ratio", "DOUBLE") };
+        for value in ["NaN", "Infinity", "-Infinity"] {
+            assert!(no_mysql_value(&double, value));
+            assert_eq!(literal(&double, Some(value)), "NULL");
+        }
+        assert_eq!(literal(&double, Some("1.5")), "1.5");
+        let decimal = MysqlColumn { kind: ValueKind::Number, ..column("amount", "DECIMAL(65,30)") };
+        assert!(no_mysql_value(&decimal, "NaN"));
+
+        let date = column("day", "DATE");
+        let datetime = MysqlColumn { kind: ValueKind::Timestamptz, ..column("at", "DATETIME(6)") };
+        for value in ["infinity", "-infinity", "0044-03-15 BC"] {
+            assert!(no_mysql_value(&date, value));
+            assert_eq!(literal(&date, Some(value)), "NULL");
+        }
+        assert!(no_mysql_value(&datetime, "0044-03-15 12:00:00 BC"));
+        assert_eq!(literal(&date, Some("2026-10-16")), "'2026-10-16'");
+        assert_eq!(literal(&datetime, Some("2026-10-16 12:00:00")), "'2026-10-16 12:00:00'");
+
+        // Text that reads like them is just text
+        assert_eq!(literal(&column("note", "LONGTEXT"), Some("infinity")), "'infinity'");
+    }
+
+    #[test]
+    fn unbounded_types_map_without_a_length() {
+        assert_eq!(map_type("character varying", &[]).0, "LONGTEXT");
+        assert_eq!(map_type("character varying(20)", &[]).0, "VARCHAR(20)");
+        assert_eq!(map_type("numeric", &[]).0, "DECIMAL(65,30)");
+        assert_eq!(map_type("numeric(7,2)", &[]).0, "DECIMAL(7,2)");
+    }
+
+    #[test]
+    fn row_bytes_counts_utf8mb4_characters() {
+        assert_eq!(row_bytes("VARCHAR(200)"), 802);
+        assert_eq!(row_bytes("CHAR(10)"), 40);
+        assert_eq!(row_bytes("DECIMAL(10,2)"), 6);
+        assert_eq!(row_bytes("LONGTEXT"), 12);
+        assert_eq!(row_bytes("JSON"), 12);
+        assert_eq!(row_bytes("BIGINT"), 8);
+    }
+
+    #[test]
+    fn fit_row_size_widens_until_the_row_fits() {
+        let mut columns = varchars(100, 200);
+        assert_eq!(fit_row_size(&mut columns, &[]), 19);
+        let widened: Vec<&MysqlColumn> = columns.iter().filter(|column| column.data_type == "LONGTEXT").collect();
+        assert_eq!(widened.len(), 19);
+        assert!(widened.iter().all(|column| column.notes[0].starts_with("VARCHAR(200) in PostgreSQL")));
+        assert!(columns.iter().map(|column| row_bytes(&column.data_type)).sum::<usize>() <= MAX_ROW_BYTES);
+    }
+
+    #[test]
+    fn fit_row_size_leaves_narrow_rows_alone() {
+        let mut columns = varchars(50, 200);
+        assert_eq!(fit_row_size(&mut columns, &[]), 0);
+        assert!(columns.iter().all(|column| column.data_type == "VARCHAR(200)"));
+    }
+
+    #[test]
+    fn fit_row_size_widens_key_columns_last() {
+        let mut columns = varchars(80, 200);
+        columns.push(column("code", "VARCHAR(1000)"));
+        let code = "code".to_string();
+        assert_eq!(fit_row_size(&mut columns, &[&code]), 4);
+        assert_eq!(columns.last().unwrap().data_type, "VARCHAR(1000)");
+    }
+
+    #[test]
+    fn fit_row_size_wraps_defaults_in_parentheses() {
+        let mut columns = varchars(100, 200);
+        for column in &mut columns {
+            column.default = Some("'x'".to_string());
+        }
+        fit_row_size(&mut columns, &[]);
+        let widened = columns.iter().find(|column| column.data_type == "LONGTEXT").unwrap();
+        assert_eq!(widened.default.as_deref(), Some("('x')"));
+    }
+
+    #[test]
+    fn tables_over_the_column_limit_are_left_out() {
+        assert_eq!(unsupported_table(&varchars(MAX_COLUMNS, 1)), None);
+        assert_eq!(
+            unsupported_table(&varchars(MAX_COLUMNS + 1, 1)).as_deref(),
+            Some("a table of 1018 columns, over the 1017 InnoDB allows")
+        );
+    }
+
+    #[test]
+    fn tables_without_columns_are_left_out() {
+        assert_eq!(unsupported_table(&[]).as_deref(), Some("a table without columns, which MySQL doesn't allow"));
+    }
+}
@@ -0,0 +1,89 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Location of the password file when neither --passfile nor PGPASSFILE is set
+pub fn default_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("postgresql").join("pgpass.conf"))
+    } else {
+        std::env::var_os("HOME").map(|dir| PathBuf::from(dir).join(".pgpass"))
+    }
+}
+
+// Finds the password of the first line matching the connection, following
+// libpq's rules: fields are host:port:database:username:password, `*` matches
+// anything, and `\` escapes a literal `:` or `\`. A missing file is not an
+// error; a file readable by group or others is ignored with a warning.
+pub fn lookup(path: &Path, host: &str, port: u16, dbname: &str, user: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(None),
+    };
+
+    if !metadata.is_file() {
+        eprintln!("Warning: password file {} is not a plain file", path.display());
+        return Ok(None);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o077 != 0 {
+            eprintln!(
+                "Warning: password file {} has group or world access; permissions should be u=rw (0600) or less",
+                path.display()
+            );
+            return Ok(None);
+        }
+    }
+
+    let contents = fs::read_to_string(path)?;
+    let port = port.to_string();
+    let wanted = [host, port.as_str(), dbname, user];
+
+    for line in contents.lines() {
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+
+        let fields = split_fields(line);
+        if fields.len() < 5 {
+            continue;
+        }
+
+        let matches = fields.iter()
+            .zip(wanted.iter())
+            .all(|(field, value)| field == "*" || field == value);
+
+        if matches {
+            return Ok(Some(fields[4].clone()));
+        }
+    }
+
+    Ok(None)
+}
+
+// Splits a line on unescaped colons, removing the escaping backslashes
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            ':' => {
+                fields.push(std::mem::take(&mut current));
+            }
+            _ => current.push(c),
+        }
+    }
+
+    fields.push(current);
+    fields
+}
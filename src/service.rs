@@ -0,0 +1,86 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::ConfigOption;
+
+// Connection parameters a service may set, with the long option each one feeds
+const SERVICE_KEYS: &[(&str, &str)] = &[
+    ("host", "host"),
+    ("port", "port"),
+    ("dbname", "dbname"),
+    ("user", "user"),
+    ("password", "password"),
+    ("passfile", "passfile"),
+    ("connect_timeout", "connect-timeout"),
+    ("keepalives_idle", "keepalives-idle"),
+    ("keepalives_interval", "keepalives-interval"),
+    ("tcp_user_timeout", "tcp-user-timeout"),
+    ("target_session_attrs", "target-session-attrs"),
+];
+
+// The per-user service file: PGSERVICEFILE, or ~/.pg_service.conf
+fn user_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("PGSERVICEFILE") {
+        return Some(PathBuf::from(path));
+    }
+    if cfg!(windows) {
+        std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("postgresql").join(".pg_service.conf"))
+    } else {
+        std::env::var_os("HOME").map(|dir| PathBuf::from(dir).join(".pg_service.conf"))
+    }
+}
+
+// The system-wide service file, when PGSYSCONFDIR names its directory
+fn system_path() -> Option<PathBuf> {
+    std::env::var_os("PGSYSCONFDIR").map(|dir| PathBuf::from(dir).join("pg_service.conf"))
+}
+
+// Reads the connection parameters of service `name`, as libpq does: the user's
+// file is searched first, then the system-wide one, and the first file with a
+// [name] section supplies all of them. Parameters this tool has no option for,
+// such as sslmode, are skipped with a warning.
+pub fn load(name: &str) -> Result<Vec<ConfigOption>, Box<dyn Error>> {
+    for path in [user_path(), system_path()].into_iter().flatten() {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Could not read service file {}: {}", path.display(), e).into()),
+        };
+        if let Some(params) = find_service(&text, name) {
+            let mut options = Vec::new();
+            for (key, value) in params {
+                match SERVICE_KEYS.iter().find(|(param, _)| *param == key) {
+                    Some((_, option)) => options.push((option.to_string(), vec![value])),
+                    None => eprintln!("Warning: ignoring {} in service {}, which database-dump does not support", key, name),
+                }
+            }
+            return Ok(options);
+        }
+    }
+    Err(format!("Service {} not found in the service file", name).into())
+}
+
+// The key=value lines of the [name] section, if the file has one
+fn find_service(text: &str, name: &str) -> Option<Vec<(String, String)>> {
+    let mut params: Option<Vec<(String, String)>> = None;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            if params.is_some() {
+                break;
+            }
+            if section == name {
+                params = Some(Vec::new());
+            }
+            continue;
+        }
+        if let Some(params) = &mut params
+            && let Some((key, value)) = line.split_once('=') {
+            params.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    params
+}
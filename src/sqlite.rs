@@ -0,0 +1,394 @@
+use std::error::Error;
+use std::time::Instant;
+
+use tokio_postgres::Client;
+
+use crate::export::{self, SourceColumn, SourceConstraint, ValueKind};
+use crate::{ColumnFilter, DumpTarget, FormatStyle, Opt, RowFilter, Section, dump_skipped_relations_to, objects_on_left_out_columns, trigger_names};
+
+// A column as SQLite gets it, typed by one of its five affinities
+struct SqliteColumn {
+    name: String,
+    affinity: &'static str,
+    kind: ValueKind,
+    not_null: bool,
+    default: Option<String>,
+    // INTEGER PRIMARY KEY, SQLite's rowid, which stands in for a sequence
+    rowid: bool,
+    // CHECK (... IN (...)) for an enum
+    check: Option<String>,
+    // What didn't carry over, listed after the table
+    notes: Vec<String>,
+}
+
+impl SqliteColumn {
+    fn definition(&self, width: usize) -> String {
+        let mut definition = format!("{:width$} {}", quote_ident(&self.name), self.affinity, width = width);
+        if self.rowid {
+            definition.push_str(" PRIMARY KEY");
+        } else if self.not_null {
+            definition.push_str(" NOT NULL");
+        }
+        if let Some(default) = &self.default {
+            definition.push_str(&format!(" DEFAULT {}", default));
+        }
+        if let Some(check) = &self.check {
+            definition.push_str(&format!(" CHECK ({})", check));
+        }
+        definition
+    }
+}
+
+// Quotes a SQLite identifier with double quotes
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// A SQLite string literal; backslashes are plain characters there
+fn quote_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+// A value as a SQLite literal. NaN and the infinities have no literal, so
+// they stay text.
+fn literal(kind: ValueKind, value: Option<&str>) -> String {
+    match value {
+        None => "NULL".to_string(),
+        Some(value) => match kind {
+            ValueKind::Number | ValueKind::Money if value.parse::<f64>().is_ok_and(f64::is_finite) => value.to_string(),
+            ValueKind::Bool => value.to_string(),
+            ValueKind::Bytes => format!("X'{}'", value),
+            _ => quote_string(value),
+        },
+    }
+}
+
+// Maps a type as format_type writes it to a SQLite affinity, with how its
+// values are written and a note when values or their type don't survive
+fn map_type(pg_type: &str) -> (&'static str, ValueKind, Option<String>) {
+    if pg_type.ends_with("[]") {
+        return ("TEXT", ValueKind::Array, Some(format!("{}, stored as JSON text", pg_type)));
+    }
+
+    // timestamp(3) without time zone has its modifier in the middle
+    let (name, modifier) = match (pg_type.find('('), pg_type.find(')')) {
+        (Some(open), Some(close)) if open < close => (
+            format!("{}{}", &pg_type[..open], &pg_type[close + 1..]),
+            Some(&pg_type[open + 1..close]),
+        ),
+        _ => (pg_type.to_string(), None),
+    };
+
+    match name.as_str() {
+        "smallint" | "integer" | "bigint" => ("INTEGER", ValueKind::Number, None),
+        "boolean" => ("INTEGER", ValueKind::Bool, None),
+        "real" | "double precision" => ("REAL", ValueKind::Number, None),
+        // NUMERIC values beyond what a double holds exactly are rounded
+        "numeric" => {
+            let precision = modifier.and_then(|modifier| modifier.split(',').next()?.trim().parse::<u32>().ok());
+            match precision {
+                Some(precision) if precision <= 15 => ("NUMERIC", ValueKind::Number, None),
+                _ => ("NUMERIC", ValueKind::Number, Some(format!("{}, kept to 15 significant digits", pg_type))),
+            }
+        }
+        "money" => ("NUMERIC", ValueKind::Money, None),
+        "bytea" => ("BLOB", ValueKind::Bytes, None),
+        "timestamp with time zone" => ("TEXT", ValueKind::Timestamptz, Some("timestamp with time zone, stored as UTC text".to_string())),
+        "text" | "character varying" | "character" | "name" | "citext" | "date" | "timestamp without time zone"
+        | "time without time zone" | "interval" | "json" | "jsonb" | "uuid" | "inet" | "cidr" | "macaddr" => ("TEXT", ValueKind::Text, None),
+        "bit" | "bit varying" => ("TEXT", ValueKind::Bits, None),
+        _ => ("TEXT", ValueKind::Text, Some(format!("{}, stored as text", pg_type))),
+    }
+}
+
+// Maps a column default to SQLite: literals and the current time and date
+fn map_default(expr: &str, kind: ValueKind) -> Option<String> {
+    if let Some((value, rest)) = export::string_literal(expr) {
+        if !rest.is_empty() && !rest.starts_with("::") {
+            return None;
+        }
+        return Some(match kind {
+            ValueKind::Bool => if value == "true" { "1" } else { "0" }.to_string(),
+            _ => literal(kind, Some(&value)),
+        });
+    }
+    if expr.parse::<f64>().is_ok() {
+        return Some(expr.to_string());
+    }
+    match expr {
+        "true" => Some("1".to_string()),
+        "false" => Some("0".to_string()),
+        "now()" | "CURRENT_TIMESTAMP" | "LOCALTIMESTAMP" | "transaction_timestamp()" | "statement_timestamp()" => Some("CURRENT_TIMESTAMP".to_string()),
+        "CURRENT_DATE" => Some("CURRENT_DATE".to_string()),
+        _ => None,
+    }
+}
+
+// Maps a column; `rowid` is set for the single integer column of a primary key
+fn map_column(source: &SourceColumn, key: &[String]) -> SqliteColumn {
+    // Enums are text checked against their labels
+    let (affinity, kind, note) = if source.enum_labels.is_empty() {
+        map_type(&source.pg_type)
+    } else {
+        ("TEXT", ValueKind::Text, None)
+    };
+    let mut column = SqliteColumn {
+        name: source.name.clone(),
+        affinity,
+        kind,
+        not_null: source.not_null,
+        default: None,
+        rowid: key.len() == 1 && key[0] == source.name && affinity == "INTEGER" && kind == ValueKind::Number,
+        check: None,
+        notes: note.into_iter().collect(),
+    };
+    if !source.enum_labels.is_empty() {
+        let labels: Vec<String> = source.enum_labels.iter().map(|label| quote_string(label)).collect();
+        column.check = Some(format!("{} IN ({})", quote_ident(&source.name), labels.join(", ")));
+    }
+    match &source.default {
+        // The rowid numbers new rows itself
+        _ if column.rowid => {}
+        _ if source.sequence => column.notes.push("values from a sequence, not generated for new rows".to_string()),
+        Some(expr) if source.generated => column.notes.push(format!("generated as {}, stored as plain values", expr)),
+        Some(expr) => match map_default(expr, kind) {
+            Some(default) => column.default = Some(default),
+            None => column.notes.push(format!("default {}", expr)),
+        },
+        None => {}
+    }
+    column
+}
+
+// Whether a CHECK constraint reads the same in SQLite: plain comparisons of
+// columns and numbers, without casts, function calls, arrays or regular
+// expressions
+fn portable_check(definition: &str) -> bool {
+    let mut previous = ' ';
+    for c in definition.chars() {
+        if c == '(' && (previous.is_alphanumeric() || previous == '_') {
+            return false;
+        }
+        previous = c;
+    }
+    definition.starts_with("CHECK ")
+        && !definition.contains("::")
+        && !definition.contains('[')
+        && !definition.contains('~')
+        && !definition.ends_with("NOT VALID")
+}
+
+// A constraint inside CREATE TABLE, or None when SQLite has no form of it
+fn table_constraint(constraint: &SourceConstraint) -> Option<String> {
+    let columns: Vec<String> = constraint.columns.iter().map(|name| quote_ident(name)).collect();
+    match constraint.kind.as_str() {
+        "p" => Some(format!("PRIMARY KEY ({})", columns.join(", "))),
+        "u" => Some(format!("CONSTRAINT {} UNIQUE ({})", quote_ident(&constraint.name), columns.join(", "))),
+        "f" => {
+            let action = |action: &str| match action {
+                "r" => "RESTRICT",
+                "c" => "CASCADE",
+                "n" => "SET NULL",
+                "d" => "SET DEFAULT",
+                _ => "",
+            };
+            let referenced: Vec<String> = constraint.referenced_columns.iter().map(|name| quote_ident(name)).collect();
+            let mut item = format!(
+                "CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} ({})",
+                quote_ident(&constraint.name), columns.join(", "),
+                quote_ident(constraint.references.as_deref().unwrap_or_default()), referenced.join(", ")
+            );
+            for (event, code) in [("UPDATE", &constraint.on_update), ("DELETE", &constraint.on_delete)] {
+                if !action(code).is_empty() {
+                    item.push_str(&format!(" ON {} {}", event, action(code)));
+                }
+            }
+            if constraint.definition.ends_with("DEFERRABLE INITIALLY DEFERRED") {
+                item.push_str(" DEFERRABLE INITIALLY DEFERRED");
+            }
+            Some(item)
+        }
+        "c" if portable_check(&constraint.definition) => {
+            Some(format!("CONSTRAINT {} {}", quote_ident(&constraint.name), constraint.definition))
+        }
+        _ => None,
+    }
+}
+
+// The SQLite type a column of `pg_type` gets, with what doesn't carry over,
+// for --help-formats
+pub fn describe_type(pg_type: &str, enum_labels: &[String]) -> (String, Vec<String>) {
+    let column = map_column(&SourceColumn {
+        name: "value".to_string(),
+        pg_type: pg_type.to_string(),
+        not_null: false,
+        default: None,
+        sequence: false,
+        generated: false,
+        enum_labels: enum_labels.to_vec(),
+    }, &[]);
+    match column.check {
+        Some(_) => (format!("{} with a CHECK of the labels", column.affinity), column.notes),
+        None => (column.affinity.to_string(), column.notes),
+    }
+}
+
+async fn fetch_columns(client: &Client, table_name: &str, column_filter: &ColumnFilter, opt: &Opt, key: &[String]) -> Result<Vec<SqliteColumn>, Box<dyn Error>> {
+    Ok(export::fetch_columns(client, table_name, column_filter, opt).await?.iter()
+        .map(|column| map_column(column, key))
+        .collect())
+}
+
+// Writes CREATE TABLE statements for SQLite, with every constraint inline
+// since its ALTER TABLE can't add them later, and plain indexes after each
+// table. What has no SQLite form, or loses something on the way, is listed
+// in -- Not converted: lines and summed up on standard error.
+async fn dump_tables_to(client: &Client, target: &mut DumpTarget<'_>, table_names: &[String], column_filter: &ColumnFilter, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    target.write_line("-- Database schema definition for SQLite (tables, keys, indexes)")?;
+    target.write_line("PRAGMA foreign_keys = OFF;")?;
+    target.write_line("")?;
+
+    let on_left_out_columns = objects_on_left_out_columns(client, table_names, column_filter).await?;
+    let mut lossy: Vec<String> = Vec::new();
+
+    for table_name in table_names {
+        target.write_line(&format!("-- Table: {}", table_name))?;
+        let constraints = export::fetch_constraints(client, table_name, &on_left_out_columns).await?;
+        let key = constraints.iter()
+            .find(|constraint| constraint.kind == "p")
+            .map(|constraint| constraint.columns.clone())
+            .unwrap_or_default();
+        let columns = fetch_columns(client, table_name, column_filter, opt, &key).await?;
+        if columns.is_empty() {
+            target.write_line("-- Not converted: a table without columns, which SQLite doesn't allow")?;
+            target.write_line("")?;
+            lossy.push(format!("{}: a table without columns, left out", table_name));
+            continue;
+        }
+        let mut not_converted: Vec<String> = columns.iter()
+            .flat_map(|column| column.notes.iter().map(move |note| format!("column {}: {}", column.name, note)))
+            .collect();
+
+        let name_width = if target.style == FormatStyle::Pretty {
+            columns.iter().map(|column| quote_ident(&column.name).chars().count()).max().unwrap_or(0)
+        } else {
+            0
+        };
+        let mut items: Vec<String> = columns.iter().map(|column| column.definition(name_width)).collect();
+
+        let rowid = columns.iter().any(|column| column.rowid);
+        for constraint in &constraints {
+            if constraint.kind == "p" && rowid {
+                continue;
+            }
+            match table_constraint(constraint) {
+                Some(item) => items.push(item),
+                None => not_converted.push(format!("constraint {} {}", constraint.name, constraint.definition)),
+            }
+        }
+
+        target.write_block(&format!("CREATE TABLE {} (", quote_ident(table_name)), &items, ");")?;
+
+        // Indexes behind constraints came with them above
+        for index in export::fetch_indexes(client, table_name, &on_left_out_columns, opt).await? {
+            if index.simple {
+                let columns: Vec<String> = index.columns.iter().map(|name| quote_ident(name)).collect();
+                target.write_line(&format!(
+                    "CREATE {}INDEX {} ON {} ({});",
+                    if index.unique { "UNIQUE " } else { "" }, quote_ident(&index.name), quote_ident(table_name), columns.join(", ")
+                ))?;
+            } else {
+                not_converted.push(format!("index {}", index.definition));
+            }
+        }
+
+        export::write_not_converted(target, &not_converted)?;
+        lossy.extend(not_converted.iter().map(|item| format!("{}: {}", table_name, item)));
+        target.write_line("")?;
+    }
+
+    // Views and partitioned tables are left out, as for PostgreSQL
+    dump_skipped_relations_to(client, target).await?;
+
+    if !lossy.is_empty() {
+        eprintln!("Warning: the SQLite export is lossy in {} places; they are listed after each CREATE TABLE:", lossy.len());
+        for item in &lossy {
+            eprintln!("  {}", item);
+        }
+    }
+    Ok(())
+}
+
+// Writes a table's rows as SQLite INSERT statements, read as they arrive
+async fn dump_table_data_to(client: &Client, target: &mut DumpTarget<'_>, table_name: &str, row_filter: &RowFilter, column_filter: &ColumnFilter, opt: &Opt) -> Result<(), Box<dyn Error>> {
+    let columns = fetch_columns(client, table_name, column_filter, opt, &[]).await?;
+    if columns.is_empty() {
+        return Ok(());
+    }
+    target.write_line(&format!("-- Data for table: {}", table_name))?;
+
+    let select_list: Vec<String> = columns.iter().map(|column| column.kind.select(&column.name)).collect();
+    let names: Vec<String> = columns.iter().map(|column| quote_ident(&column.name)).collect();
+    let prefix = format!("INSERT INTO {} ({}) VALUES (", quote_ident(table_name), names.join(", "));
+    let statement = |values: &[Option<&str>]| {
+        let literals: Vec<String> = columns.iter().zip(values)
+            .map(|(column, &value)| literal(column.kind, value))
+            .collect();
+        format!("{}{});", prefix, literals.join(", "))
+    };
+    export::write_rows(client, target, table_name, &select_list, statement, row_filter, opt).await?;
+    target.write_line("")?;
+    Ok(())
+}
+
+// Writes a section of the dump for --dialect sqlite. The load runs in one
+// transaction unless --commit-every batches it; roles, triggers and the other
+// PostgreSQL-only sections become a note at most.
+pub async fn dump_section_to(client: &Client, target: &mut DumpTarget<'_>, opt: &Opt, section: &Section, row_filter: &RowFilter, column_filter: &ColumnFilter) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    match section {
+        Section::Roles => {
+            target.write_line("-- Not converted: roles and privileges")?;
+            target.write_line("")?;
+        }
+        Section::Schema(table_names) => {
+            dump_tables_to(client, target, table_names, column_filter, opt).await?;
+            target.stats.record("tables", started);
+        }
+        Section::LoadSetup if opt.commit_every.is_none() => {
+            target.write_line("-- Data load")?;
+            target.write_line("BEGIN TRANSACTION;")?;
+            target.write_line("")?;
+        }
+        Section::Truncate(table_names) => {
+            target.write_line("-- Empty tables before loading")?;
+            for table_name in table_names {
+                target.write_line(&format!("DELETE FROM {};", quote_ident(table_name)))?;
+            }
+            target.write_line("")?;
+        }
+        Section::Data(table_name) => {
+            dump_table_data_to(client, target, table_name, row_filter, column_filter, opt).await?;
+            target.stats.tables += 1;
+            target.stats.record("data", started);
+        }
+        Section::LoadFinish => {
+            target.write_line("-- End of data load")?;
+            if opt.commit_every.is_none() {
+                target.write_line("COMMIT;")?;
+            }
+            target.write_line("PRAGMA foreign_keys = ON;")?;
+            target.write_line("")?;
+        }
+        Section::Triggers(table_names) => {
+            target.write_line("-- Not converted: triggers and their functions")?;
+            for (table_name, trigger) in &trigger_names(client, table_names, opt).await? {
+                target.write_line(&format!("--   trigger {} on {}", trigger, table_name))?;
+            }
+            target.write_line("")?;
+        }
+        // Foreign keys are already inline, with checks off during the load
+        _ => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use std::error::Error;
+use std::io::Cursor;
+
+use futures_util::{SinkExt, pin_mut};
+use tokio_postgres::Client;
+
+// Runs dump SQL the way psql would: statements are sent as-is, COPY ... FROM
+// stdin blocks are streamed through the copy protocol, and psql meta-commands
+// such as \connect are skipped.
+pub async fn apply_sql(client: &Client, sql: &str) -> Result<(), Box<dyn Error>> {
+    let mut pending = String::new();
+    let mut quote: Option<String> = None;
+    let mut lines = sql.split('\n');
+
+    while let Some(line) = lines.next() {
+        if quote.is_none() && is_copy_from_stdin(line) {
+            execute_pending(client, &mut pending).await?;
+
+            let mut data = String::new();
+            for data_line in lines.by_ref() {
+                if data_line == "\\." {
+                    break;
+                }
+                data.push_str(data_line);
+                data.push('\n');
+            }
+
+            let sink = client.copy_in(line.trim_end().trim_end_matches(';')).await?;
+            pin_mut!(sink);
+            sink.send(Cursor::new(data.into_bytes())).await?;
+            sink.finish().await?;
+            continue;
+        }
+
+        if quote.is_none() && (line.starts_with("--") || line.starts_with('\\')) {
+            continue;
+        }
+
+        // Track string literals, quoted identifiers and dollar-quoted bodies,
+        // so a line inside a multi-line value is never mistaken for a COPY,
+        // a comment or a meta-command
+        track_quotes(line, &mut quote);
+
+        pending.push_str(line);
+        pending.push('\n');
+    }
+
+    execute_pending(client, &mut pending).await
+}
+
+// Splits dump SQL into statements as apply_sql would send them: comment lines,
+// psql meta-commands and blank lines outside quoted text are dropped, and a
+// statement ends with the line that ends in a semicolon. COPY data is not
+// expected here.
+pub fn split_statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut pending = String::new();
+    let mut quote: Option<String> = None;
+
+    for line in sql.split('\n') {
+        if quote.is_none() && (line.starts_with("--") || line.starts_with('\\') || line.trim().is_empty()) {
+            continue;
+        }
+        track_quotes(line, &mut quote);
+
+        pending.push_str(line);
+        if quote.is_none() && line.trim_end().ends_with(';') {
+            statements.push(std::mem::take(&mut pending));
+        } else {
+            pending.push('\n');
+        }
+    }
+
+    if !pending.trim().is_empty() {
+        statements.push(pending.trim_end().to_string());
+    }
+    statements
+}
+
+// Updates `quote`, the delimiter that closes the open quoted text, if any,
+// after scanning `line`
+fn track_quotes(line: &str, quote: &mut Option<String>) {
+    let mut rest = line;
+    loop {
+        match quote {
+            Some(close) => match rest.find(close.as_str()) {
+                Some(end) => {
+                    rest = &rest[end + close.len()..];
+                    *quote = None;
+                }
+                None => return,
+            },
+            None => match rest.find(['\'', '"', '$']) {
+                Some(start) => {
+                    let opened = &rest[start..];
+                    let after = &opened[1..];
+                    if !opened.starts_with('$') {
+                        *quote = Some(opened[..1].to_string());
+                    } else {
+                        // $tag$ opens a dollar quote; $1 and the like don't
+                        let tag_len = after.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(after.len());
+                        if after[tag_len..].starts_with('$') && !after.starts_with(|c: char| c.is_ascii_digit()) {
+                            *quote = Some(opened[..tag_len + 2].to_string());
+                            rest = &opened[tag_len + 2..];
+                            continue;
+                        }
+                    }
+                    rest = after;
+                }
+                None => return,
+            },
+        }
+    }
+}
+
+pub fn is_copy_from_stdin(line: &str) -> bool {
+    // --keyword-case may have written the keywords in lower case
+    let line = line.trim_end().to_ascii_lowercase();
+    line.starts_with("copy ") && (line.ends_with("from stdin;") || (line.contains(" from stdin with (") && line.ends_with(");")))
+}
+
+async fn execute_pending(client: &Client, pending: &mut String) -> Result<(), Box<dyn Error>> {
+    if !pending.trim().is_empty() {
+        client.batch_execute(pending).await?;
+    }
+    pending.clear();
+    Ok(())
+}
+
+// Tables whose row count differs between the source and the reloaded copy,
+// with both counts
+pub async fn compare_row_counts(source: &Client, reloaded: &Client, table_names: &[String]) -> Result<Vec<(String, i64, i64)>, Box<dyn Error>> {
+    let mut mismatches = Vec::new();
+
+    for table_name in table_names {
+        let query = format!("SELECT count(*) FROM {}", table_name);
+        let expected: i64 = source.query_one(&query, &[]).await?.get(0);
+        let actual: i64 = reloaded.query_one(&query, &[]).await?.get(0);
+        if expected != actual {
+            mismatches.push((table_name.clone(), expected, actual));
+        }
+    }
+
+    Ok(mismatches)
+}
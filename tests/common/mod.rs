@@ -0,0 +1,129 @@
+// Helpers for the tests that dump a real database. They need a PostgreSQL
+// server on which they may create databases, given by DUMP_TEST_HOST and
+// optionally DUMP_TEST_PORT, DUMP_TEST_USER and PGPASSWORD. Without
+// DUMP_TEST_HOST they are skipped. Each test works in databases named
+// dump_test_<name>, replaced on the next run and left behind for a look.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use tokio_postgres::{Client, NoTls};
+
+// Returns the test server, or skips the test when none is configured
+#[macro_export]
+macro_rules! server_or_skip {
+    () => {
+        match common::Server::from_env() {
+            Some(server) => server,
+            None => {
+                eprintln!("skipped: set DUMP_TEST_HOST to run the tests against a PostgreSQL server");
+                return;
+            }
+        }
+    };
+}
+
+pub struct Server {
+    host: String,
+    port: u16,
+    user: String,
+    password: Option<String>,
+}
+
+impl Server {
+    pub fn from_env() -> Option<Server> {
+        Some(Server {
+            host: env::var("DUMP_TEST_HOST").ok()?,
+            port: env::var("DUMP_TEST_PORT").ok().and_then(|port| port.parse().ok()).unwrap_or(5432),
+            user: env::var("DUMP_TEST_USER").unwrap_or_else(|_| "postgres".to_string()),
+            password: env::var("PGPASSWORD").ok(),
+        })
+    }
+
+    pub async fn connect(&self, dbname: &str) -> Client {
+        let mut config = tokio_postgres::Config::new();
+        config.host(&self.host).port(self.port).user(&self.user).dbname(dbname);
+        if let Some(password) = &self.password {
+            config.password(password);
+        }
+        let (client, connection) = config.connect(NoTls).await.expect("connect to the test server");
+        tokio::spawn(async move {
+            let _ = connection.await;
+        });
+        client
+    }
+
+    // A new database for the test, named dump_test_<name>, with `setup` run in it
+    pub async fn create_database(&self, name: &str, setup: &str) -> Client {
+        let dbname = database_name(name);
+        let admin = self.connect("postgres").await;
+        admin.batch_execute(&format!("DROP DATABASE IF EXISTS {}", dbname)).await.expect("drop the old test database");
+        admin.batch_execute(&format!("CREATE DATABASE {}", dbname)).await.expect("create the test database");
+        let client = self.connect(&dbname).await;
+        client.batch_execute(setup).await.expect("set up the test database");
+        client
+    }
+
+    // database-dump with the server's host, port and user but no database
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_database-dump"));
+        command.args(["--host", &self.host, "--port", &self.port.to_string(), "--user", &self.user]);
+        command
+    }
+
+    // Runs database-dump on the test database with `args`
+    pub fn run(&self, name: &str, args: &[&str]) -> Output {
+        self.command()
+            .args(["--dbname", &database_name(name)])
+            .args(args)
+            .output()
+            .expect("run database-dump")
+    }
+
+    // The plain SQL dump of the test database, written to standard output
+    pub fn dump(&self, name: &str, args: &[&str]) -> String {
+        let output = self.run(name, &[&["--output", "-"], args].concat());
+        assert!(output.status.success(), "dump failed: {}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).expect("the dump is UTF-8")
+    }
+
+    // Loads `sql` into a new, empty database named dump_test_<name> with psql,
+    // as a user would. psql carries on past errors, such as CREATE ROLE for a
+    // role the server already has; what matters is checked on the result.
+    pub async fn reload(&self, name: &str, sql: &str) -> Client {
+        self.reload_onto(name, "", sql).await
+    }
+
+    // Like reload, into a database that has what `setup` creates, such as
+    // the objects the dump leaves out
+    pub async fn reload_onto(&self, name: &str, setup: &str, sql: &str) -> Client {
+        let client = self.create_database(name, setup).await;
+        let mut psql = Command::new("psql")
+            .args(["-X", "-q", "-h", &self.host, "-p", &self.port.to_string(), "-U", &self.user, "-d", &database_name(name)])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("run psql");
+        psql.stdin.take().expect("stdin is piped").write_all(sql.as_bytes()).expect("write to psql");
+        assert!(psql.wait().expect("wait for psql").success(), "psql failed");
+        client
+    }
+}
+
+fn database_name(name: &str) -> String {
+    format!("dump_test_{}", name)
+}
+
+// The lines of `sql` that start with `prefix`
+pub fn lines_starting<'a>(sql: &'a str, prefix: &str) -> Vec<&'a str> {
+    sql.lines().filter(|line| line.starts_with(prefix)).collect()
+}
+
+// The single text value `query` returns
+pub async fn text(client: &Client, query: &str) -> String {
+    client.query_one(query, &[]).await.expect("run the query").get(0)
+}
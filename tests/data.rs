@@ -0,0 +1,232 @@
+// How values are written in the data section of a plain dump
+
+mod common;
+
+#[tokio::test]
+async fn booleans_are_unquoted_whatever_their_value() {
+    let server = server_or_skip!();
+    server.create_database("booleans", "
+        CREATE TABLE flags (id integer, on_call boolean);
+        INSERT INTO flags VALUES (1, true), (2, false), (3, NULL);
+    ").await;
+
+    let sql = server.dump("booleans", &[]);
+    assert_eq!(common::lines_starting(&sql, "INSERT INTO flags"), [
+        "INSERT INTO flags (id, on_call) VALUES (1, TRUE);",
+        "INSERT INTO flags (id, on_call) VALUES (2, FALSE);",
+        "INSERT INTO flags (id, on_call) VALUES (3, NULL);",
+    ]);
+}
+
+#[tokio::test]
+async fn numbers_use_a_decimal_point_without_grouping() {
+    let server = server_or_skip!();
+    let source = server.create_database("numbers", "
+        CREATE TABLE amounts (id integer, s smallint, r real, d double precision);
+        INSERT INTO amounts VALUES (1, 1234, 1234.56, 1234.56), (2, -32768, 0.1, -0.001), (3, NULL, 3e38, 1e300);
+    ").await;
+
+    let sql = server.dump("numbers", &[]);
+    assert_eq!(common::lines_starting(&sql, "INSERT INTO amounts"), [
+        "INSERT INTO amounts (id, s, r, d) VALUES (1, 1234, 1234.56, 1234.56);",
+        "INSERT INTO amounts (id, s, r, d) VALUES (2, -32768, 0.1, -0.001);",
+        "INSERT INTO amounts (id, s, r, d) VALUES (3, NULL, 3e38, 1e300);",
+    ]);
+
+    // The values come back exactly
+    let reloaded = server.reload("numbers_reload", &sql).await;
+    let rows = "SELECT array_agg((s, r, d) ORDER BY id)::text FROM amounts";
+    assert_eq!(common::text(&reloaded, rows).await, common::text(&source, rows).await);
+}
+
+#[tokio::test]
+async fn money_and_bit_strings_round_trip() {
+    let server = server_or_skip!();
+    let source = server.create_database("money_bits", "
+        CREATE TABLE ledger (id integer, amount money, flags bit(4), mask bit varying(8));
+        INSERT INTO ledger VALUES (1, '1234.50', B'1010', B'1'), (2, '-0.01', B'0000', B''), (3, NULL, NULL, NULL);
+    ").await;
+
+    let sql = server.dump("money_bits", &[]);
+    assert_eq!(common::lines_starting(&sql, "INSERT INTO ledger"), [
+        "INSERT INTO ledger (id, amount, flags, mask) VALUES (1, '1234.50'::numeric::money, B'1010', B'1');",
+        "INSERT INTO ledger (id, amount, flags, mask) VALUES (2, '-0.01'::numeric::money, B'0000', B'');",
+        "INSERT INTO ledger (id, amount, flags, mask) VALUES (3, NULL, NULL, NULL);",
+    ]);
+
+    let reloaded = server.reload("money_bits_reload", &sql).await;
+    let rows = "SELECT array_agg((amount::numeric, flags, mask) ORDER BY id)::text FROM ledger";
+    assert_eq!(common::text(&reloaded, rows).await, common::text(&source, rows).await);
+}
+
+#[tokio::test]
+async fn infinities_and_nan_round_trip() {
+    let server = server_or_skip!();
+    let source = server.create_database("infinity", "
+        CREATE TABLE readings (id integer, taken timestamptz, value double precision, ratio real);
+        INSERT INTO readings VALUES
+            (1, 'infinity', 'NaN', 'Infinity'),
+            (2, '-infinity', '-Infinity', 'NaN'),
+            (3, '2024-02-29 12:00:00+00', 1.5, -0.25);
+    ").await;
+
+    let sql = server.dump("infinity", &[]);
+    assert_eq!(common::lines_starting(&sql, "INSERT INTO readings"), [
+        "INSERT INTO readings (id, taken, value, ratio) VALUES (1, 'infinity', 'NaN', 'Infinity');",
+        "INSERT INTO readings (id, taken, value, ratio) VALUES (2, '-infinity', '-Infinity', 'NaN');",
+        "INSERT INTO readings (id, taken, value, ratio) VALUES (3, '2024-02-29 12:00:00+00', 1.5, -0.25);",
+    ]);
+
+    let reloaded = server.reload("infinity_reload", &sql).await;
+    let rows = "SELECT array_agg((taken, value, ratio) ORDER BY id)::text FROM readings";
+    assert_eq!(common::text(&reloaded, rows).await, common::text(&source, rows).await);
+}
+
+#[tokio::test]
+async fn intervals_round_trip() {
+    let server = server_or_skip!();
+    let source = server.create_database("intervals", "
+        CREATE TABLE spans (id integer, length interval);
+        INSERT INTO spans VALUES (1, '1 day 02:03:04'), (2, '-1 day +02:03:04'), (3, '-3 mons'), (4, NULL);
+    ").await;
+
+    let sql = server.dump("intervals", &[]);
+    assert_eq!(common::lines_starting(&sql, "INSERT INTO spans"), [
+        "INSERT INTO spans (id, length) VALUES (1, '1 day 02:03:04'::interval);",
+        "INSERT INTO spans (id, length) VALUES (2, '-1 days +02:03:04'::interval);",
+        "INSERT INTO spans (id, length) VALUES (3, '-3 mons'::interval);",
+        "INSERT INTO spans (id, length) VALUES (4, NULL);",
+    ]);
+
+    let reloaded = server.reload("intervals_reload", &sql).await;
+    let rows = "SELECT array_agg(length ORDER BY id)::text FROM spans";
+    assert_eq!(common::text(&reloaded, rows).await, common::text(&source, rows).await);
+}
+
+#[tokio::test]
+async fn intervals_round_trip_from_sql_standard() {
+    let server = server_or_skip!();
+    let source = server.create_database("intervals_sql_standard", "
+        ALTER DATABASE dump_test_intervals_sql_standard SET intervalstyle = 'sql_standard';
+        CREATE TABLE spans (id integer, length interval);
+        INSERT INTO spans VALUES (1, '-1 day +02:03:04'), (2, '1 year -2 mons');
+    ").await;
+
+    let sql = server.dump("intervals_sql_standard", &[]);
+    assert!(sql.contains("SET intervalstyle = 'sql_standard';\n"));
+    assert_eq!(common::lines_starting(&sql, "INSERT INTO spans"), [
+        "INSERT INTO spans (id, length) VALUES (1, '+0-0 -1 +2:03:04'::interval);",
+        "INSERT INTO spans (id, length) VALUES (2, '0-10'::interval);",
+    ]);
+
+    let reloaded = server.reload("intervals_sql_standard_reload", &sql).await;
+    let rows = "SELECT array_agg(length ORDER BY id)::text FROM spans";
+    assert_eq!(common::text(&reloaded, rows).await, common::text(&source, rows).await);
+}
+
+#[tokio::test]
+async fn dropped_columns_leave_no_gap() {
+    let server = server_or_skip!();
+    let source = server.create_database("dropped_column", "
+        CREATE TABLE people (id integer, legacy text, name text, age integer);
+        INSERT INTO people VALUES (1, 'gone', 'Ann', 30);
+        ALTER TABLE people DROP COLUMN legacy;
+        ALTER TABLE people ADD COLUMN city text;
+        INSERT INTO people VALUES (2, NULL, 41, 'Oslo');
+    ").await;
+
+    let sql = server.dump("dropped_column", &[]);
+    assert_eq!(common::lines_starting(&sql, "INSERT INTO people"), [
+        "INSERT INTO people (id, name, age, city) VALUES (1, 'Ann', 30, NULL);",
+        "INSERT INTO people (id, name, age, city) VALUES (2, NULL, 41, 'Oslo');",
+    ]);
+    let sorted = server.dump("dropped_column", &["--sort-columns", "alphabetical"]);
+    assert_eq!(common::lines_starting(&sorted, "INSERT INTO people"), [
+        "INSERT INTO people (age, city, id, name) VALUES (30, NULL, 1, 'Ann');",
+        "INSERT INTO people (age, city, id, name) VALUES (41, 'Oslo', 2, NULL);",
+    ]);
+
+    let rows = "SELECT array_agg((id, name, age, city) ORDER BY id)::text FROM people";
+    for (name, sql) in [("dropped_column_reload", &sql), ("dropped_column_sorted_reload", &sorted)] {
+        let reloaded = server.reload(name, sql).await;
+        assert_eq!(common::text(&reloaded, rows).await, common::text(&source, rows).await);
+    }
+}
+
+#[tokio::test]
+async fn null_stays_apart_from_empty_strings_in_copy() {
+    let server = server_or_skip!();
+    let source = server.create_database("null_as", "
+        CREATE TABLE notes (id integer, body text);
+        INSERT INTO notes VALUES (1, NULL), (2, ''), (3, 'NULL');
+    ").await;
+
+    let csv = server.dump("null_as", &["--data-format", "copy", "--null-as", ""]);
+    assert!(csv.contains("COPY notes (id, body) FROM stdin WITH (FORMAT csv, NULL '');\n\"1\",\n\"2\",\"\"\n\"3\",\"NULL\"\n\\.\n"));
+    let sentinel = server.dump("null_as", &["--data-format", "copy", "--null-as", "NULL"]);
+    assert!(sentinel.contains("COPY notes (id, body) FROM stdin WITH (NULL 'NULL');\n1\tNULL\n2\t\n3\t\\116ULL\n\\.\n"));
+    let default = server.dump("null_as", &["--data-format", "copy"]);
+    assert!(default.contains("COPY notes (id, body) FROM stdin;\n1\t\\N\n2\t\n3\tNULL\n\\.\n"));
+
+    let rows = "SELECT array_agg((id, body IS NULL, body) ORDER BY id)::text FROM notes";
+    for (name, sql) in [("null_as_csv_reload", &csv), ("null_as_sentinel_reload", &sentinel), ("null_as_default_reload", &default)] {
+        let reloaded = server.reload(name, sql).await;
+        assert_eq!(common::text(&reloaded, rows).await, common::text(&source, rows).await);
+    }
+}
+
+#[tokio::test]
+async fn streamed_rows_match_buffered_rows() {
+    let server = server_or_skip!();
+    server.create_database("streamed", "
+        CREATE TABLE odd (id integer, label text);
+        INSERT INTO odd SELECT i, 'row ' || i FROM generate_series(1, 5) i;
+        CREATE TABLE even (id integer, label text);
+        INSERT INTO even SELECT i, NULL FROM generate_series(1, 4) i;
+    ").await;
+
+    // Both tables are over a 1-byte --max-memory, so both are read two rows
+    // at a time, and the even one ends on an empty batch
+    for format in ["insert", "copy"] {
+        let buffered = server.run("streamed", &["--output", "-", "--data-format", format]);
+        let streamed = server.run("streamed", &["--output", "-", "--data-format", format, "--max-memory", "1", "--fetch-size", "2"]);
+        assert!(streamed.status.success(), "dump failed: {}", String::from_utf8_lossy(&streamed.stderr));
+        assert!(String::from_utf8_lossy(&streamed.stderr).contains("Note: streaming table even"));
+        let data = |output: &std::process::Output| -> Vec<String> {
+            String::from_utf8_lossy(&output.stdout).lines()
+                .filter(|line| !line.starts_with("-- ") || line.starts_with("-- Data for table"))
+                .map(str::to_string)
+                .collect()
+        };
+        assert_eq!(data(&streamed), data(&buffered));
+    }
+}
+
+#[tokio::test]
+async fn mysql_rows_leave_out_values_it_has_no_form_for() {
+    let server = server_or_skip!();
+    server.create_database("mysql_no_form", "
+        CREATE TABLE readings (id integer, ratio double precision, day date, at timestamp NOT NULL);
+        INSERT INTO readings VALUES
+            (1, 'NaN', '2026-10-16', '2026-10-16 12:00:00'),
+            (2, 1.5, 'infinity', '2026-10-16 12:00:00'),
+            (3, 2.5, '0044-03-15 BC', '2026-10-16 12:00:00'),
+            (4, '-Infinity', '2026-10-16', '-infinity'),
+            (5, 3.5, '2026-10-16', '0044-03-15 12:00:00 BC');
+    ").await;
+
+    let output = server.run("mysql_no_form", &["--output", "-", "--dialect", "mysql"]);
+    assert!(output.status.success(), "dump failed: {}", String::from_utf8_lossy(&output.stderr));
+    let sql = String::from_utf8(output.stdout).unwrap();
+    let rows: Vec<&str> = sql.lines().filter(|line| line.contains("INSERT INTO `readings`")).collect();
+    assert_eq!(rows, [
+        "INSERT INTO `readings` (`id`, `ratio`, `day`, `at`) VALUES (1, NULL, '2026-10-16', '2026-10-16 12:00:00'); -- Not converted: ratio NaN",
+        "INSERT INTO `readings` (`id`, `ratio`, `day`, `at`) VALUES (2, 1.5, NULL, '2026-10-16 12:00:00'); -- Not converted: day infinity",
+        "INSERT INTO `readings` (`id`, `ratio`, `day`, `at`) VALUES (3, 2.5, NULL, '2026-10-16 12:00:00'); -- Not converted: day 0044-03-15 BC",
+        "-- Not converted, as NULL can't stand in for ratio -Infinity, at -infinity in NOT NULL columns: INSERT INTO `readings` (`id`, `ratio`, `day`, `at`) VALUES (4, '-Infinity', '2026-10-16', '-infinity');",
+        "-- Not converted, as NULL can't stand in for at 0044-03-15 12:00:00 BC in NOT NULL columns: INSERT INTO `readings` (`id`, `ratio`, `day`, `at`) VALUES (5, 3.5, '2026-10-16', '0044-03-15 12:00:00 BC');",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Warning: 3 NaN, infinite or BC values in table readings have no MySQL form and were written as NULL"), "{}", stderr);
+    assert!(stderr.contains("Warning: 2 rows of table readings have NaN, infinite or BC values in NOT NULL columns; they are listed as not converted"), "{}", stderr);
+}
@@ -0,0 +1,39 @@
+// Where options come from: the command line, the environment, the config
+// file and the connection service
+
+mod common;
+
+use std::fs;
+
+#[tokio::test]
+async fn service_follows_the_option_precedence() {
+    let server = server_or_skip!();
+    for place in ["flag", "env", "config"] {
+        server.create_database(&format!("service_{}", place), &format!("CREATE TABLE from_{} (id integer)", place)).await;
+    }
+    let dir = std::env::temp_dir().join(format!("database-dump-service-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let services = dir.join("pg_service.conf");
+    fs::write(&services, ["flag", "env", "config"].map(|place| format!("[{0}]\ndbname=dump_test_service_{0}\n", place)).concat()).unwrap();
+    let config = dir.join("dump.toml");
+    fs::write(&config, "service = \"config\"\n").unwrap();
+
+    // The table the dump finds tells which service it used
+    let dumped_from = |args: &[&str], env_service: Option<&str>| {
+        let mut command = server.command();
+        command.args(["--config", config.to_str().unwrap(), "--output", "-"]).args(args);
+        command.env("PGSERVICEFILE", &services).env_remove("PGSERVICE");
+        if let Some(service) = env_service {
+            command.env("PGSERVICE", service);
+        }
+        let output = command.output().expect("run database-dump");
+        assert!(output.status.success(), "dump failed: {}", String::from_utf8_lossy(&output.stderr));
+        let sql = String::from_utf8(output.stdout).unwrap();
+        common::lines_starting(&sql, "CREATE TABLE").concat()
+    };
+
+    assert_eq!(dumped_from(&[], None), "CREATE TABLE from_config (");
+    assert_eq!(dumped_from(&[], Some("env")), "CREATE TABLE from_env (");
+    assert_eq!(dumped_from(&["--service", "flag"], Some("env")), "CREATE TABLE from_flag (");
+    fs::remove_dir_all(&dir).unwrap();
+}
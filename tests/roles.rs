@@ -0,0 +1,45 @@
+// The roles section of a plain dump
+
+mod common;
+
+const SETUP: &str = "
+    DO $$
+    BEGIN
+        IF NOT EXISTS (SELECT FROM pg_catalog.pg_roles WHERE rolname = 'dump_test_scram') THEN
+            CREATE ROLE dump_test_scram LOGIN;
+        END IF;
+        IF NOT EXISTS (SELECT FROM pg_catalog.pg_roles WHERE rolname = 'dump_test_reader') THEN
+            CREATE ROLE dump_test_reader NOSUPERUSER;
+        END IF;
+    END
+    $$;
+    SET password_encryption = 'scram-sha-256';
+    ALTER ROLE dump_test_scram PASSWORD 'correct horse';
+    CREATE TABLE accounts (id integer);
+    ALTER TABLE accounts OWNER TO dump_test_scram;
+    GRANT SELECT ON accounts TO dump_test_reader;
+";
+
+// Roles are shared by the whole server, so one test covers both cases
+#[tokio::test]
+async fn scram_verifiers_are_copied_verbatim() {
+    let server = server_or_skip!();
+    let client = server.create_database("scram", SETUP).await;
+    let verifier: String = client
+        .query_one("SELECT rolpassword FROM pg_catalog.pg_authid WHERE rolname = 'dump_test_scram'", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert!(verifier.starts_with("SCRAM-SHA-256$"));
+
+    let sql = server.dump("scram", &[]);
+    assert_eq!(
+        common::lines_starting(&sql, "ALTER ROLE dump_test_scram "),
+        [format!("ALTER ROLE dump_test_scram WITH ENCRYPTED PASSWORD '{}';", verifier)]
+    );
+
+    // Without superuser, pg_authid can't be read
+    let sql = server.dump("scram", &["--set-role", "dump_test_reader"]);
+    assert!(common::lines_starting(&sql, "ALTER ROLE dump_test_scram ").is_empty());
+    assert!(sql.contains("-- Password for role dump_test_scram not dumped: reading pg_authid requires superuser\n"));
+}
@@ -0,0 +1,269 @@
+// Table definitions and what comes with them in a plain dump
+
+mod common;
+
+#[tokio::test]
+async fn mutually_referencing_tables_reload() {
+    let server = server_or_skip!();
+    let source = server.create_database("cycle", "
+        CREATE TABLE dept (id integer PRIMARY KEY, manager_id integer);
+        CREATE TABLE emp (id integer PRIMARY KEY, dept_id integer REFERENCES dept);
+        ALTER TABLE dept ADD FOREIGN KEY (manager_id) REFERENCES emp;
+        INSERT INTO dept VALUES (1, NULL);
+        INSERT INTO emp VALUES (10, 1);
+        UPDATE dept SET manager_id = 10;
+    ").await;
+
+    let sql = server.dump("cycle", &[]);
+    let reloaded = server.reload("cycle_reload", &sql).await;
+    let rows = "SELECT (SELECT array_agg((id, manager_id))::text FROM dept) || (SELECT array_agg((id, dept_id))::text FROM emp)";
+    assert_eq!(common::text(&reloaded, rows).await, common::text(&source, rows).await);
+    let keys = "SELECT string_agg(conname, ', ' ORDER BY conname) FROM pg_catalog.pg_constraint WHERE contype = 'f'";
+    assert_eq!(common::text(&reloaded, keys).await, common::text(&source, keys).await);
+}
+
+#[tokio::test]
+async fn owned_sequences_go_with_their_table() {
+    let server = server_or_skip!();
+    server.create_database("owned_by", "
+        CREATE TABLE tickets (id serial PRIMARY KEY, code integer);
+        CREATE SEQUENCE ticket_codes;
+        ALTER SEQUENCE ticket_codes OWNED BY tickets.code;
+        CREATE SEQUENCE unowned;
+    ").await;
+
+    let sql = server.dump("owned_by", &[]);
+    let owned_by: Vec<&str> = common::lines_starting(&sql, "ALTER SEQUENCE").into_iter().filter(|line| line.contains(" OWNED BY ")).collect();
+    assert_eq!(owned_by, [
+        "ALTER SEQUENCE ticket_codes OWNED BY tickets.code;",
+        "ALTER SEQUENCE tickets_id_seq OWNED BY tickets.id;",
+    ]);
+
+    let reloaded = server.reload("owned_by_reload", &sql).await;
+    reloaded.batch_execute("DROP TABLE tickets").await.unwrap();
+    let sequences = "SELECT string_agg(relname, ', ' ORDER BY relname) FROM pg_catalog.pg_class WHERE relkind = 'S'";
+    assert_eq!(common::text(&reloaded, sequences).await, "unowned");
+}
+
+#[tokio::test]
+async fn identity_columns_keep_their_options_and_position() {
+    let server = server_or_skip!();
+    server.create_database("identity", "
+        CREATE TABLE orders (id integer GENERATED ALWAYS AS IDENTITY (START WITH 100 INCREMENT BY 5), item text);
+        INSERT INTO orders (item) VALUES ('a'), ('b');
+    ").await;
+
+    let sql = server.dump("identity", &[]);
+    assert!(sql.contains("GENERATED ALWAYS AS IDENTITY (START WITH 100 INCREMENT BY 5 MINVALUE 1 MAXVALUE 2147483647 CACHE 1)"));
+    assert_eq!(common::lines_starting(&sql, "SELECT pg_catalog.setval"), ["SELECT pg_catalog.setval(pg_catalog.pg_get_serial_sequence('orders', 'id'), 105, true);"]);
+
+    let reloaded = server.reload("identity_reload", &sql).await;
+    reloaded.batch_execute("INSERT INTO orders (item) VALUES ('c')").await.unwrap();
+    assert_eq!(common::text(&reloaded, "SELECT array_agg(id ORDER BY id)::text FROM orders").await, "{100,105,110}");
+    let start = "SELECT seqstart::text FROM pg_catalog.pg_sequence WHERE seqrelid = pg_catalog.pg_get_serial_sequence('orders', 'id')::regclass";
+    assert_eq!(common::text(&reloaded, start).await, "100");
+}
+
+#[tokio::test]
+async fn unbounded_varchar_and_numeric_keep_no_length() {
+    let server = server_or_skip!();
+    let source = server.create_database("unbounded", "
+        CREATE TABLE readings (label varchar, value numeric, code varchar(5), price numeric(7,2));
+        INSERT INTO readings VALUES (repeat('x', 300), 3.14159265358979323846264338327950288, 'abc', 12.5);
+    ").await;
+
+    let sql = server.dump("unbounded", &[]);
+    assert!(sql.contains("CREATE TABLE readings (\n  label character varying,\n  value numeric,\n  code  character varying(5),\n  price numeric(7,2)\n);"));
+
+    let reloaded = server.reload("unbounded_reload", &sql).await;
+    let types = "SELECT string_agg(pg_catalog.format_type(atttypid, atttypmod), ', ' ORDER BY attnum) FROM pg_catalog.pg_attribute WHERE attrelid = 'readings'::regclass AND attnum > 0";
+    assert_eq!(common::text(&reloaded, types).await, "character varying, numeric, character varying(5), numeric(7,2)");
+    let rows = "SELECT array_agg((label, value, code, price))::text FROM readings";
+    assert_eq!(common::text(&reloaded, rows).await, common::text(&source, rows).await);
+}
+
+#[tokio::test]
+async fn multibyte_names_line_up_and_reload() {
+    let server = server_or_skip!();
+    let source = server.create_database("multibyte", "
+        CREATE TABLE café (id integer, naïve text);
+        INSERT INTO café VALUES (1, 'crème brûlée');
+    ").await;
+
+    let sql = server.dump("multibyte", &["-t", "café"]);
+    assert!(sql.contains("CREATE TABLE café (\n  id    integer,\n  naïve text\n);"));
+    assert_eq!(common::lines_starting(&sql, "INSERT INTO"), ["INSERT INTO café (id, naïve) VALUES (1, 'crème brûlée');"]);
+
+    let reloaded = server.reload("multibyte_reload", &sql).await;
+    let rows = "SELECT array_agg((id, naïve))::text FROM café";
+    assert_eq!(common::text(&reloaded, rows).await, common::text(&source, rows).await);
+}
+
+#[tokio::test]
+async fn tables_with_only_dropped_columns_dump_without_rows() {
+    let server = server_or_skip!();
+    server.create_database("no_columns", "
+        CREATE TABLE hollow (gone integer);
+        INSERT INTO hollow VALUES (1), (2);
+        ALTER TABLE hollow DROP COLUMN gone;
+    ").await;
+
+    for format in ["insert", "copy"] {
+        let sql = server.dump("no_columns", &["--data-format", format]);
+        assert!(sql.contains("CREATE TABLE hollow (\n);"));
+        assert!(sql.contains("-- Data for table: hollow\n-- No columns to dump; the table's rows are left out\n"));
+        assert!(!sql.contains("INSERT INTO hollow") && !sql.contains("COPY hollow"));
+
+        let reloaded = server.reload(&format!("no_columns_{}_reload", format), &sql).await;
+        assert_eq!(common::text(&reloaded, "SELECT count(*)::text FROM hollow").await, "0");
+    }
+
+    for (dialect, note) in [
+        ("mysql", "-- Not converted: a table without columns, which MySQL doesn't allow"),
+        ("sqlite", "-- Not converted: a table without columns, which SQLite doesn't allow"),
+    ] {
+        let sql = server.dump("no_columns", &["--dialect", dialect]);
+        assert!(sql.contains(&format!("-- Table: hollow\n{}\n", note)), "{}", sql);
+        assert!(!sql.contains("CREATE TABLE") && !sql.contains("INSERT INTO"));
+    }
+}
+
+#[tokio::test]
+async fn public_schema_comment_only_when_changed() {
+    let server = server_or_skip!();
+    server.create_database("public_comment_default", "").await;
+    let sql = server.dump("public_comment_default", &[]);
+    assert!(!sql.contains("COMMENT ON SCHEMA public"));
+    server.create_database("public_comment_removed", "COMMENT ON SCHEMA public IS NULL").await;
+    let sql = server.dump("public_comment_removed", &[]);
+    assert!(!sql.contains("COMMENT ON SCHEMA public"));
+
+    server.create_database("public_comment", "COMMENT ON SCHEMA public IS 'the app''s tables'").await;
+    let sql = server.dump("public_comment", &[]);
+    assert_eq!(common::lines_starting(&sql, "COMMENT ON SCHEMA"), ["COMMENT ON SCHEMA public IS 'the app''s tables';"]);
+    let reloaded = server.reload("public_comment_reload", &sql).await;
+    let comment = "SELECT pg_catalog.obj_description('public'::regnamespace, 'pg_namespace')";
+    assert_eq!(common::text(&reloaded, comment).await, "the app's tables");
+}
+
+#[tokio::test]
+async fn identities_at_their_limit_stay_there() {
+    let server = server_or_skip!();
+    server.create_database("identity_limit", "
+        CREATE TABLE spent (id smallint GENERATED BY DEFAULT AS IDENTITY (MAXVALUE 2), item text);
+        INSERT INTO spent (item) VALUES ('a'), ('b');
+        CREATE TABLE wheel (id integer GENERATED BY DEFAULT AS IDENTITY (START WITH 2 MINVALUE 1 MAXVALUE 3 CYCLE), item text);
+        INSERT INTO wheel (item) VALUES ('a'), ('b');
+        CREATE TABLE falling (id integer GENERATED BY DEFAULT AS IDENTITY (INCREMENT BY -1 START WITH 1 MINVALUE 1 MAXVALUE 3 CYCLE), item text);
+        INSERT INTO falling (item) VALUES ('a');
+    ").await;
+
+    let sql = server.dump("identity_limit", &[]);
+    let reloaded = server.reload("identity_limit_reload", &sql).await;
+
+    // An exhausted identity hands out nothing more rather than a duplicate
+    let error = reloaded.batch_execute("INSERT INTO spent (item) VALUES ('c')").await.unwrap_err();
+    assert!(error.to_string().contains("reached maximum value"), "{}", error);
+    // One that cycles wraps around to its MINVALUE, or MAXVALUE going down,
+    // not back to its START
+    reloaded.batch_execute("INSERT INTO wheel (item) VALUES ('c'); INSERT INTO falling (item) VALUES ('b')").await.unwrap();
+    assert_eq!(common::text(&reloaded, "SELECT array_agg(id ORDER BY id)::text FROM wheel").await, "{1,2,3}");
+    assert_eq!(common::text(&reloaded, "SELECT array_agg(id ORDER BY id)::text FROM falling").await, "{1,3}");
+}
+
+#[tokio::test]
+async fn foreign_keys_to_other_schemas_reload() {
+    let server = server_or_skip!();
+    let geo = "
+        CREATE SCHEMA geo;
+        CREATE TABLE geo.countries (code text PRIMARY KEY);
+        INSERT INTO geo.countries VALUES ('NO'), ('SE');
+    ";
+    server.create_database("cross_schema", &format!("{}
+        CREATE TABLE cities (id integer PRIMARY KEY, country text CONSTRAINT cities_country_fkey REFERENCES geo.countries);
+        INSERT INTO cities VALUES (1, 'NO');
+    ", geo)).await;
+
+    // Only the public schema is dumped, so the target brings geo itself
+    let sql = server.dump("cross_schema", &[]);
+    assert_eq!(common::lines_starting(&sql, "ALTER TABLE public.cities ADD CONSTRAINT"), [
+        "ALTER TABLE public.cities ADD CONSTRAINT cities_country_fkey FOREIGN KEY (country) REFERENCES geo.countries(code);",
+    ]);
+
+    let reloaded = server.reload_onto("cross_schema_reload", geo, &sql).await;
+    let key = "SELECT pg_catalog.pg_get_constraintdef(oid) FROM pg_catalog.pg_constraint WHERE conname = 'cities_country_fkey' AND conrelid = 'public.cities'::regclass";
+    assert_eq!(common::text(&reloaded, key).await, "FOREIGN KEY (country) REFERENCES geo.countries(code)");
+    assert_eq!(common::text(&reloaded, "SELECT array_agg((id, country))::text FROM cities").await, "{\"(1,NO)\"}");
+    let error = reloaded.batch_execute("INSERT INTO cities VALUES (2, 'DK')").await.unwrap_err();
+    assert!(error.to_string().contains("violates foreign key constraint"), "{}", error);
+}
+
+#[tokio::test]
+async fn data_only_tables_load_into_their_existing_definitions() {
+    let server = server_or_skip!();
+    let definitions = "
+        CREATE TABLE accounts (id integer GENERATED ALWAYS AS IDENTITY PRIMARY KEY, name text);
+        CREATE TABLE orders (id serial PRIMARY KEY, account_id integer REFERENCES accounts);
+    ";
+    server.create_database("data_only_for", &format!("{}
+        CREATE TABLE notes (id integer PRIMARY KEY, body text);
+        INSERT INTO accounts (name) VALUES ('a'), ('b'), ('c');
+        INSERT INTO orders (account_id) VALUES (1), (2), (2), (3);
+        INSERT INTO notes VALUES (1, 'x');
+    ", definitions)).await;
+
+    let sql = server.dump("data_only_for", &["--data-only-for", "accounts", "--data-only-for", "ord*"]);
+    // The target has the tables and the sequences they own
+    assert_eq!(common::lines_starting(&sql, "CREATE TABLE"), ["CREATE TABLE notes ("]);
+    assert!(!sql.contains("CREATE SEQUENCE"));
+    // The sequences are moved past the rows once they are in
+    assert!(sql.contains("INSERT INTO accounts (id, name) OVERRIDING SYSTEM VALUE VALUES (3, 'c');\n\nSELECT pg_catalog.setval(pg_catalog.pg_get_serial_sequence('accounts', 'id'), 3, true);\n"));
+    assert!(sql.contains("INSERT INTO orders (id, account_id) VALUES (4, 3);\n\nSELECT pg_catalog.setval('orders_id_seq', 4, true);\n"));
+
+    let reloaded = server.reload_onto("data_only_for_reload", definitions, &sql).await;
+    reloaded.batch_execute("INSERT INTO accounts (name) VALUES ('d'); INSERT INTO orders (account_id) VALUES (4)").await.unwrap();
+    assert_eq!(common::text(&reloaded, "SELECT array_agg(id ORDER BY id)::text FROM accounts").await, "{1,2,3,4}");
+    assert_eq!(common::text(&reloaded, "SELECT array_agg((id, account_id) ORDER BY id)::text FROM orders").await,
+        "{\"(1,1)\",\"(2,2)\",\"(3,2)\",\"(4,3)\",\"(5,4)\"}");
+    assert_eq!(common::text(&reloaded, "SELECT array_agg(body)::text FROM notes").await, "{x}");
+
+    let both = server.run("data_only_for", &["--output", "-", "--data-only-for", "orders", "--schema-only-for", "ord*"]);
+    assert!(!both.status.success());
+    assert!(String::from_utf8_lossy(&both.stderr).contains("Table orders matches both --data-only-for and --schema-only-for"));
+}
+
+#[tokio::test]
+async fn replica_identities_reload() {
+    let server = server_or_skip!();
+    server.create_database("replica_identity", "
+        CREATE TABLE audit (id integer);
+        ALTER TABLE audit REPLICA IDENTITY FULL;
+        CREATE TABLE scratch (id integer);
+        ALTER TABLE scratch REPLICA IDENTITY NOTHING;
+        CREATE TABLE keyed (code text NOT NULL);
+        CREATE UNIQUE INDEX \"Keyed Code\" ON keyed (code);
+        ALTER TABLE keyed REPLICA IDENTITY USING INDEX \"Keyed Code\";
+        CREATE TABLE filtered (code text NOT NULL);
+        CREATE UNIQUE INDEX filtered_code ON filtered (code);
+        ALTER TABLE filtered REPLICA IDENTITY USING INDEX filtered_code;
+        CREATE TABLE plain (id integer PRIMARY KEY);
+    ").await;
+
+    let filter = std::env::temp_dir().join(format!("database-dump-replica-identity-{}.txt", std::process::id()));
+    std::fs::write(&filter, "exclude index filtered_code\n").unwrap();
+    let sql = server.dump("replica_identity", &["--filter", filter.to_str().unwrap()]);
+    std::fs::remove_file(&filter).unwrap();
+    assert_eq!(common::lines_starting(&sql, "ALTER TABLE").into_iter().filter(|line| line.contains(" REPLICA IDENTITY ")).collect::<Vec<_>>(), [
+        "ALTER TABLE audit REPLICA IDENTITY FULL;",
+        "ALTER TABLE keyed REPLICA IDENTITY USING INDEX \"Keyed Code\";",
+        "ALTER TABLE scratch REPLICA IDENTITY NOTHING;",
+    ]);
+
+    // The index the filter leaves out leaves its table at the default
+    let reloaded = server.reload("replica_identity_reload", &sql).await;
+    let identities = "SELECT string_agg(c.relname || ' ' || c.relreplident::text || coalesce(' ' || i.indexrelid::regclass::text, ''), ', ' ORDER BY c.relname)
+        FROM pg_catalog.pg_class c
+        LEFT JOIN pg_catalog.pg_index i ON i.indrelid = c.oid AND i.indisreplident
+        WHERE c.relkind = 'r' AND c.relnamespace = 'public'::regnamespace";
+    assert_eq!(common::text(&reloaded, identities).await, "audit f, filtered d, keyed i \"Keyed Code\", plain d, scratch n");
+}